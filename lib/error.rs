@@ -3,8 +3,10 @@
 use std::{
     error,
     fmt::{self, Display, Formatter},
+    io,
     rc::Rc,
     result,
+    time::Duration,
 };
 
 use crate::{Method, Request};
@@ -45,6 +47,15 @@ pub enum StartupError {
 
     /// The socket timeout specified is invalid (must be greater than 0)
     InvalidSocketTimeout,
+
+    /// A keep-alive timeout or connection limit specified is invalid (must be greater than 0)
+    InvalidConnectionLimit,
+
+    /// The queue size specified is invalid (must be greater than 0)
+    InvalidQueueSize,
+
+    /// The PROXY protocol can't be used together with TLS, as the header is sent before the TLS handshake
+    ProxyProtocolTls,
 }
 
 /// Errors that can arise while handling a request
@@ -83,6 +94,36 @@ pub enum ParseError {
 
     /// Invalid Header in Request HTTP
     InvalidHeader,
+
+    /// Invalid HTTP Version in Request HTTP
+    InvalidVersion,
+
+    /// HTTP Version in Request HTTP is not supported (only HTTP/1.0 and HTTP/1.1 are)
+    UnsupportedVersion,
+
+    /// Request has both a `Content-Length` and a `Transfer-Encoding` header
+    ConflictingLength,
+
+    /// Invalid or conflicting `Content-Length` header(s)
+    InvalidContentLength,
+
+    /// Unsupported transfer coding in the `Transfer-Encoding` header, only `chunked` is supported
+    InvalidTransferEncoding,
+
+    /// Malformed chunk in a chunked request body
+    InvalidChunk,
+
+    /// The request line is longer than [`crate::Limits::max_request_line`]
+    RequestLineTooLong,
+
+    /// The request has more headers (or trailers) than [`crate::Limits::max_header_count`]
+    TooManyHeaders,
+
+    /// A header (or trailer) line is longer than [`crate::Limits::max_header_size`]
+    HeaderTooLarge,
+
+    /// The request body is larger than [`crate::Limits::max_body_size`]
+    BodyTooLarge,
 }
 
 /// Error that can occur while reading or writing to a stream
@@ -90,9 +131,131 @@ pub enum ParseError {
 pub enum StreamError {
     /// The stream ended unexpectedly
     UnexpectedEof,
+
+    /// The client took too long to send the request
+    Timeout,
+}
+
+/// Error getting a typed path param with [`crate::Request::param_as`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParamError {
+    /// The route has no path param with this name
+    Missing(String),
+
+    /// The path param couldn't be parsed into the requested type
+    Invalid {
+        /// Name of the path param
+        name: String,
+        /// Value of the path param
+        value: String,
+        /// Error returned when parsing the value
+        error: String,
+    },
+}
+
+/// An error that occurred while accepting a connection.
+/// Passed to the handler set with [`crate::Server::accept_error_handler`].
+#[derive(Debug)]
+pub struct AcceptError {
+    /// The error returned by the listener.
+    pub error: io::Error,
+
+    /// How the server handles the error.
+    pub kind: AcceptErrorKind,
+
+    /// How long the server waits before accepting connections again.
+    /// Only set for [`AcceptErrorKind::Resources`].
+    pub backoff: Option<Duration>,
+}
+
+/// How serious an error accepting a connection is, and how the server handles it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AcceptErrorKind {
+    /// Only the connection being accepted failed, like it being reset before it could be accepted (`ECONNABORTED`).
+    /// The server carries on accepting connections.
+    Connection,
+
+    /// The process or system ran out of file descriptors or memory (`EMFILE`, `ENFILE`, `ENOBUFS`, `ENOMEM`).
+    /// The server stops accepting connections for a moment, backing off exponentially while the error persists.
+    Resources,
+
+    /// The listener itself is broken (`EBADF`, `ENOTSOCK`, `EINVAL`).
+    /// The server shuts down and returns the error.
+    Fatal,
+}
+
+impl AcceptError {
+    /// Classifies an error returned while accepting a connection.
+    pub(crate) fn new(error: io::Error) -> Self {
+        Self {
+            kind: AcceptErrorKind::classify(&error),
+            error,
+            backoff: None,
+        }
+    }
 }
 
+impl AcceptErrorKind {
+    /// Works out the kind of an error returned while accepting a connection.
+    /// Unknown errors are assumed to only affect the connection being accepted.
+    fn classify(error: &io::Error) -> Self {
+        #[cfg(unix)]
+        const RESOURCES: &[i32] = &[23, 24, 12, ENOBUFS];
+        #[cfg(unix)]
+        const FATAL: &[i32] = &[9, 14, 22, ENOTSOCK];
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        const ENOBUFS: i32 = 105;
+        #[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+        const ENOBUFS: i32 = 55;
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        const ENOTSOCK: i32 = 88;
+        #[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+        const ENOTSOCK: i32 = 38;
+
+        // WSAEMFILE, WSAENOBUFS and WSAEBADF, WSAENOTSOCK, WSAEINVAL
+        #[cfg(windows)]
+        const RESOURCES: &[i32] = &[10024, 10055];
+        #[cfg(windows)]
+        const FATAL: &[i32] = &[10009, 10038, 10022];
+
+        #[cfg(any(unix, windows))]
+        if let Some(code) = error.raw_os_error() {
+            if RESOURCES.contains(&code) {
+                return AcceptErrorKind::Resources;
+            }
+
+            if FATAL.contains(&code) {
+                return AcceptErrorKind::Fatal;
+            }
+        }
+
+        match error.kind() {
+            io::ErrorKind::OutOfMemory => AcceptErrorKind::Resources,
+            io::ErrorKind::InvalidInput => AcceptErrorKind::Fatal,
+            _ => AcceptErrorKind::Connection,
+        }
+    }
+}
+
+impl Display for AcceptError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            AcceptErrorKind::Connection => write!(f, "Error accepting connection: {}", self.error),
+            AcceptErrorKind::Resources => write!(
+                f,
+                "Out of resources accepting connection, backing off for {:?}: {}",
+                self.backoff.unwrap_or_default(),
+                self.error
+            ),
+            AcceptErrorKind::Fatal => write!(f, "Fatal error accepting connection: {}", self.error),
+        }
+    }
+}
+
+impl error::Error for AcceptError {}
 impl error::Error for Error {}
+impl error::Error for ParseError {}
+impl error::Error for ParamError {}
 impl Display for Error {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
@@ -119,6 +282,17 @@ impl Display for HandleError {
     }
 }
 
+impl Display for ParamError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ParamError::Missing(name) => f.write_fmt(format_args!("Missing path param `{name}`")),
+            ParamError::Invalid { name, value, error } => f.write_fmt(format_args!(
+                "Invalid value `{value}` for path param `{name}`: {error}"
+            )),
+        }
+    }
+}
+
 impl Display for StartupError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         f.write_str(match self {
@@ -127,14 +301,34 @@ impl Display for StartupError {
             StartupError::InvalidSocketTimeout => {
                 "The socket timeout specified is invalid (must be greater than 0)"
             }
+            StartupError::InvalidConnectionLimit => {
+                "A keep-alive timeout or connection limit specified is invalid (must be greater than 0)"
+            }
+            StartupError::InvalidQueueSize => {
+                "The queue size specified is invalid (must be greater than 0)"
+            }
+            StartupError::ProxyProtocolTls => {
+                "The PROXY protocol can't be used together with TLS"
+            }
         })
     }
 }
 
+impl StreamError {
+    /// Gets the StreamError for an IO error that occurred while reading a request.
+    pub(crate) fn from_read(error: &io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => StreamError::Timeout,
+            _ => StreamError::UnexpectedEof,
+        }
+    }
+}
+
 impl Display for StreamError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         f.write_str(match self {
             StreamError::UnexpectedEof => "The stream ended unexpectedly",
+            StreamError::Timeout => "The client took too long to send the request",
         })
     }
 }
@@ -152,6 +346,18 @@ impl Display for ParseError {
             ParseError::InvalidQuery => "Invalid Query in Path",
             ParseError::InvalidMethod => "Invalid Method in Request HTTP",
             ParseError::InvalidHeader => "Invalid Header in Request HTTP",
+            ParseError::InvalidVersion => "Invalid HTTP Version in Request HTTP",
+            ParseError::UnsupportedVersion => "Unsupported HTTP Version in Request HTTP",
+            ParseError::ConflictingLength => {
+                "Request has both a Content-Length and a Transfer-Encoding header"
+            }
+            ParseError::InvalidContentLength => "Invalid Content-Length in Request HTTP",
+            ParseError::InvalidTransferEncoding => "Unsupported Transfer-Encoding in Request HTTP",
+            ParseError::InvalidChunk => "Invalid chunk in Request body",
+            ParseError::RequestLineTooLong => "Request line is too long",
+            ParseError::TooManyHeaders => "Request has too many headers",
+            ParseError::HeaderTooLarge => "Request header is too large",
+            ParseError::BodyTooLarge => "Request body is too large",
         })
     }
 }
@@ -1,15 +1,28 @@
 //! Methods for getting the real IP of a client through a reverse proxy.
 //!
-//! **Warning**: Make sure your reverse proxy is overwriting the specified header on the incoming requests so clients cant spoof their original Ips.
+//! Forwarding headers are only trusted when the request comes from a trusted proxy, by default the only trusted proxies are on localhost.
+//! Proxies connected over a Unix domain socket are only trusted with [`TrustedProxies::trust_unix`].
+//! Use [`TrustedProxies`] to configure which proxies to trust, for example when your load balancer is on another machine.
+//!
+//! **Warning**: Only trust proxies that overwrite or append to the forwarding headers of incoming requests, so clients cant spoof their original Ips.
 
-use std::net::IpAddr;
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+};
 
-use crate::{HeaderType, Request};
+use crate::{header::Headers, HeaderType, PeerAddr, Request};
 
 /// Trait that adds methods for getting the real IP of a client through a reverse proxy.
-/// If you are using the "X-Forwarded-For" header you can use `req.real_ip()` but if you are using a different header you will have to use `req.real_ip_header(...)`.
+/// If you are using the "Forwarded" or "X-Forwarded-For" headers you can use `req.real_ip()` but if you are using a different header you will have to use `req.real_ip_header(...)`.
+/// Both only trust proxies on localhost (not including Unix domain sockets), see [`TrustedProxies`] to trust other proxies.
 pub trait RealIp {
-    /// Uses [`RealIp::real_ip_header`] with the ["X-Forwarded-For"](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/X-Forwarded-For) header.
+    /// Gets the 'real IP' of a client from the ["Forwarded"](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Forwarded) header, or if it is not present, the ["X-Forwarded-For"](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/X-Forwarded-For) header.
+    /// See [`TrustedProxies::real_ip`] for how the chain of proxies is walked.
+    ///
+    /// The default implementation only uses `X-Forwarded-For`, through [`RealIp::real_ip_header`].
     /// ## Example
     /// ```rust
     /// use afire::extension::RealIp;
@@ -26,27 +39,580 @@ pub trait RealIp {
         self.real_ip_header(HeaderType::XForwardedFor)
     }
 
-    /// Gets the 'real IP' of a client by parsing the value of `header` into an IpAddr.
-    /// If the connection is not coming from localhost, the header isn't found or the header contains an invalid IP address, the raw socket address will be returned.
+    /// Gets the 'real IP' of a client from a comma separated list of addresses in `header`, like `X-Forwarded-For`.
+    /// If the connection is not coming from localhost (over TCP), the header isn't found or the header contains an invalid IP address, the raw socket address will be returned.
     ///
     /// **Warning**: Make sure your reverse proxy is overwriting the specified header on the incoming requests so clients cant spoof their original Ips.
     fn real_ip_header(&self, header: impl Into<HeaderType>) -> IpAddr;
 }
 
 impl RealIp for Request {
+    fn real_ip(&self) -> IpAddr {
+        TrustedProxies::new().real_ip(self)
+    }
+
     fn real_ip_header(&self, header: impl Into<HeaderType>) -> IpAddr {
-        let ip = self.address.ip();
+        TrustedProxies::new().real_ip_header(self, header)
+    }
+}
+
+/// The proxies whose forwarding headers are trusted, as a list of IP ranges.
+/// Proxies on localhost are always trusted.
+/// Proxies connected over a Unix domain socket are not, as any local process may be able to connect to it, see [`TrustedProxies::trust_unix`].
+///
+/// The address of the client is found by walking the chain of proxies from right to left (from the proxy closest to the server), stopping at the first address that is not a trusted proxy.
+/// So even if a client sends its own forwarding headers, the address added by your proxy is used.
+///
+/// ## Example
+/// ```rust
+/// # use afire::{Server, Method, Response};
+/// use afire::extension::TrustedProxies;
+///
+/// let proxies = TrustedProxies::new()
+///     // Trust the load balancers on the private network
+///     .trust("10.0.0.0/8")
+///     .trust("fd00::/8");
+///
+/// # fn test(server: &mut Server, proxies: TrustedProxies) {
+/// server.route(Method::GET, "/", move |req| {
+///     let url = format!("{}://{}{}", proxies.scheme(req), proxies.host(req).unwrap_or_default(), req.path);
+///     Response::new().text(format!("{} requested {}", proxies.real_ip(req), url))
+/// });
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct TrustedProxies {
+    ranges: Vec<Cidr>,
+    unix: bool,
+}
+
+/// A range of IP addresses in CIDR notation, like `10.0.0.0/8` or `2001:db8::/32`.
+/// A single address without a prefix length is also accepted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+/// Error returned when parsing a [`Cidr`] fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidCidr;
+
+/// A hop in the chain of proxies, from one element of a forwarding header.
+#[derive(Debug, Default)]
+struct Hop {
+    /// The address the proxy received the request from.
+    addr: Option<IpAddr>,
+    /// The protocol the proxy received the request over.
+    proto: Option<String>,
+    /// The `Host` header the proxy received.
+    host: Option<String>,
+}
+
+/// The original client of a request, and the hop it was found in, if any.
+struct Client {
+    ip: IpAddr,
+    hop: Option<Hop>,
+    /// Index of the hop, and the total number of hops.
+    index: Option<(usize, usize)>,
+}
+
+impl TrustedProxies {
+    /// Creates a list of trusted proxies, which only contains proxies on localhost.
+    pub fn new() -> Self {
+        Self {
+            ranges: vec![
+                Cidr::new([127, 0, 0, 0].into(), 8).unwrap(),
+                Cidr::new([0, 0, 0, 0, 0, 0, 0, 1].into(), 128).unwrap(),
+            ],
+            unix: false,
+        }
+    }
+
+    /// Trust proxies in the IP range `range`, like `10.0.0.0/8` or `192.168.1.5`.
+    /// Will <u>panic</u> if the range is not valid CIDR notation.
+    pub fn trust(mut self, range: &str) -> Self {
+        let range = range
+            .parse()
+            .unwrap_or_else(|_| panic!("Invalid CIDR range `{}`", range));
+        self.ranges.push(range);
+        self
+    }
+
+    /// Trust proxies connected over a Unix domain socket.
+    /// Only use this if the permissions of the socket file make sure only your proxy can connect to it.
+    pub fn trust_unix(mut self) -> Self {
+        self.unix = true;
+        self
+    }
+
+    /// Checks if `ip` is a trusted proxy.
+    pub fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.ranges.iter().any(|x| x.contains(ip))
+    }
+
+    /// Checks if the peer a request came from is a trusted proxy.
+    /// Unix domain socket peers are only trusted with [`TrustedProxies::trust_unix`].
+    pub fn is_trusted_peer(&self, peer: &PeerAddr) -> bool {
+        match peer {
+            PeerAddr::Tcp(i) => self.is_trusted(i.ip()),
+            PeerAddr::Unix(_) => self.unix,
+        }
+    }
+
+    /// Gets the 'real IP' of a client from the `Forwarded` header, or if it is not present, the `X-Forwarded-For` header.
+    /// If the request doesn't come from a trusted proxy or neither header is present, the socket address is returned.
+    /// The addresses are walked from right to left, returning the first one that is not a trusted proxy.
+    /// If an address is invalid (or `unknown`), the last valid one is returned.
+    pub fn real_ip(&self, req: &Request) -> IpAddr {
+        self.client(&req.address, &req.headers).ip
+    }
+
+    /// Gets the 'real IP' of a client from a comma separated list of addresses in `header`, like `X-Forwarded-For`.
+    /// See [`TrustedProxies::real_ip`] for how the addresses are walked.
+    pub fn real_ip_header(&self, req: &Request, header: impl Into<HeaderType>) -> IpAddr {
+        let header = header.into();
+        let hops = match header {
+            HeaderType::Forwarded => forwarded_hops(&req.headers),
+            _ => list(&req.headers, header).map(Hop::new).collect(),
+        };
+
+        self.walk(&req.address, hops).ip
+    }
+
+    /// Gets the scheme (`http` or `https`) the client originally used, from the `Forwarded` or `X-Forwarded-Proto` headers of a trusted proxy.
+    /// Otherwise it is based on if the request arrived over TLS.
+    pub fn scheme(&self, req: &Request) -> String {
+        self.forwarded_scheme(&req.address, &req.headers, req.is_tls())
+    }
+
+    /// Gets the host the client originally requested, from the `Forwarded` or `X-Forwarded-Host` headers of a trusted proxy.
+    /// Otherwise it is the `Host` header of the request, if it has one.
+    pub fn host(&self, req: &Request) -> Option<String> {
+        self.forwarded_host(&req.address, &req.headers)
+    }
+
+    fn forwarded_scheme(&self, peer: &PeerAddr, headers: &Headers, tls: bool) -> String {
+        let proto = self.forwarded(peer, headers, |x| x.proto, HeaderType::XForwardedProto);
+        proto.map(|x| x.to_ascii_lowercase()).unwrap_or_else(|| {
+            match tls {
+                true => "https",
+                false => "http",
+            }
+            .to_owned()
+        })
+    }
+
+    fn forwarded_host(&self, peer: &PeerAddr, headers: &Headers) -> Option<String> {
+        self.forwarded(peer, headers, |x| x.host, HeaderType::XForwardedHost)
+            .or_else(|| headers.get(HeaderType::Host).map(|x| x.to_owned()))
+    }
+
+    /// Finds the client of a request from the `Forwarded` header, falling back to `X-Forwarded-For`.
+    fn client(&self, peer: &PeerAddr, headers: &Headers) -> Client {
+        match headers.has(HeaderType::Forwarded) {
+            true => self.walk(peer, forwarded_hops(headers)),
+            false => {
+                let hops = list(headers, HeaderType::XForwardedFor)
+                    .map(Hop::new)
+                    .collect();
+                self.walk(peer, hops)
+            }
+        }
+    }
+
+    /// Walks `hops` from right to left, while the address is a trusted proxy.
+    fn walk(&self, peer: &PeerAddr, hops: Vec<Hop>) -> Client {
+        let mut client = Client {
+            ip: peer.ip(),
+            hop: None,
+            index: None,
+        };
+
+        if !self.is_trusted_peer(peer) {
+            return client;
+        }
+
+        let len = hops.len();
+        for (i, hop) in hops.into_iter().enumerate().rev() {
+            let Some(addr) = hop.addr else {
+                break;
+            };
+
+            client = Client {
+                ip: addr,
+                hop: Some(hop),
+                index: Some((i, len)),
+            };
+
+            if !self.is_trusted(addr) {
+                break;
+            }
+        }
+
+        client
+    }
+
+    /// Gets a value of the hop the client was found in, or from the `X-Forwarded-*` header `fallback`.
+    /// If the fallback header has a value for each address in `X-Forwarded-For`, the one for the client is used, otherwise the last one.
+    fn forwarded(
+        &self,
+        peer: &PeerAddr,
+        headers: &Headers,
+        value: impl Fn(Hop) -> Option<String>,
+        fallback: HeaderType,
+    ) -> Option<String> {
+        if !self.is_trusted_peer(peer) {
+            return None;
+        }
+
+        let client = self.client(peer, headers);
+        if headers.has(HeaderType::Forwarded) {
+            return client.hop.and_then(value);
+        }
+
+        let values = list(headers, fallback).collect::<Vec<_>>();
+        let index = match client.index {
+            Some((i, len)) if len == values.len() => i,
+            _ => values.len().checked_sub(1)?,
+        };
+        Some(values[index].to_owned())
+    }
+}
+
+impl Default for TrustedProxies {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Hop {
+    /// Creates a hop from an address in a list like `X-Forwarded-For`.
+    fn new(addr: &str) -> Self {
+        Self {
+            addr: parse_node(addr),
+            ..Default::default()
+        }
+    }
+}
+
+impl Cidr {
+    /// Creates a range from an address and the number of leading bits that are fixed.
+    /// Returns None if the prefix length is too long for the address.
+    pub fn new(addr: IpAddr, prefix: u8) -> Option<Self> {
+        let max = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+
+        (prefix <= max).then_some(Self { addr, prefix })
+    }
+
+    /// Checks if `ip` is in this range.
+    /// IPv4 addresses mapped to IPv6 (`::ffff:10.0.0.1`) are treated as IPv4 addresses.
+    /// ## Example
+    /// ```rust
+    /// # use afire::extension::real_ip::Cidr;
+    /// let range = "10.0.0.0/8".parse::<Cidr>().unwrap();
+    /// assert!(range.contains([10, 1, 2, 3].into()));
+    /// assert!(!range.contains([11, 0, 0, 1].into()));
+    /// ```
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = match ip {
+            IpAddr::V6(i) => i.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+            i => i,
+        };
+
+        match (self.addr, ip) {
+            (IpAddr::V4(range), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(range) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(range), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(range) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = InvalidCidr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+
+        let addr = addr.parse::<IpAddr>().map_err(|_| InvalidCidr)?;
+        let prefix = match prefix {
+            Some(i) => i.parse().map_err(|_| InvalidCidr)?,
+            None if addr.is_ipv4() => 32,
+            None => 128,
+        };
+
+        Cidr::new(addr, prefix).ok_or(InvalidCidr)
+    }
+}
+
+impl Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+impl Display for InvalidCidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Invalid CIDR range")
+    }
+}
+
+impl Error for InvalidCidr {}
+
+/// Gets the values of a comma separated list header, across all headers with that name.
+fn list(headers: &Headers, header: HeaderType) -> impl Iterator<Item = &str> {
+    headers
+        .iter()
+        .filter(move |x| x.name == header)
+        .flat_map(|x| x.value.split(','))
+        .map(|x| x.trim())
+        .filter(|x| !x.is_empty())
+}
+
+/// Parses the elements of the `Forwarded` headers of a request, like `for=192.0.2.60;proto=https;host=example.com`.
+/// See [RFC 7239](https://www.rfc-editor.org/rfc/rfc7239).
+fn forwarded_hops(headers: &Headers) -> Vec<Hop> {
+    let mut hops = Vec::new();
+    for header in headers.iter().filter(|x| x.name == HeaderType::Forwarded) {
+        for element in split_quoted(&header.value, ',') {
+            let mut hop = Hop::default();
+            for pair in split_quoted(element, ';') {
+                let Some((key, value)) = pair.split_once('=') else {
+                    continue;
+                };
+
+                let value = unquote(value.trim());
+                match key.trim().to_ascii_lowercase().as_str() {
+                    "for" => hop.addr = parse_node(&value),
+                    "proto" => hop.proto = Some(value),
+                    "host" => hop.host = Some(value),
+                    _ => {}
+                }
+            }
+
+            hops.push(hop);
+        }
+    }
 
-        // If the connection is not coming from localhost (likely from reverse proxy) return the raw IP
-        if !ip.is_loopback() {
-            return ip;
+    hops
+}
+
+/// Splits `value` on `separator`, ignoring separators inside quoted strings.
+fn split_quoted(value: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut start, mut quoted, mut escaped) = (0, false, false);
+    for (i, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            c if c == separator && !quoted => {
+                parts.push(value[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
         }
+    }
+
+    parts.push(value[start..].trim());
+    parts.retain(|x| !x.is_empty());
+    parts
+}
+
+/// Removes the quotes and escapes of a quoted string, if `value` is one.
+fn unquote(value: &str) -> String {
+    match value.strip_prefix('"').and_then(|x| x.strip_suffix('"')) {
+        Some(i) => {
+            let mut out = String::with_capacity(i.len());
+            let mut chars = i.chars();
+            while let Some(c) = chars.next() {
+                out.push(match c {
+                    '\\' => chars.next().unwrap_or(c),
+                    c => c,
+                });
+            }
+            out
+        }
+        None => value.to_owned(),
+    }
+}
+
+/// Parses an address from a forwarding header, which can have a port, like `192.0.2.60:4711` or `[2001:db8::1]:4711`.
+/// Returns None for obfuscated identifiers and `unknown`.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim();
+    if let Some(i) = node.strip_prefix('[') {
+        return i.split(']').next()?.parse().ok();
+    }
+
+    node.parse::<IpAddr>()
+        .ok()
+        .or_else(|| node.parse::<SocketAddr>().ok().map(|x| x.ip()))
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{IpAddr, SocketAddr};
+
+    use super::{parse_node, split_quoted, Cidr, TrustedProxies};
+    use crate::{
+        header::{Header, Headers},
+        HeaderType, PeerAddr,
+    };
+
+    fn headers(headers: &[(HeaderType, &str)]) -> Headers {
+        Headers(headers.iter().map(|(k, v)| Header::new(k, v)).collect())
+    }
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    fn tcp(ip: &str) -> PeerAddr {
+        PeerAddr::Tcp(SocketAddr::new(ip.parse().unwrap(), 1234))
+    }
+
+    #[test]
+    fn test_cidr() {
+        let range = "192.168.0.0/16".parse::<Cidr>().unwrap();
+        assert!(range.contains(ip("192.168.10.1")));
+        assert!(range.contains(ip("::ffff:192.168.10.1")));
+        assert!(!range.contains(ip("192.169.0.1")));
+        assert!(!range.contains(ip("::1")));
+
+        let range = "2001:db8::/32".parse::<Cidr>().unwrap();
+        assert!(range.contains(ip("2001:db8:1::1")));
+        assert!(!range.contains(ip("2001:db9::1")));
+
+        assert!("0.0.0.0/0".parse::<Cidr>().unwrap().contains(ip("1.2.3.4")));
+        assert!("10.0.0.1".parse::<Cidr>().unwrap().contains(ip("10.0.0.1")));
+        assert!(!"10.0.0.1".parse::<Cidr>().unwrap().contains(ip("10.0.0.2")));
+
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("10.0.0/8".parse::<Cidr>().is_err());
+        assert!("::/129".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn test_parse_node() {
+        assert_eq!(parse_node("192.0.2.60"), Some(ip("192.0.2.60")));
+        assert_eq!(parse_node(" 192.0.2.60:4711"), Some(ip("192.0.2.60")));
+        assert_eq!(parse_node("2001:db8::1"), Some(ip("2001:db8::1")));
+        assert_eq!(parse_node("[2001:db8::1]:4711"), Some(ip("2001:db8::1")));
+        assert_eq!(parse_node("unknown"), None);
+        assert_eq!(parse_node("_hidden"), None);
+    }
+
+    #[test]
+    fn test_split_quoted() {
+        assert_eq!(
+            split_quoted(r#"for=a;host="x,y", for="b\",c""#, ','),
+            [r#"for=a;host="x,y""#, r#"for="b\",c""#]
+        );
+    }
+
+    #[test]
+    fn test_x_forwarded_for() {
+        let proxies = TrustedProxies::new().trust("10.0.0.0/8");
+        let client = |peer, chain: &[_]| proxies.client(&tcp(peer), &headers(chain)).ip;
+        let chain = [(HeaderType::XForwardedFor, "1.1.1.1, 2.2.2.2, 10.0.0.2")];
+
+        // The first untrusted address from the right is the client, not the one the client sent
+        assert_eq!(client("10.0.0.1", &chain), ip("2.2.2.2"));
+
+        // Headers from untrusted peers are ignored
+        assert_eq!(client("3.3.3.3", &chain), ip("3.3.3.3"));
+
+        // Localhost is always trusted
+        assert_eq!(client("127.0.0.1", &chain), ip("2.2.2.2"));
+
+        // Unix domain socket peers are only trusted if configured
+        let unix = PeerAddr::Unix(None);
+        assert_eq!(proxies.client(&unix, &headers(&chain)).ip, ip("127.0.0.1"));
+        let unix_proxies = proxies.clone().trust_unix();
+        assert_eq!(
+            unix_proxies.client(&unix, &headers(&chain)).ip,
+            ip("2.2.2.2")
+        );
+
+        // If every address is trusted, the leftmost one is used
+        let chain = [(HeaderType::XForwardedFor, "10.0.0.3, 10.0.0.2")];
+        assert_eq!(client("10.0.0.1", &chain), ip("10.0.0.3"));
+
+        // Walking stops at invalid addresses
+        let chain = [(HeaderType::XForwardedFor, "1.1.1.1, unknown, 10.0.0.2")];
+        assert_eq!(client("10.0.0.1", &chain), ip("10.0.0.2"));
+
+        // Multiple headers are combined
+        let chain = [
+            (HeaderType::XForwardedFor, "1.1.1.1"),
+            (HeaderType::XForwardedFor, "10.0.0.2"),
+        ];
+        assert_eq!(client("10.0.0.1", &chain), ip("1.1.1.1"));
+    }
+
+    #[test]
+    fn test_forwarded() {
+        let proxies = TrustedProxies::new().trust("10.0.0.0/8");
+        let peer = &tcp("10.0.0.1");
+        let headers = headers(&[
+            (
+                HeaderType::Forwarded,
+                r#"for=1.1.1.1;proto=http;host=evil.com, for="[2001:db8::1]:4711";proto=HTTPS;host="example.com""#,
+            ),
+            (
+                HeaderType::Forwarded,
+                "for=10.0.0.2;proto=http;host=internal",
+            ),
+            // Ignored, as the Forwarded header is preferred
+            (HeaderType::XForwardedFor, "4.4.4.4"),
+        ]);
+
+        assert_eq!(proxies.client(peer, &headers).ip, ip("2001:db8::1"));
+        assert_eq!(proxies.forwarded_scheme(peer, &headers, false), "https");
+        assert_eq!(
+            proxies.forwarded_host(peer, &headers).as_deref(),
+            Some("example.com")
+        );
+    }
+
+    #[test]
+    fn test_scheme_and_host() {
+        let proxies = TrustedProxies::new().trust("10.0.0.0/8");
+        let headers = headers(&[
+            (HeaderType::Host, "internal"),
+            (HeaderType::XForwardedFor, "1.1.1.1, 10.0.0.2"),
+            (HeaderType::XForwardedProto, "https, http"),
+            (HeaderType::XForwardedHost, "example.com"),
+        ]);
+
+        // Proto has a value for each hop, host only has one
+        let peer = &tcp("10.0.0.1");
+        assert_eq!(proxies.forwarded_scheme(peer, &headers, false), "https");
+        assert_eq!(
+            proxies.forwarded_host(peer, &headers).as_deref(),
+            Some("example.com")
+        );
 
-        // If the 'X-Forwarded-For' Header is present and its value is a valid IP, return it
-        // Otherwise return the socket address
-        self.headers
-            .get(header.into())
-            .and_then(|x| x.parse().ok())
-            .unwrap_or(ip)
+        // Untrusted peers get the values of the request itself
+        let peer = &tcp("3.3.3.3");
+        assert_eq!(proxies.forwarded_scheme(peer, &headers, true), "https");
+        assert_eq!(proxies.forwarded_scheme(peer, &headers, false), "http");
+        assert_eq!(
+            proxies.forwarded_host(peer, &headers).as_deref(),
+            Some("internal")
+        );
     }
 }
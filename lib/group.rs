@@ -0,0 +1,293 @@
+//! Groups of routes that share a path prefix and middleware.
+
+use std::{any::type_name, sync::Arc};
+
+use crate::{trace::emoji, Method, Middleware, Request, Response, Route, Server};
+
+/// A group of routes sharing a path prefix and middleware, created with [`Server::group`].
+///
+/// Middleware attached to a group only runs for requests to the routes in it (including those in nested groups).
+/// It runs after the servers middleware in the `pre` hooks, and before it in the `post` and `end` hooks.
+/// Nested groups unwind like scopes: `pre` hooks run from the outermost group in, and `post` and `end` hooks from the innermost group out.
+/// As the route has to be known for this, it is found before the groups `pre` hooks run, so changing the path in them doesn't change the route.
+/// ## Example
+/// ```rust
+/// # use afire::{Server, Response, Method, Request, Status};
+/// # use afire::middleware::{Middleware, MiddleResult};
+/// struct Auth;
+///
+/// impl Middleware for Auth {
+///     fn pre(&self, req: &mut Request) -> MiddleResult {
+///         match req.headers.get("Authorization") {
+///             Some(token) if token == "secret" => MiddleResult::Continue,
+///             _ => MiddleResult::Send(Response::new().status(Status::Unauthorized)),
+///         }
+///     }
+/// }
+///
+/// # let mut server = Server::<()>::new("localhost", 8080);
+/// server.route(Method::GET, "/", |_req| Response::new().text("Hello"));
+///
+/// // Every route in the group requires authentication
+/// server.group("/admin", |admin| {
+///     admin.middleware(Auth);
+///     admin.route(Method::GET, "/", |_req| Response::new().text("Admin panel"));
+///
+///     // Handles `/admin/users/{id}`
+///     admin.group("/users", |users| {
+///         users.route(Method::DELETE, "/{id}", |req| {
+///             Response::new().text(format!("Deleted {}", req.param("id").unwrap()))
+///         });
+///     });
+/// });
+/// ```
+pub struct Group<'a, State: 'static + Send + Sync> {
+    server: &'a mut Server<State>,
+    prefix: String,
+    middleware: Vec<Arc<dyn Middleware + Send + Sync>>,
+}
+
+impl<'a, State: 'static + Send + Sync> Group<'a, State> {
+    /// Runs `builder` with a new group, then applies the groups middleware to all the routes it added.
+    pub(crate) fn build(
+        server: &'a mut Server<State>,
+        prefix: String,
+        builder: impl FnOnce(&mut Group<State>),
+    ) {
+        trace!("{}Adding Group {}", emoji("📂"), prefix);
+        let start = server.routes.len();
+        let mut group = Group {
+            server,
+            prefix,
+            middleware: Vec::new(),
+        };
+        builder(&mut group);
+
+        // Like on the server, middleware attached last runs first in the pre hooks.
+        // The routes of nested groups already have their own middleware, which runs after this (and is unwound before it).
+        let middleware = group.middleware.iter().rev().cloned().collect::<Vec<_>>();
+        for route in &mut group.server.routes[start..] {
+            route.middleware.splice(0..0, middleware.iter().cloned());
+        }
+    }
+
+    /// Create a new route in the group, with the groups prefix added to the path.
+    /// See [`Server::route`].
+    pub fn route(
+        &mut self,
+        method: Method,
+        path: impl AsRef<str>,
+        handler: impl Fn(&Request) -> Response + Send + Sync + 'static,
+    ) -> &mut Route<State> {
+        let path = self.path(path.as_ref());
+        self.server.route(method, path, handler)
+    }
+
+    /// Create a new stateful route in the group, with the groups prefix added to the path.
+    /// See [`Server::stateful_route`].
+    pub fn stateful_route(
+        &mut self,
+        method: Method,
+        path: impl AsRef<str>,
+        handler: impl Fn(Arc<State>, &Request) -> Response + Send + Sync + 'static,
+    ) -> &mut Route<State> {
+        let path = self.path(path.as_ref());
+        self.server.stateful_route(method, path, handler)
+    }
+
+    /// Create a nested group, with a prefix added to this groups prefix.
+    /// Requests to its routes run this groups middleware first, followed by its own.
+    pub fn group(&mut self, prefix: impl AsRef<str>, builder: impl FnOnce(&mut Group<State>)) {
+        let prefix = self.path(prefix.as_ref());
+        Group::build(self.server, prefix, builder);
+    }
+
+    /// Attach middleware to the group, which only runs for requests to routes in it.
+    /// Applies to all routes in the group, including the ones added before it.
+    ///
+    /// Unlike [`Middleware::attach`], this can't be overridden by the middleware.
+    pub fn middleware<T>(&mut self, middleware: T) -> &mut Self
+    where
+        T: Middleware + Send + Sync + 'static,
+    {
+        trace!(
+            "{}Adding Middleware {} to group {}",
+            emoji("📦"),
+            type_name::<T>(),
+            self.prefix
+        );
+
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Gets the prefix of the group, including the prefixes of the groups it is nested in.
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// Adds the groups prefix to a path.
+    fn path(&self, path: &str) -> String {
+        format!(
+            "{}/{}",
+            self.prefix.trim_end_matches('/'),
+            path.trim_start_matches('/')
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use crate::{
+        internal::test_util::{get, send},
+        middleware::{MiddleResult, Middleware},
+        Method, Request, Response, Server,
+    };
+
+    #[test]
+    fn test_prefix() {
+        let mut server = Server::<()>::new("localhost", 0);
+        server.group("/api/", |api| {
+            api.route(Method::GET, "/", |_req| Response::new());
+            api.group("v1", |v1| {
+                assert_eq!(v1.prefix(), "/api/v1");
+                v1.route(Method::GET, "users/{id}", |_req| Response::new());
+            });
+        });
+
+        let paths = server
+            .routes
+            .iter()
+            .map(|x| x.path.raw.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, ["api", "api/v1/users/{id}"]);
+    }
+
+    #[test]
+    fn test_middleware_order() {
+        type Log = Arc<Mutex<Vec<String>>>;
+
+        /// Records when its hooks run, and aborts the pre hooks of requests with an `Abort` header if told to.
+        struct Record(&'static str, Log, bool);
+
+        impl Middleware for Record {
+            fn pre(&self, req: &mut Request) -> MiddleResult {
+                self.1.lock().unwrap().push(format!("pre {}", self.0));
+                match self.2 && req.headers.has("Abort") {
+                    true => MiddleResult::Abort,
+                    false => MiddleResult::Continue,
+                }
+            }
+
+            fn post(&self, _req: &Request, _res: &mut Response) -> MiddleResult {
+                self.1.lock().unwrap().push(format!("post {}", self.0));
+                MiddleResult::Continue
+            }
+        }
+
+        let log = Log::default();
+        let record = |name| Record(name, log.clone(), false);
+        let mut server = Server::<()>::new("localhost", 0);
+        Record("server", log.clone(), true).attach(&mut server);
+        server.group("/outer", |outer| {
+            outer
+                .middleware(record("outer 2"))
+                .middleware(record("outer 1"));
+            outer.group("/inner", |inner| {
+                inner
+                    .route(Method::GET, "/", |_req| Response::new())
+                    .middleware(record("route"));
+                inner.middleware(record("inner"));
+            });
+        });
+        let server = server.start_background(1).unwrap();
+
+        let request = |headers: &str| {
+            let request =
+                format!("GET /outer/inner HTTP/1.1\r\nConnection: close\r\n{headers}\r\n");
+            send(server.addr(), request);
+            log.lock().unwrap().drain(..).collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            request(""),
+            [
+                "pre server",
+                "pre outer 1",
+                "pre outer 2",
+                "pre inner",
+                "pre route",
+                "post route",
+                "post inner",
+                "post outer 2",
+                "post outer 1",
+                "post server",
+            ]
+        );
+
+        // Aborting in the servers middleware skips the middleware of the route and its groups
+        assert_eq!(request("Abort: 1\r\n"), ["pre server", "post server"]);
+
+        server.stop().unwrap();
+    }
+
+    #[test]
+    fn test_groups() {
+        /// Adds its name to the `Trace` header of requests and responses.
+        struct Tag(&'static str);
+
+        impl Middleware for Tag {
+            fn pre(&self, req: &mut Request) -> MiddleResult {
+                match req.headers.get_mut("Trace") {
+                    Some(i) => i.push_str(self.0),
+                    None => req.headers.add("Trace", self.0),
+                }
+                MiddleResult::Continue
+            }
+
+            fn post(&self, _req: &Request, res: &mut Response) -> MiddleResult {
+                match res.headers.get_mut("Trace") {
+                    Some(i) => i.push_str(self.0),
+                    None => res.headers.add("Trace", self.0),
+                }
+                MiddleResult::Continue
+            }
+        }
+
+        let trace = |req: &Request| Response::new().text(req.headers.get("Trace").unwrap());
+        let mut server = Server::<()>::new("localhost", 0);
+        Tag("s").attach(&mut server);
+        server.route(Method::GET, "/", trace);
+        server.group("/a", |a| {
+            a.route(Method::GET, "/", trace);
+            a.group("/b", |b| {
+                b.middleware(Tag("3"));
+                b.route(Method::GET, "/{id}", trace);
+            });
+            a.middleware(Tag("2")).middleware(Tag("1"));
+        });
+        let server = server.start_background(1).unwrap();
+
+        let addr = server.addr();
+        let check = |path: &str, pre: &str, post: &str| {
+            let response = get(addr, path);
+            assert!(
+                response.contains(&format!("Trace: {post}\r\n")),
+                "{}",
+                response
+            );
+            assert!(response.ends_with(pre), "{}", response);
+        };
+
+        check("/", "s", "s");
+        check("/a", "s12", "21s");
+        check("/a/b/1", "s123", "321s");
+
+        // Requests that don't match a route in the group don't run its middleware
+        assert!(get(addr, "/a/c").starts_with("HTTP/1.1 404"));
+
+        server.stop().unwrap();
+    }
+}
@@ -305,18 +305,18 @@ impl fmt::Display for SetCookie {
         let mut cookie_string = format!("{}={}; ", self.cookie.name, self.cookie.value);
 
         // Add max_age
-        if self.max_age.is_some() {
-            cookie_string.push_str(&format!("Max-Age={}; ", self.max_age.unwrap()));
+        if let Some(max_age) = self.max_age {
+            cookie_string.push_str(&format!("Max-Age={max_age}; "));
         }
 
         // Add domain
-        if self.domain.is_some() {
-            cookie_string.push_str(&format!("Domain={}; ", self.domain.as_ref().unwrap()));
+        if let Some(domain) = &self.domain {
+            cookie_string.push_str(&format!("Domain={domain}; "));
         }
 
         // Add path
-        if self.path.is_some() {
-            cookie_string.push_str(&format!("Path={}; ", self.path.as_ref().unwrap()));
+        if let Some(path) = &self.path {
+            cookie_string.push_str(&format!("Path={path}; "));
         }
 
         // Add secure
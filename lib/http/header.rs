@@ -92,7 +92,7 @@ impl Header {
     }
 
     /// Get the parameters of the header.
-    pub fn params(&self) -> HeaderParams {
+    pub fn params(&self) -> HeaderParams<'_> {
         HeaderParams::new(self.value.as_str())
     }
 }
@@ -317,10 +317,21 @@ pub enum HeaderType {
     /// The date and time at which the message was originated.
     /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Date))
     Date,
+    /// Indicates expectations that need to be met by the server before the request body is sent.
+    /// afire supports `100-continue`, which makes the server send a `100 Continue` response before the body is read.
+    /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Expect))
+    Expect,
+    /// Standardized version of the `X-Forwarded-*` headers, added by reverse proxies with the address of the client and the protocol and host it used.
+    /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Forwarded))
+    Forwarded,
     /// Sent with requests to indicate the host and port of the server to which the request is being sent.
     /// This allows for reverse proxies to forward requests to the correct server.
     /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Host))
     Host,
+    /// Sent with responses on persistent connections, to let the client know how long an idle connection will be kept open and how many more requests it can be used for.
+    /// afire sets it when [`crate::Server::idle_timeout`] or [`crate::Server::max_requests`] is set.
+    /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Keep-Alive))
+    KeepAlive,
     /// Used with redirection status codes (301, 302, 303, 307, 308) to indicate the URL to redirect to.
     /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Location))
     Location,
@@ -328,6 +339,9 @@ pub enum HeaderType {
     /// Note the misspelling of referrer as 'referer' in the HTTP spec.
     /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Referer))
     Referer,
+    /// Sent with `503 Service Unavailable` (and some other) responses to indicate how long the client should wait before retrying.
+    /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Retry-After))
+    RetryAfter,
     /// An identifier for a specific name / version of the web server software.
     /// This is set to `afire/VERSION` by default.
     /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Server))
@@ -353,6 +367,12 @@ pub enum HeaderType {
     /// This is not an official HTTP header, but is still widely used.
     /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/X-Forwarded-For))
     XForwardedFor,
+    /// A header often added by reverse proxies with the host the client originally requested.
+    /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/X-Forwarded-Host))
+    XForwardedHost,
+    /// A header often added by reverse proxies with the protocol (`http` or `https`) the client used to connect to the proxy.
+    /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/X-Forwarded-Proto))
+    XForwardedProto,
     /// Any other header that is not in this enum.
     Custom(String),
 }
@@ -383,9 +403,13 @@ impl HeaderType {
             "content-type"      => HeaderType::ContentType,
             "cookie"            => HeaderType::Cookie,
             "date"              => HeaderType::Date,
+            "expect"            => HeaderType::Expect,
+            "forwarded"         => HeaderType::Forwarded,
             "host"              => HeaderType::Host,
+            "keep-alive"        => HeaderType::KeepAlive,
             "location"          => HeaderType::Location,
             "referer"           => HeaderType::Referer,
+            "retry-after"       => HeaderType::RetryAfter,
             "server"            => HeaderType::Server,
             "set-cookie"        => HeaderType::SetCookie,
             "transfer-encoding" => HeaderType::TransferEncoding,
@@ -393,6 +417,8 @@ impl HeaderType {
             "user-agent"        => HeaderType::UserAgent,
             "via"               => HeaderType::Via,
             "x-forwarded-for"   => HeaderType::XForwardedFor,
+            "x-forwarded-host"  => HeaderType::XForwardedHost,
+            "x-forwarded-proto" => HeaderType::XForwardedProto,
             _                   => HeaderType::Custom(s.to_string()),
         }
     }
@@ -415,9 +441,13 @@ impl Display for HeaderType {
                 HeaderType::ContentType      => "Content-Type",
                 HeaderType::Cookie           => "Cookie",
                 HeaderType::Date             => "Date",
+                HeaderType::Expect           => "Expect",
+                HeaderType::Forwarded        => "Forwarded",
                 HeaderType::Host             => "Host",
+                HeaderType::KeepAlive        => "Keep-Alive",
                 HeaderType::Location         => "Location",
                 HeaderType::Referer          => "Referer",
+                HeaderType::RetryAfter       => "Retry-After",
                 HeaderType::Server           => "Server",
                 HeaderType::SetCookie        => "Set-Cookie",
                 HeaderType::TransferEncoding => "Transfer-Encoding",
@@ -425,6 +455,8 @@ impl Display for HeaderType {
                 HeaderType::UserAgent        => "User-Agent",
                 HeaderType::Via              => "Via",
                 HeaderType::XForwardedFor    => "X-Forwarded-For",
+                HeaderType::XForwardedHost   => "X-Forwarded-Host",
+                HeaderType::XForwardedProto  => "X-Forwarded-Proto",
                 HeaderType::Custom(s)        => s,
             }
         )
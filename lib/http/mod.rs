@@ -8,3 +8,4 @@ pub mod multipart;
 pub mod query;
 pub mod server_sent_events;
 pub mod status;
+pub mod version;
@@ -43,12 +43,12 @@ pub enum MultipartError {
 
 impl<'a> MultipartData<'a> {
     /// Get an entry by name, returns `None` if the entry does not exist.
-    pub fn get(&self, name: impl AsRef<str>) -> Option<&MultipartEntry> {
+    pub fn get(&self, name: impl AsRef<str>) -> Option<&MultipartEntry<'a>> {
         self.entries.iter().find(|x| x.name == name.as_ref())
     }
 
     /// Gets a mutable reference to an entry by name, returns `None` if the entry does not exist.
-    pub fn get_mut(&'a mut self, name: impl AsRef<str>) -> Option<&mut MultipartEntry> {
+    pub fn get_mut(&'a mut self, name: impl AsRef<str>) -> Option<&'a mut MultipartEntry<'a>> {
         self.entries.iter_mut().find(|x| x.name == name.as_ref())
     }
 }
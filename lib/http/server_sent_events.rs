@@ -25,7 +25,7 @@
 //! });
 //! ```
 use std::{
-    fmt::Display,
+    fmt::{self, Display},
     io::{self, Write},
     sync::{
         mpsc::{self, Sender},
@@ -157,23 +157,19 @@ impl Event {
     }
 }
 
-impl ToString for Event {
-    fn to_string(&self) -> String {
-        let mut out = String::new();
-
+impl Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if let Some(id) = self.id {
-            out.push_str(&format!("id: {id}\n"));
+            writeln!(f, "id: {id}")?;
         }
 
-        let event = &self.event;
-        out.push_str(&format!("event: {event}\n"));
+        writeln!(f, "event: {}", self.event)?;
 
         for i in self.data.split('\n') {
-            out.push_str(&format!("data: {i}\n"));
+            writeln!(f, "data: {i}")?;
         }
 
-        out.push('\n');
-        out
+        writeln!(f)
     }
 }
 
@@ -0,0 +1,94 @@
+//! HTTP versions.
+
+use std::{fmt, str::FromStr};
+
+/// HTTP versions supported by afire.
+///
+/// The version of a request decides if its connection is persistent by default.
+/// HTTP/1.1 connections are kept alive unless the client sends `Connection: close`, while HTTP/1.0 connections are closed unless the client sends `Connection: keep-alive`.
+#[derive(Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum HttpVersion {
+    /// HTTP/1.0.
+    /// Does not support chunked transfer-encoding, so streaming responses are sent without it and the connection is closed to mark the end of the body.
+    Http10,
+
+    /// HTTP/1.1.
+    Http11,
+}
+
+/// Error returned when parsing an [`HttpVersion`] fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersionError {
+    /// The version is not in the `HTTP/<major>.<minor>` format.
+    Invalid,
+    /// The version is valid, but not one afire supports (like `HTTP/2.0`).
+    Unsupported,
+}
+
+impl HttpVersion {
+    /// Checks if connections using this version are persistent by default.
+    ///
+    /// ```rust
+    /// # use afire::HttpVersion;
+    /// assert!(HttpVersion::Http11.keep_alive_default());
+    /// assert!(!HttpVersion::Http10.keep_alive_default());
+    /// ```
+    pub fn keep_alive_default(&self) -> bool {
+        *self >= HttpVersion::Http11
+    }
+
+    /// Checks if this version supports chunked transfer-encoding.
+    pub fn supports_chunked(&self) -> bool {
+        *self >= HttpVersion::Http11
+    }
+}
+
+impl FromStr for HttpVersion {
+    type Err = VersionError;
+
+    /// Parses a version from the last part of a request line.
+    /// Later HTTP/1.x versions are treated as HTTP/1.1, as they are compatible with it.
+    /// ## Examples
+    /// ```rust
+    /// # use std::str::FromStr;
+    /// # use afire::{HttpVersion, version::VersionError};
+    /// assert_eq!(HttpVersion::from_str("HTTP/1.0"), Ok(HttpVersion::Http10));
+    /// assert_eq!(HttpVersion::from_str("HTTP/1.1"), Ok(HttpVersion::Http11));
+    /// assert_eq!(HttpVersion::from_str("HTTP/2.0"), Err(VersionError::Unsupported));
+    /// assert_eq!(HttpVersion::from_str("HTTP/one"), Err(VersionError::Invalid));
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (major, minor) = s
+            .strip_prefix("HTTP/")
+            .and_then(|x| x.split_once('.'))
+            .ok_or(VersionError::Invalid)?;
+
+        let digits = |x: &str| !x.is_empty() && x.bytes().all(|x| x.is_ascii_digit());
+        if !digits(major) || !digits(minor) {
+            return Err(VersionError::Invalid);
+        }
+
+        Ok(
+            match (major.trim_start_matches('0'), minor.trim_start_matches('0')) {
+                ("1", "") => HttpVersion::Http10,
+                ("1", _) => HttpVersion::Http11,
+                _ => return Err(VersionError::Unsupported),
+            },
+        )
+    }
+}
+
+impl fmt::Display for HttpVersion {
+    /// Returns the string representation of the version, as used in request and status lines.
+    ///
+    /// ```rust
+    /// # use afire::HttpVersion;
+    /// assert_eq!("HTTP/1.1", HttpVersion::Http11.to_string());
+    /// ```
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            HttpVersion::Http10 => "HTTP/1.0",
+            HttpVersion::Http11 => "HTTP/1.1",
+        })
+    }
+}
@@ -2,7 +2,6 @@ use std::{
     convert::TryInto,
     fmt::Display,
     io::{self, Read, Write},
-    net::TcpStream,
     sync::{
         mpsc::{self, Iter, Receiver, SyncSender},
         Arc,
@@ -14,6 +13,7 @@ use crate::{
     internal::{
         common::ForceLock,
         encoding::{base64, sha1},
+        socket::Socket,
     },
     HeaderType, Request, Response, Status,
 };
@@ -257,7 +257,7 @@ impl Frame {
         buf
     }
 
-    fn write(&self, socket: &mut TcpStream) -> io::Result<()> {
+    fn write(&self, socket: &mut Socket) -> io::Result<()> {
         let buf = self.to_bytes();
         trace!(Level::Debug, "WS: Writing: {:?}", buf);
 
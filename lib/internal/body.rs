@@ -0,0 +1,720 @@
+//! Request bodies.
+//! Works out how a request body is delimited, decodes `Content-Length` and chunked bodies and lets routes stream them.
+
+use std::{
+    convert::TryFrom,
+    io::{self, BufRead, Read},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::{
+    consts::CHUNK_SIZE,
+    error::{ParseError, Result, StreamError},
+    header::{HeaderType, Headers},
+    internal::{common::ForceLock, socket::SocketReader},
+    Error, Header, Limits,
+};
+
+/// How the body of a request is delimited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Framing {
+    /// The request has no body.
+    None,
+    /// The body is exactly this many bytes long.
+    Length(u64),
+    /// The body uses chunked transfer-encoding.
+    Chunked,
+}
+
+/// Reads a request body from a connection's reader, stopping at the end of the body.
+/// The reader is passed to every call, so it can be shared with the rest of the connection.
+/// For chunked bodies, chunk extensions are ignored and trailers are collected into `trailers`.
+pub(crate) struct BodyReader {
+    state: State,
+    limits: Limits,
+    /// Bytes of body data read so far.
+    read: u64,
+    trailers: Vec<Header>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Bytes left in a `Content-Length` body.
+    Length(u64),
+    /// Waiting for the size line of the next chunk.
+    ChunkSize,
+    /// Bytes left in the current chunk.
+    Chunk(u64),
+    /// Waiting for the CRLF after a chunk's data.
+    ChunkEnd,
+    /// The whole body (and any trailers) has been read.
+    Done,
+}
+
+impl Framing {
+    /// Works out how the body of a request with these headers is delimited.
+    ///
+    /// Requests with both a `Content-Length` and a `Transfer-Encoding` header are rejected, as proxies may disagree on where the body ends.
+    /// The same goes for multiple `Content-Length` headers that don't agree, and transfer codings other than `chunked`.
+    pub(crate) fn from_headers(headers: &Headers) -> Result<Self> {
+        let mut length = None;
+        let mut codings = Vec::new();
+
+        for header in headers.iter() {
+            match header.name {
+                HeaderType::ContentLength => {
+                    for i in header.value.split(',').map(str::trim) {
+                        if i.is_empty() || !i.bytes().all(|x| x.is_ascii_digit()) {
+                            return Err(ParseError::InvalidContentLength.into());
+                        }
+
+                        let i = i
+                            .parse::<u64>()
+                            .map_err(|_| ParseError::InvalidContentLength)?;
+                        match length {
+                            Some(len) if len != i => {
+                                return Err(ParseError::InvalidContentLength.into())
+                            }
+                            _ => length = Some(i),
+                        }
+                    }
+                }
+                HeaderType::TransferEncoding => codings.extend(
+                    header
+                        .value
+                        .split(',')
+                        .map(|x| x.trim().to_ascii_lowercase())
+                        .filter(|x| !x.is_empty()),
+                ),
+                _ => {}
+            }
+        }
+
+        let has_encoding = headers.has(HeaderType::TransferEncoding);
+        if has_encoding && length.is_some() {
+            return Err(ParseError::ConflictingLength.into());
+        }
+
+        if has_encoding {
+            // Decoding other transfer codings is not supported
+            if codings.len() != 1 || codings[0] != "chunked" {
+                return Err(ParseError::InvalidTransferEncoding.into());
+            }
+
+            return Ok(Framing::Chunked);
+        }
+
+        Ok(match length {
+            Some(0) | None => Framing::None,
+            Some(i) => Framing::Length(i),
+        })
+    }
+}
+
+impl BodyReader {
+    /// Creates a reader for a body with the given framing.
+    /// The body size, as well as the number and size of trailers are checked against `limits`.
+    pub(crate) fn new(framing: Framing, limits: Limits) -> Self {
+        let state = match framing {
+            Framing::None => State::Done,
+            Framing::Length(i) => State::Length(i),
+            Framing::Chunked => State::ChunkSize,
+        };
+
+        Self {
+            state,
+            limits,
+            read: 0,
+            trailers: Vec::new(),
+        }
+    }
+
+    /// Reads the rest of the body from `inner` into a Vec.
+    /// Malformed chunks and exceeded limits are returned as a [`ParseError`] and a connection that closes early or times out as a [`StreamError`].
+    pub(crate) fn read_all(&mut self, inner: &mut impl BufRead) -> Result<Vec<u8>> {
+        let mut body = Vec::new();
+        if let State::Length(i) = self.state {
+            body.reserve(i.min(self.limits.max_body_size as u64) as usize);
+        }
+
+        Decoder { body: self, inner }
+            .read_to_end(&mut body)
+            .map_err(
+                |e| match e.get_ref().and_then(|x| x.downcast_ref::<ParseError>()) {
+                    Some(e) => Error::Parse(e.to_owned()),
+                    None => Error::Stream(StreamError::from_read(&e)),
+                },
+            )?;
+        Ok(body)
+    }
+
+    /// Checks if the whole body has been read.
+    pub(crate) fn is_done(&self) -> bool {
+        self.state == State::Done
+    }
+
+    /// Gets the trailers sent after a chunked body.
+    /// These are only available once the whole body has been read.
+    pub(crate) fn trailers(&self) -> &[Header] {
+        &self.trailers
+    }
+
+    /// Takes the trailers sent after a chunked body.
+    /// These are only available once the whole body has been read.
+    pub(crate) fn take_trailers(&mut self) -> Vec<Header> {
+        std::mem::take(&mut self.trailers)
+    }
+
+    /// Reads some of the body from `inner` into `buf`, returning how many bytes were read.
+    /// Like [`Read::read`], 0 is returned once the end of the body has been reached.
+    pub(crate) fn read(&mut self, inner: &mut impl BufRead, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.state {
+                State::Done => return Ok(0),
+                State::Length(0) => self.state = State::Done,
+                State::Length(left) => {
+                    self.check_size(left)?;
+                    let read = self.read_data(inner, buf, left)?;
+                    self.state = State::Length(left - read as u64);
+                    return Ok(read);
+                }
+                State::ChunkSize => match self.read_chunk_size(inner)? {
+                    0 => {
+                        self.read_trailers(inner)?;
+                        self.state = State::Done;
+                    }
+                    i => {
+                        self.check_size(i)?;
+                        self.state = State::Chunk(i);
+                    }
+                },
+                State::Chunk(0) => self.state = State::ChunkEnd,
+                State::Chunk(left) => {
+                    let read = self.read_data(inner, buf, left)?;
+                    self.state = State::Chunk(left - read as u64);
+                    return Ok(read);
+                }
+                State::ChunkEnd => {
+                    if !read_line(inner, 0, ParseError::InvalidChunk)?.is_empty() {
+                        return Err(parse_error(ParseError::InvalidChunk));
+                    }
+
+                    self.state = State::ChunkSize;
+                }
+            }
+        }
+    }
+
+    /// Checks that `len` more bytes still fit in the body size limit.
+    fn check_size(&self, len: u64) -> io::Result<()> {
+        match self.read.checked_add(len) {
+            Some(i) if i <= self.limits.max_body_size as u64 => Ok(()),
+            _ => Err(parse_error(ParseError::BodyTooLarge)),
+        }
+    }
+
+    /// Reads at most `left` bytes of body data into `buf`.
+    fn read_data(
+        &mut self,
+        inner: &mut impl BufRead,
+        buf: &mut [u8],
+        left: u64,
+    ) -> io::Result<usize> {
+        let max = buf.len().min(usize::try_from(left).unwrap_or(usize::MAX));
+        let read = inner.read(&mut buf[..max])?;
+        if read == 0 && max > 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        self.read += read as u64;
+        Ok(read)
+    }
+
+    /// Reads the size line of a chunk, ignoring any chunk extensions.
+    fn read_chunk_size(&self, inner: &mut impl BufRead) -> io::Result<u64> {
+        let line = read_line(inner, self.limits.max_header_size, ParseError::InvalidChunk)?;
+        let size = line.split(|x| *x == b';').next().unwrap_or_default();
+        let size = String::from_utf8_lossy(size);
+        let size = size.trim();
+
+        if size.is_empty() || !size.bytes().all(|x| x.is_ascii_hexdigit()) {
+            return Err(parse_error(ParseError::InvalidChunk));
+        }
+
+        u64::from_str_radix(size, 16).map_err(|_| parse_error(ParseError::InvalidChunk))
+    }
+
+    /// Reads the trailer section after the last chunk, up to and including the final empty line.
+    fn read_trailers(&mut self, inner: &mut impl BufRead) -> io::Result<()> {
+        loop {
+            let line = read_line(
+                inner,
+                self.limits.max_header_size,
+                ParseError::HeaderTooLarge,
+            )?;
+            if line.is_empty() {
+                return Ok(());
+            }
+
+            if self.trailers.len() >= self.limits.max_header_count {
+                return Err(parse_error(ParseError::TooManyHeaders));
+            }
+
+            let header = Header::from_string(String::from_utf8_lossy(&line))
+                .map_err(|_| parse_error(ParseError::InvalidChunk))?;
+            self.trailers.push(header);
+        }
+    }
+}
+
+/// A reader over the body of a request, returned by [`crate::Request::body_reader`].
+///
+/// For routes with a streaming body (see [`crate::Route::stream_body`]), the body is read from the connection as you read from this.
+/// Any part of the body that has not been read once the response is sent is read and discarded by the server, so the connection can be used for the next request.
+/// For all other routes, this reads from the already buffered [`crate::Request::body`].
+pub struct RequestBody {
+    source: BodySource,
+}
+
+enum BodySource {
+    Buffered {
+        body: Arc<Vec<u8>>,
+        trailers: Headers,
+        pos: usize,
+    },
+    Stream {
+        reader: Arc<Mutex<SocketReader>>,
+        body: Arc<Mutex<BodyReader>>,
+    },
+}
+
+impl RequestBody {
+    /// Creates a reader over an already buffered body.
+    pub(crate) fn buffered(body: Arc<Vec<u8>>, trailers: Headers) -> Self {
+        Self {
+            source: BodySource::Buffered {
+                body,
+                trailers,
+                pos: 0,
+            },
+        }
+    }
+
+    /// Creates a reader that streams the body from the connection's reader.
+    pub(crate) fn stream(reader: Arc<Mutex<SocketReader>>, body: BodyReader) -> Self {
+        Self {
+            source: BodySource::Stream {
+                reader,
+                body: Arc::new(Mutex::new(body)),
+            },
+        }
+    }
+
+    /// Checks if the body is streamed from the connection, rather than buffered.
+    pub fn is_streaming(&self) -> bool {
+        matches!(self.source, BodySource::Stream { .. })
+    }
+
+    /// Gets the trailers sent after a chunked body.
+    /// For streaming bodies, these are only available once the whole body has been read.
+    pub fn trailers(&self) -> Headers {
+        match &self.source {
+            BodySource::Buffered { trailers, .. } => trailers.clone(),
+            BodySource::Stream { body, .. } => Headers(body.force_lock().trailers().to_vec()),
+        }
+    }
+
+    /// Creates another reader over the same body.
+    /// Streaming readers share their position, while buffered ones start again from the beginning.
+    pub(crate) fn share(&self) -> Self {
+        let source = match &self.source {
+            BodySource::Buffered { body, trailers, .. } => BodySource::Buffered {
+                body: body.clone(),
+                trailers: trailers.clone(),
+                pos: 0,
+            },
+            BodySource::Stream { reader, body } => BodySource::Stream {
+                reader: reader.clone(),
+                body: body.clone(),
+            },
+        };
+
+        Self { source }
+    }
+
+    /// Sets a deadline `timeout` from now, that reading the rest of a streaming body has to be finished by.
+    pub(crate) fn set_deadline(&self, timeout: Option<Duration>) {
+        if let BodySource::Stream { reader, .. } = &self.source {
+            reader.force_lock().get_mut().set_deadline(timeout);
+        }
+    }
+
+    /// Reads the rest of a streaming body into a Vec, along with its trailers.
+    pub(crate) fn read_all(&self) -> Result<(Vec<u8>, Headers)> {
+        match &self.source {
+            BodySource::Buffered { body, trailers, .. } => Ok((body.to_vec(), trailers.clone())),
+            BodySource::Stream { reader, body } => {
+                let mut body = body.force_lock();
+                let data = body.read_all(&mut *reader.force_lock())?;
+                Ok((data, Headers(body.take_trailers())))
+            }
+        }
+    }
+
+    /// Reads and discards the rest of a streaming body, so the next request can be read from the connection.
+    pub(crate) fn drain(&self) -> io::Result<()> {
+        if let BodySource::Stream { reader, body } = &self.source {
+            let mut body = body.force_lock();
+            let mut reader = reader.force_lock();
+            let mut buf = vec![0; CHUNK_SIZE];
+            while !body.is_done() {
+                match body.read(&mut *reader, &mut buf) {
+                    Ok(_) => {}
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Read for RequestBody {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.source {
+            BodySource::Buffered { body, pos, .. } => {
+                let read = (&body[*pos..]).read(buf)?;
+                *pos += read;
+                Ok(read)
+            }
+            BodySource::Stream { reader, body } => {
+                let mut body = body.force_lock();
+                body.read(&mut *reader.force_lock(), buf)
+            }
+        }
+    }
+}
+
+/// Lets a [`BodyReader`] be used as a [`Read`] while borrowing its source.
+struct Decoder<'a, R> {
+    body: &'a mut BodyReader,
+    inner: &'a mut R,
+}
+
+impl<R: BufRead> Read for Decoder<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.body.read(self.inner, buf)
+    }
+}
+
+/// Reads a CRLF (or bare LF) terminated line of at most `max` bytes, without the line ending.
+fn read_line(inner: &mut impl BufRead, max: usize, error: ParseError) -> io::Result<Vec<u8>> {
+    let mut line = Vec::new();
+    inner.take(max as u64 + 2).read_until(b'\n', &mut line)?;
+
+    if line.pop() != Some(b'\n') {
+        return Err(match line.len() > max {
+            true => parse_error(error),
+            false => io::ErrorKind::UnexpectedEof.into(),
+        });
+    }
+
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+
+    if line.len() > max {
+        return Err(parse_error(error));
+    }
+
+    Ok(line)
+}
+
+/// Wraps a [`ParseError`] in an [`io::Error`], so it can be returned from [`Read::read`].
+fn parse_error(error: ParseError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{BufReader, Read, Write},
+        net::TcpStream,
+        time::Duration,
+    };
+
+    use super::{BodyReader, Framing};
+    use crate::{
+        error::{Error, ParseError, StreamError},
+        header::Headers,
+        internal::test_util::{read_lossy, responses, send},
+        Header, Limits, Method, Response, Server,
+    };
+
+    fn headers(headers: &[(&str, &str)]) -> Headers {
+        Headers(headers.iter().map(|(k, v)| Header::new(*k, *v)).collect())
+    }
+
+    fn framing(list: &[(&str, &str)]) -> Result<Framing, Error> {
+        Framing::from_headers(&headers(list))
+    }
+
+    #[test]
+    fn test_framing() {
+        assert_eq!(framing(&[]), Ok(Framing::None));
+        assert_eq!(framing(&[("Content-Length", "0")]), Ok(Framing::None));
+        assert_eq!(
+            framing(&[("Content-Length", "12")]),
+            Ok(Framing::Length(12))
+        );
+        assert_eq!(
+            framing(&[("Content-Length", "12"), ("Content-Length", "12, 12")]),
+            Ok(Framing::Length(12))
+        );
+        assert_eq!(
+            framing(&[("Transfer-Encoding", "Chunked")]),
+            Ok(Framing::Chunked)
+        );
+    }
+
+    #[test]
+    fn test_framing_invalid() {
+        assert_eq!(
+            framing(&[("Content-Length", "5"), ("Transfer-Encoding", "chunked")]),
+            Err(ParseError::ConflictingLength.into())
+        );
+        assert_eq!(
+            framing(&[("Content-Length", "5"), ("Content-Length", "6")]),
+            Err(ParseError::InvalidContentLength.into())
+        );
+        assert_eq!(
+            framing(&[("Content-Length", "+5")]),
+            Err(ParseError::InvalidContentLength.into())
+        );
+        assert_eq!(
+            framing(&[("Transfer-Encoding", "gzip, chunked")]),
+            Err(ParseError::InvalidTransferEncoding.into())
+        );
+        assert_eq!(
+            framing(&[("Transfer-Encoding", "chunked, chunked")]),
+            Err(ParseError::InvalidTransferEncoding.into())
+        );
+    }
+
+    #[test]
+    fn test_chunked_body() {
+        let raw = b"4\r\nWiki\r\n7;name=value\r\npedia i\r\nB\r\nn \r\nchunks.\r\n0\r\nExpires: never\r\n\r\nGET";
+        let mut reader = BufReader::new(&raw[..]);
+        let mut body = BodyReader::new(Framing::Chunked, Limits::new());
+
+        assert_eq!(
+            body.read_all(&mut reader).unwrap(),
+            b"Wikipedia in \r\nchunks."
+        );
+        let trailers = Headers(body.take_trailers());
+        assert_eq!(trailers.get("Expires"), Some("never"));
+
+        // Bytes after the body are left in the reader
+        let mut rest = String::new();
+        reader.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "GET");
+    }
+
+    #[test]
+    fn test_chunked_body_invalid() {
+        let read =
+            |mut raw: &[u8]| BodyReader::new(Framing::Chunked, Limits::new()).read_all(&mut raw);
+
+        assert_eq!(read(b"x\r\n"), Err(ParseError::InvalidChunk.into()));
+        assert_eq!(read(b"\r\n"), Err(ParseError::InvalidChunk.into()));
+        assert_eq!(
+            read(b"2\r\nabc\r\n0\r\n\r\n"),
+            Err(ParseError::InvalidChunk.into())
+        );
+        assert_eq!(
+            read(b"ffffffffffffffffff\r\n"),
+            Err(ParseError::InvalidChunk.into())
+        );
+        assert_eq!(
+            read(b"5\r\nab"),
+            Err(Error::Stream(StreamError::UnexpectedEof))
+        );
+    }
+
+    #[test]
+    fn test_length_body() {
+        let raw = b"hello world";
+        let mut reader = BufReader::new(&raw[..]);
+        let mut body = BodyReader::new(Framing::Length(5), Limits::new());
+        assert_eq!(body.read_all(&mut reader).unwrap(), b"hello");
+        assert!(body.take_trailers().is_empty());
+
+        let mut body = BodyReader::new(Framing::Length(20), Limits::new());
+        assert_eq!(
+            body.read_all(&mut &raw[..]),
+            Err(Error::Stream(StreamError::UnexpectedEof))
+        );
+    }
+
+    #[test]
+    fn test_body_limits() {
+        let limits = Limits {
+            max_body_size: 5,
+            max_header_count: 1,
+            ..Limits::new()
+        };
+        let read = |mut raw: &[u8], framing| BodyReader::new(framing, limits).read_all(&mut raw);
+
+        assert_eq!(read(b"hello", Framing::Length(5)).unwrap(), b"hello");
+        assert_eq!(
+            read(b"hello world", Framing::Length(11)),
+            Err(ParseError::BodyTooLarge.into())
+        );
+        assert_eq!(
+            read(b"3\r\nhel\r\n3\r\nlo \r\n0\r\n\r\n", Framing::Chunked),
+            Err(ParseError::BodyTooLarge.into())
+        );
+        assert_eq!(
+            read(b"0\r\nA: 1\r\nB: 2\r\n\r\n", Framing::Chunked),
+            Err(ParseError::TooManyHeaders.into())
+        );
+    }
+
+    #[test]
+    fn test_chunked_request() {
+        let mut server = Server::<()>::new("localhost", 0);
+        server.route(Method::POST, "/{name}", |req| {
+            let trailer = req.trailers.get("Checksum").unwrap_or("none");
+            Response::new().text(format!(
+                "{}={};{}",
+                req.param("name").unwrap(),
+                req.body_str(),
+                trailer
+            ))
+        });
+
+        let server = server.start_background(1).unwrap();
+        let response = send(
+            server.addr(),
+            b"POST /a HTTP/1.1\r\nConnection: keep-alive\r\nTransfer-Encoding: chunked\r\n\r\n\
+              3;ext=1\r\nabc\r\n2\r\nde\r\n0\r\nChecksum: 1234\r\n\r\n\
+              POST /b HTTP/1.1\r\nConnection: close\r\nContent-Length: 2\r\n\r\n22",
+        );
+        let bodies = responses(&response)
+            .iter()
+            .map(|x| x.rsplit("\r\n\r\n").next().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(bodies, ["a=abcde;1234", "b=22;none"]);
+
+        let response = send(
+            server.addr(),
+            "POST /a HTTP/1.1\r\nContent-Length: 3\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n",
+        );
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request"));
+
+        server.stop().unwrap();
+    }
+
+    #[test]
+    fn test_streaming_body() {
+        let mut server = Server::<()>::new("localhost", 0);
+        server
+            .route(Method::POST, "/partial", |req| {
+                // Only read the first 3 bytes, the rest is drained by the server
+                let mut buf = [0; 3];
+                req.body_reader().read_exact(&mut buf).unwrap();
+                Response::new().text(String::from_utf8_lossy(&buf))
+            })
+            .stream_body();
+        server
+            .route(Method::POST, "/echo", |req| {
+                Response::new().stream(req.body_reader())
+            })
+            .stream_body();
+        server.route(Method::POST, "/buffered", |req| {
+            let mut body = String::new();
+            req.body_reader().read_to_string(&mut body).unwrap();
+            Response::new().text(format!("{}|{}", body, req.body_str()))
+        });
+
+        let server = server.start_background(1).unwrap();
+        let response = send(
+            server.addr(),
+            b"POST /partial HTTP/1.1\r\nConnection: keep-alive\r\nContent-Length: 10\r\n\r\n0123456789\
+              POST /echo HTTP/1.1\r\nConnection: keep-alive\r\nTransfer-Encoding: chunked\r\n\r\n\
+              5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n\
+              POST /buffered HTTP/1.1\r\nConnection: close\r\nContent-Length: 3\r\n\r\nabc",
+        );
+        let responses = responses(&response);
+        assert_eq!(responses.len(), 3);
+        assert!(responses[0].ends_with("\r\n\r\n012"));
+        assert!(responses[1].ends_with("\r\n\r\n5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n"));
+        assert!(responses[2].ends_with("\r\n\r\nabc|abc"));
+
+        server.stop().unwrap();
+    }
+
+    #[test]
+    fn test_streaming_body_panic() {
+        let mut server = Server::<()>::new("localhost", 0).max_body_size(32);
+        server
+            .route(Method::POST, "/upload", |req| {
+                req.body_reader().read_exact(&mut [0; 1]).unwrap();
+                panic!("Upload failed");
+            })
+            .stream_body();
+        server.route(Method::POST, "/", |req| {
+            Response::new().text(req.body_str())
+        });
+        server.route(Method::GET, "/secret", |_req| {
+            Response::new().text("SMUGGLED")
+        });
+
+        let server = server.start_background(1).unwrap();
+        let send = |request: &[u8]| {
+            let mut stream = TcpStream::connect(server.addr()).unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            stream.write_all(request).unwrap();
+            read_lossy(&mut stream)
+        };
+
+        // The unread body of a handler that panicked is not read as the next request
+        let body = "GET /secret HTTP/1.1\r\n\r\n";
+        let response = send(
+            format!(
+                "POST /upload HTTP/1.1\r\nContent-Length: {}\r\n\r\n{body}\
+                 GET /secret HTTP/1.1\r\nConnection: close\r\n\r\n",
+                body.len()
+            )
+            .as_bytes(),
+        );
+        assert!(response.starts_with("HTTP/1.1 500"));
+        assert_eq!(response.matches("SMUGGLED").count(), 1);
+
+        // Neither is the rest of a body that failed to read after a `100 Continue`
+        let mut stream = TcpStream::connect(server.addr()).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        stream
+            .write_all(
+                b"POST / HTTP/1.1\r\nExpect: 100-continue\r\nTransfer-Encoding: chunked\r\n\r\n",
+            )
+            .unwrap();
+        let mut interim = [0; 25];
+        stream.read_exact(&mut interim).unwrap();
+        assert_eq!(&interim, b"HTTP/1.1 100 Continue\r\n\r\n");
+        stream
+            .write_all(b"40\r\nGET /secret HTTP/1.1\r\n\r\n")
+            .unwrap();
+        let response = read_lossy(&mut stream);
+        assert!(response.starts_with("HTTP/1.1 413"));
+        assert!(!response.contains("SMUGGLED"));
+
+        server.stop().unwrap();
+    }
+}
@@ -1,18 +1,25 @@
 //! Some little functions used here and there
 
-use std::net::{Ipv4Addr, Ipv6Addr};
+use std::net::{Ipv4Addr, Ipv6Addr, ToSocketAddrs};
 use std::sync::{Mutex, MutexGuard};
 use std::{borrow::Cow, net::IpAddr};
 
 use crate::error::{Result, StartupError};
 
 /// Trait used to accept multiple types for the address of a server.
-/// Default implementations are provided for `Ipv4Addr`, `String`, `&String` and `&str`.
+/// Default implementations are provided for `IpAddr`, `Ipv4Addr`, `Ipv6Addr`, byte arrays, `String`, `&String` and `&str`.
+/// Strings can be IPv4 or IPv6 literals (optionally in brackets, like `[::1]`), `localhost` or a hostname to resolve, see [`parse_host`].
 pub trait ToHostAddress {
-    /// Convert the type to an `Ipv4Addr`.
+    /// Convert the type to an `IpAddr`.
     fn to_address(&self) -> Result<IpAddr>;
 }
 
+impl ToHostAddress for IpAddr {
+    fn to_address(&self) -> Result<IpAddr> {
+        Ok(*self)
+    }
+}
+
 impl ToHostAddress for Ipv4Addr {
     fn to_address(&self) -> Result<IpAddr> {
         Ok((*self).into())
@@ -45,29 +52,29 @@ impl ToHostAddress for [u8; 16] {
 
 impl ToHostAddress for String {
     fn to_address(&self) -> Result<IpAddr> {
-        Ok(Ipv4Addr::from(parse_ip(self)?).into())
+        parse_host(self)
     }
 }
 
 impl ToHostAddress for &String {
     fn to_address(&self) -> Result<IpAddr> {
-        Ok(Ipv4Addr::from(parse_ip(self)?).into())
+        parse_host(self)
     }
 }
 
 impl ToHostAddress for &str {
     fn to_address(&self) -> Result<IpAddr> {
-        Ok(Ipv4Addr::from(parse_ip(self)?).into())
+        parse_host(self)
     }
 }
 
 /// Adds a force_lock method to Mutex, which will return the inner value even if its poisoned.
 pub(crate) trait ForceLock<T> {
-    fn force_lock(&self) -> MutexGuard<T>;
+    fn force_lock(&self) -> MutexGuard<'_, T>;
 }
 
 impl<T> ForceLock<T> for Mutex<T> {
-    fn force_lock(&self) -> MutexGuard<T> {
+    fn force_lock(&self) -> MutexGuard<'_, T> {
         match self.lock() {
             Ok(i) => i,
             Err(e) => e.into_inner(),
@@ -96,6 +103,31 @@ pub fn parse_ip(raw: &str) -> Result<[u8; 4]> {
     Ok(ip)
 }
 
+/// Parse a string to an IP address.
+/// Accepts IPv4 and IPv6 literals (IPv6 optionally in brackets), and `localhost`, which is always 127.0.0.1.
+/// Anything else is resolved as a hostname, using the first address it resolves to.
+/// Will return a [`StartupError::InvalidIp`] if the string is not an IP address and can't be resolved.
+pub fn parse_host(raw: &str) -> Result<IpAddr> {
+    if raw == "localhost" {
+        return Ok(Ipv4Addr::LOCALHOST.into());
+    }
+
+    let literal = raw
+        .strip_prefix('[')
+        .and_then(|x| x.strip_suffix(']'))
+        .unwrap_or(raw);
+    if let Ok(ip) = literal.parse::<IpAddr>() {
+        return Ok(ip);
+    }
+
+    (raw, 0)
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut x| x.next())
+        .map(|x| x.ip())
+        .ok_or_else(|| StartupError::InvalidIp.into())
+}
+
 /// Attempt to downcast a `Box<dyn Any>` to a `String` or `&str`.
 /// Will return an empty string if the downcast fails.
 pub(crate) fn any_string(any: Box<dyn std::any::Any + Send>) -> Cow<'static, str> {
@@ -125,7 +157,7 @@ pub(crate) fn epoch() -> std::time::Duration {
 mod test {
     use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
-    use super::{parse_ip, ToHostAddress};
+    use super::{parse_host, parse_ip, ToHostAddress};
     use crate::error::StartupError;
 
     #[test]
@@ -190,10 +222,23 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_parse_host() {
+        assert_eq!(parse_host("localhost"), Ok(Ipv4Addr::LOCALHOST.into()));
+        assert_eq!(
+            parse_host("10.0.0.1"),
+            Ok(Ipv4Addr::new(10, 0, 0, 1).into())
+        );
+        assert_eq!(parse_host("::1"), Ok(Ipv6Addr::LOCALHOST.into()));
+        assert_eq!(parse_host("[::]"), Ok(Ipv6Addr::UNSPECIFIED.into()));
+        assert_eq!(parse_host("256.0.0.1"), Err(StartupError::InvalidIp.into()));
+        assert_eq!(parse_host("[::1"), Err(StartupError::InvalidIp.into()));
+    }
+
     #[test]
     fn test_from_ref_string_addr() {
         assert_eq!(
-            (&"127.0.0.1".to_owned()).to_address().unwrap(),
+            <&String>::to_address(&&"127.0.0.1".to_owned()).unwrap(),
             IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))
         );
     }
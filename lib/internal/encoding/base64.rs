@@ -8,7 +8,7 @@ const CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ\
 
 /// Encodes a byte slice into a base64 string (with padding).
 pub fn encode(inp: &[u8]) -> String {
-    let end_len = (inp.len() + 2) / 3 * 4;
+    let end_len = inp.len().div_ceil(3) * 4;
     let mut out = String::with_capacity(end_len);
 
     for i in (0..inp.len()).step_by(3) {
@@ -1,20 +1,26 @@
 use std::{
     cell::RefCell,
-    io::Read,
-    net::{Shutdown, TcpStream},
+    io::{self, BufRead, Read},
+    net::Shutdown,
     ops::Deref,
     panic,
     rc::Rc,
     sync::{Arc, Mutex},
+    time::Instant,
 };
 
 use crate::{
     error::{HandleError, ParseError, Result, StreamError},
-    internal::common::any_string,
-    middleware::MiddleResult,
+    header::HeaderType,
+    internal::{
+        common::{any_string, ForceLock},
+        proxy::ProxyInfo,
+        socket::{SharedSocket, Socket, SocketReader},
+    },
+    middleware::{MiddleResult, Middleware},
     response::ResponseFlag,
-    route::RouteType,
-    trace, Content, Error, Request, Response, Server, Status,
+    route::{Route, RouteType},
+    trace, Content, Error, Header, HttpVersion, Request, Response, Server, Status,
 };
 
 pub(crate) type Writeable = Box<RefCell<dyn Read + Send>>;
@@ -22,24 +28,110 @@ pub(crate) type Writeable = Box<RefCell<dyn Read + Send>>;
 // https://open.spotify.com/track/50txng2W8C9SycOXKIQP0D
 
 /// - Manages keep-alive sockets
+/// - Keeps a read buffer for the connection, so pipelined requests are not lost
 /// - Lets Request::from_socket read the request
+/// - Drains the rest of streaming request bodies
 /// - Lets Response::write write the response to the socket
 /// - Runs End Middleware
 /// - Optionally closes the socket
-pub(crate) fn handle<State>(stream: TcpStream, this: &Server<State>)
+pub(crate) fn handle<State>(stream: Socket, this: &Server<State>, proxy_protocol: bool)
 where
     State: 'static + Send + Sync,
 {
     trace!(Level::Debug, "Opening socket {:?}", stream.peer_addr());
     stream.set_read_timeout(this.socket_timeout).unwrap();
     stream.set_write_timeout(this.socket_timeout).unwrap();
+    let connection = this.shutdown.track(&stream);
     let stream = Arc::new(Mutex::new(stream));
+    let reader = SharedSocket::new(stream.clone(), this.socket_timeout);
+    let reader = Arc::new(Mutex::new(SocketReader::new(reader)));
+
+    // Connections from a proxy start with a PROXY protocol header, which has to be read before the first request.
+    // Reading it counts towards the header timeout.
+    let proxy = match proxy_protocol {
+        true => match read_proxy_header(&reader, this) {
+            Ok(i) => Some(i),
+            Err(e) => {
+                trace!(Level::Debug, "Invalid PROXY protocol header: {}", e);
+                let _ = stream.force_lock().shutdown(Shutdown::Both);
+                return;
+            }
+        },
+        false => None,
+    };
+
+    let started = Instant::now();
+    let mut requests = 0;
+    let mut first = true;
     loop {
+        // Mark the connection as idle while waiting for the next request on a keep-alive connection.
+        // If the server is shutting down, the connection is closed instead.
+        // Connections with pipelined requests already buffered are not idle.
+        let buffered = !reader.force_lock().buffer().is_empty();
+        if let (Some(connection), false, false) = (&connection, first, buffered) {
+            if !connection.set_idle(true) {
+                trace!(Level::Debug, "Closing idle socket for shutdown");
+                break;
+            }
+        }
+
+        // Wait for the next request, closing the socket if the client closes the connection first or it is idle for too long.
+        // The header timeout starts when the connection is accepted, or on keep-alive connections, when the next request starts.
+        let mut socket_reader = reader.force_lock();
+        let wait = if first {
+            this.header_timeout
+        } else {
+            this.idle_timeout
+        };
+        socket_reader.get_mut().set_deadline(wait);
+        match socket_reader.fill_buf().map(|x| x.is_empty()) {
+            Ok(false) => {}
+            // A client that doesn't start its first request in time gets a 408, idle keep-alive connections are closed quietly
+            Err(e) if first && StreamError::from_read(&e) == StreamError::Timeout => {
+                drop(socket_reader);
+                trace!(Level::Debug, "Header timeout before the first request");
+                let mut res = error_response(&StreamError::Timeout.into(), this);
+                let version = HttpVersion::Http11;
+                if let Err(e) = res.write_for(stream.clone(), &this.default_headers, version, false)
+                {
+                    trace!(Level::Debug, "Error writing to socket: {:?}", e);
+                }
+                let _ = stream.force_lock().shutdown(Shutdown::Both);
+                break;
+            }
+            _ => {
+                trace!(Level::Debug, "Socket closed by client or idle timeout");
+                break;
+            }
+        }
+
+        if !first {
+            socket_reader.get_mut().set_deadline(this.header_timeout);
+        }
+
+        drop(socket_reader);
+        first = false;
+        requests += 1;
+
         let mut keep_alive = false;
-        let req = Request::from_socket(stream.clone());
+        let mut version = HttpVersion::Http11;
+        let mut req = Request::from_socket(&reader, this);
+        if let (Ok(req), Some(proxy)) = (&mut req, &proxy) {
+            proxy.apply(req);
+        }
+
+        if let Some(connection) = &connection {
+            connection.set_idle(false);
+        }
+
+        if req.is_err() && this.shutdown.is_shutdown() {
+            trace!(Level::Debug, "Socket closed for shutdown");
+            break;
+        }
 
         if let Ok(req) = &req {
             keep_alive = req.keep_alive();
+            version = req.version;
             trace!(
                 Level::Debug,
                 "{} {} {{ keep_alive: {} }}",
@@ -49,27 +141,68 @@ where
             );
         }
 
-        let (req, mut res) = get_response(req, this);
+        let Handled {
+            req,
+            mut res,
+            scoped,
+            run_end,
+        } = get_response(req, this);
+
+        // Let the client know the connection will be closed if the server is shutting down
+        if res.flag == ResponseFlag::None && this.shutdown.is_shutdown() {
+            res.flag = ResponseFlag::Close;
+        }
 
         if res.flag == ResponseFlag::End {
             trace!(Level::Debug, "Ending socket");
             break;
         }
 
-        if let Err(e) = res.write(stream.clone(), &this.default_headers) {
+        // Work out if the connection will be kept open, so the response can let the client know.
+        // If the client is still waiting for a `100 Continue` its body was refused, so the connection is closed.
+        // The same goes for requests lost to an error reading their body, as it is unknown how much of it is left on the connection.
+        // Streaming bodies sent without chunked encoding are ended by closing the connection.
+        // Connections that reached the max number of requests or age are closed too.
+        keep_alive &= this.keep_alive
+            && res.flag != ResponseFlag::Close
+            && req.as_ref().is_some_and(|x| !x.continue_pending)
+            && (res.data.is_static() || version.supports_chunked())
+            && this.max_requests.map_or(true, |x| requests < x)
+            && this
+                .max_connection_age
+                .map_or(true, |x| started.elapsed() < x);
+
+        if keep_alive {
+            add_keep_alive(&mut res, this, requests);
+        }
+
+        if let Err(e) = res.write_for(stream.clone(), &this.default_headers, version, keep_alive) {
             trace!(Level::Debug, "Error writing to socket: {:?}", e);
         }
 
-        // End Middleware
-        if let Some(req) = req {
-            for i in this.middleware.iter().rev() {
-                if let Err(e) = panic::catch_unwind(panic::AssertUnwindSafe(|| i.end(&req, &res))) {
+        // End Middleware, starting with the route's, in the reverse order of its pre hooks
+        if let (Some(req), true) = (&req, run_end) {
+            let middleware = scoped
+                .iter()
+                .rev()
+                .map(|x| &**x)
+                .chain(this.middleware.iter().rev().map(|x| &**x));
+            for i in middleware {
+                if let Err(e) = panic::catch_unwind(panic::AssertUnwindSafe(|| i.end(req, &res))) {
                     trace!(Level::Error, "Error running end middleware: {:?}", e);
                 }
             }
         }
 
-        if !keep_alive || res.flag == ResponseFlag::Close || !this.keep_alive {
+        // Discard the unread part of a streaming body, so the next request can be read
+        if let (Some(req), true) = (&req, keep_alive) {
+            if let Err(e) = req.drain_body() {
+                trace!(Level::Debug, "Error draining request body: {:?}", e);
+                keep_alive = false;
+            }
+        }
+
+        if !keep_alive {
             trace!(Level::Debug, "Closing socket");
             if let Err(e) = stream.lock().unwrap().shutdown(Shutdown::Both) {
                 trace!(Level::Debug, "Error closing socket: {:?}", e);
@@ -79,101 +212,226 @@ where
     }
 }
 
+/// Reads the PROXY protocol header from the start of a connection, within the servers header timeout.
+fn read_proxy_header<State>(
+    reader: &Arc<Mutex<SocketReader>>,
+    this: &Server<State>,
+) -> io::Result<ProxyInfo>
+where
+    State: 'static + Send + Sync,
+{
+    let mut reader = reader.force_lock();
+    reader.get_mut().set_deadline(this.header_timeout);
+    let peer = reader.get_ref().socket.force_lock().peer_addr()?;
+    ProxyInfo::read(&mut *reader, peer)
+}
+
+/// Adds a `Keep-Alive` header to the response, letting the client know how long the connection can be idle and how many more requests it can be used for.
+/// Not added if neither is limited or the response already has one.
+fn add_keep_alive<State>(res: &mut Response, this: &Server<State>, requests: usize)
+where
+    State: 'static + Send + Sync,
+{
+    if res.headers.has(HeaderType::KeepAlive) {
+        return;
+    }
+
+    // The timeout is in whole seconds, rounded up so sub-second timeouts aren't advertised as 0
+    let mut params = Vec::new();
+    if let Some(timeout) = this.idle_timeout {
+        let secs = timeout.as_secs() + u64::from(timeout.subsec_nanos() > 0);
+        params.push(format!("timeout={}", secs.max(1)));
+    }
+
+    if let Some(max) = this.max_requests {
+        params.push(format!("max={}", max - requests));
+    }
+
+    if !params.is_empty() {
+        res.headers
+            .push(Header::new(HeaderType::KeepAlive, params.join(", ")));
+    }
+}
+
+/// Middleware that only runs for a route, from [`crate::Route::middleware`].
+type ScopedMiddleware = [Arc<dyn Middleware + Send + Sync>];
+
+/// The result of [`get_response`].
+struct Handled<'a> {
+    /// The request, if it could be read.
+    /// Also returned with error responses, as the state of its body decides if the connection can be used for another request.
+    req: Option<Rc<Request>>,
+    /// The response to send.
+    res: Response,
+    /// The middleware scoped to the route that handled the request, so its end hooks can be run.
+    /// Empty if its pre hooks were skipped.
+    scoped: &'a ScopedMiddleware,
+    /// If the end hooks of the middleware should run, which they don't for error responses from the route handler.
+    run_end: bool,
+}
+
 /// Gets the response from a request.
 /// Will call middleware, route handlers and error handlers if needed.
-fn get_response<State>(
-    mut req: Result<Request>,
-    server: &Server<State>,
-) -> (Option<Rc<Request>>, Response)
+fn get_response<State>(mut req: Result<Request>, server: &Server<State>) -> Handled<'_>
 where
     State: 'static + Send + Sync,
 {
     let mut res = Err(Error::None);
     let handle_error = |error, req: Result<_>, server| {
         let err = HandleError::Panic(Box::new(req.clone()), any_string(error).into_owned()).into();
-        (req.ok(), error_response(&err, server))
+        Handled {
+            req: req.ok(),
+            res: error_response(&err, server),
+            scoped: &[],
+            run_end: true,
+        }
     };
 
     // Pre Middleware
+    let mut aborted = false;
     for i in server.middleware.iter().rev() {
         match panic::catch_unwind(panic::AssertUnwindSafe(|| i.pre_raw(&mut req))) {
             Ok(MiddleResult::Send(this_res)) => {
                 res = Ok(this_res);
                 break;
             }
-            Ok(MiddleResult::Abort) => break,
+            Ok(MiddleResult::Abort) => {
+                aborted = true;
+                break;
+            }
             Ok(MiddleResult::Continue) => {}
             Err(e) => return handle_error(e, req.map(Rc::new), server),
         }
     }
 
+    // Find the route, after the middleware had its chance to change the request
+    let route = match (&res, &mut req) {
+        (Err(Error::None), Ok(inner)) => {
+            server
+                .match_route(inner.method, &inner.path)
+                .map(|(route, params)| {
+                    *inner.path_params.borrow_mut() = params;
+                    inner.route_name = route.name.clone();
+                    route
+                })
+        }
+        _ => None,
+    };
+    // If the servers middleware aborted, the route's middleware is skipped entirely
+    let scoped = match aborted {
+        false => route.map_or(&[][..], |x| &x.middleware[..]),
+        true => &[],
+    };
+
+    // Pre Middleware of the route, starting with the outermost group's
+    if res.is_err() {
+        for i in scoped {
+            match panic::catch_unwind(panic::AssertUnwindSafe(|| i.pre_raw(&mut req))) {
+                Ok(MiddleResult::Send(this_res)) => {
+                    res = Ok(this_res);
+                    break;
+                }
+                Ok(MiddleResult::Abort) => break,
+                Ok(MiddleResult::Continue) => {}
+                Err(e) => return handle_error(e, req.map(Rc::new), server),
+            }
+        }
+    }
+
+    // Let clients waiting to send the body know to continue, unless middleware already sent a response or no route will handle the request.
+    // Body errors (like exceeding the size limit) are treated like errors parsing the request.
+    if let (Err(Error::None), Ok(inner)) = (&res, &mut req) {
+        if inner.continue_pending && route.is_some() {
+            if let Err(e) = inner.continue_body(server.body_timeout) {
+                req = Err(e);
+            }
+        }
+    }
+
     let req = req.map(Rc::new);
     if res.is_err() {
         if let Ok(req) = req.clone() {
-            res = handle_route(req, server);
+            res = handle_route(req, route, server);
         }
     }
 
-    // Post Middleware
-    for i in server.middleware.iter().rev() {
+    // Post Middleware, starting with the route's, then the groups from the innermost out
+    let middleware = scoped
+        .iter()
+        .rev()
+        .map(|x| &**x)
+        .chain(server.middleware.iter().rev().map(|x| &**x));
+    for i in middleware {
         match panic::catch_unwind(panic::AssertUnwindSafe(|| {
             i.post_raw(req.clone(), &mut res)
         })) {
-            Ok(MiddleResult::Send(res)) => return (req.ok(), res),
+            Ok(MiddleResult::Send(res)) => {
+                return Handled {
+                    req: req.ok(),
+                    res,
+                    scoped,
+                    run_end: true,
+                }
+            }
             Ok(MiddleResult::Abort) => break,
             Ok(MiddleResult::Continue) => {}
             Err(e) => return handle_error(e, req, server),
         }
     }
 
-    let res = match res {
-        Ok(res) => res,
+    let (res, run_end) = match res {
+        Ok(res) => (res, true),
         Err(e) => {
             let error = match req {
                 Err(ref err) => err,
                 Ok(_) => &e,
             };
 
-            return (None, error_response(error, server));
+            (error_response(error, server), false)
         }
     };
 
-    (req.ok(), res)
+    Handled {
+        req: req.ok(),
+        res,
+        scoped,
+        run_end,
+    }
 }
 
-/// Tries to find a route that matches the request.
-/// If it finds one, it will call the handler and return the result (assuming it doesn't panic).
-/// If it doesn't find one, it will return an Error of HandleError::NotFound.
-fn handle_route<State>(req: Rc<Request>, this: &Server<State>) -> Result<Response>
+/// Calls the handler of the route found for the request and returns the result (assuming it doesn't panic).
+/// If no route was found, it will return an Error of HandleError::NotFound.
+fn handle_route<State>(
+    req: Rc<Request>,
+    route: Option<&Route<State>>,
+    this: &Server<State>,
+) -> Result<Response>
 where
     State: 'static + Send + Sync,
 {
-    // Handle Route
-    let path = req.path.to_owned();
-    for route in this.routes.iter().rev() {
-        if let Some(params) = route.matches(req.clone()) {
-            *req.path_params.borrow_mut() = params;
-            let result = panic::catch_unwind(panic::AssertUnwindSafe(|| match &route.handler {
-                RouteType::Stateless(i) => (i)(&req),
-                RouteType::Stateful(i) => {
-                    (i)(this.state.clone().expect("State not initialized"), &req)
-                }
-            }));
+    let route = match route {
+        Some(i) => i,
+        None => {
+            return Err(Error::Handle(Box::new(HandleError::NotFound(
+                req.method,
+                req.path.to_owned(),
+            ))))
+        }
+    };
 
-            let err = match result {
-                Ok(i) => return Ok(i),
-                Err(e) => any_string(e),
-            };
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| match &route.handler {
+        RouteType::Stateless(i) => (i)(&req),
+        RouteType::Stateful(i) => (i)(this.state.clone().expect("State not initialized"), &req),
+    }));
 
-            return Err(Error::Handle(Box::new(HandleError::Panic(
-                Box::new(Ok(req)),
-                err.into_owned(),
-            ))));
-        }
-    }
+    let err = match result {
+        Ok(i) => return Ok(i),
+        Err(e) => any_string(e),
+    };
 
-    Err(Error::Handle(Box::new(HandleError::NotFound(
-        req.method, path,
+    Err(Error::Handle(Box::new(HandleError::Panic(
+        Box::new(Ok(req)),
+        err.into_owned(),
     ))))
 }
 
@@ -189,17 +447,42 @@ where
         }
         Error::Stream(e) => match e {
             StreamError::UnexpectedEof => Response::new().status(400).text("Unexpected EOF"),
+            StreamError::Timeout => Response::new()
+                .status(Status::RequestTimeOut)
+                .text("Request timeout"),
         },
-        Error::Parse(e) => Response::new().status(400).text(match e {
-            ParseError::NoSeparator => "No separator",
-            ParseError::NoMethod => "No method",
-            ParseError::NoPath => "No path",
-            ParseError::NoVersion => "No HTTP version",
-            ParseError::NoRequestLine => "No request line",
-            ParseError::InvalidQuery => "Invalid query",
-            ParseError::InvalidHeader => "Invalid header",
-            ParseError::InvalidMethod => "Invalid method",
-        }),
+        Error::Parse(e) => {
+            let status = match e {
+                ParseError::RequestLineTooLong => Status::URITooLarge,
+                ParseError::TooManyHeaders | ParseError::HeaderTooLarge => {
+                    Status::RequestHeaderFieldsTooLarge
+                }
+                ParseError::BodyTooLarge => Status::PayloadTooLarge,
+                ParseError::UnsupportedVersion => Status::HTTPVersionNotSupported,
+                _ => Status::BadRequest,
+            };
+
+            Response::new().status(status).text(match e {
+                ParseError::NoSeparator => "No separator",
+                ParseError::NoMethod => "No method",
+                ParseError::NoPath => "No path",
+                ParseError::NoVersion => "No HTTP version",
+                ParseError::NoRequestLine => "No request line",
+                ParseError::InvalidQuery => "Invalid query",
+                ParseError::InvalidHeader => "Invalid header",
+                ParseError::InvalidMethod => "Invalid method",
+                ParseError::InvalidVersion => "Invalid HTTP version",
+                ParseError::UnsupportedVersion => "Unsupported HTTP version",
+                ParseError::ConflictingLength => "Conflicting Content-Length and Transfer-Encoding",
+                ParseError::InvalidContentLength => "Invalid Content-Length",
+                ParseError::InvalidTransferEncoding => "Unsupported Transfer-Encoding",
+                ParseError::InvalidChunk => "Invalid chunk",
+                ParseError::RequestLineTooLong => "Request line too long",
+                ParseError::TooManyHeaders => "Too many headers",
+                ParseError::HeaderTooLarge => "Header too large",
+                ParseError::BodyTooLarge => "Body too large",
+            })
+        }
         Error::Handle(e) => match e.deref() {
             HandleError::NotFound(method, path) => Response::new()
                 .status(Status::NotFound)
@@ -212,3 +495,224 @@ where
         Error::Io(e) => Response::new().status(500).text(e),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{Read, Write},
+        net::TcpStream,
+        time::Duration,
+    };
+
+    use crate::{
+        internal::test_util::{read, read_lossy, responses, send},
+        middleware::{MiddleResult, Middleware},
+        Method, Request, Response, Server,
+    };
+
+    #[test]
+    fn test_pipelining() {
+        let mut server = Server::<()>::new("localhost", 0);
+        server.route(Method::POST, "/{name}", |req| {
+            Response::new().text(format!("{}={}", req.param("name").unwrap(), req.body_str()))
+        });
+
+        let server = server.start_background(1).unwrap();
+        let response = send(
+            server.addr(),
+            b"POST /a HTTP/1.1\r\nConnection: keep-alive\r\nContent-Length: 1\r\n\r\n1\
+              POST /b HTTP/1.1\r\nConnection: keep-alive\r\nContent-Length: 2\r\n\r\n22\
+              POST /c HTTP/1.1\r\nConnection: close\r\nContent-Length: 3\r\n\r\n333",
+        );
+        let bodies = responses(&response)
+            .iter()
+            .map(|x| x.rsplit("\r\n\r\n").next().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(bodies, ["a=1", "b=22", "c=333"]);
+
+        server.stop().unwrap();
+    }
+
+    #[test]
+    fn test_expect_continue_not_found() {
+        let mut server = Server::<()>::new("localhost", 0);
+        server.route(Method::GET, "/secret", |_req| {
+            Response::new().text("SMUGGLED")
+        });
+
+        let server = server.start_background(1).unwrap();
+        let mut stream = TcpStream::connect(server.addr()).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let body = "GET /secret HTTP/1.1\r\nConnection: close\r\n\r\n";
+        let request = format!(
+            "POST /missing HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: {}\r\n\r\n",
+            body.len()
+        );
+        stream.write_all(request.as_bytes()).unwrap();
+
+        // No route matches, so the body is refused without a `100 Continue`
+        let mut response = Vec::new();
+        let mut buf = [0; 1024];
+        while !String::from_utf8_lossy(&response).ends_with("Cannot POST /missing") {
+            let read = stream.read(&mut buf).unwrap();
+            assert_ne!(read, 0);
+            response.extend_from_slice(&buf[..read]);
+        }
+        let response = String::from_utf8(response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 404 Not Found"));
+        assert!(response.contains("Connection: close"));
+
+        // A client that sends the body anyway must not have it handled as another request
+        let _ = stream.write_all(body.as_bytes());
+        assert!(!read_lossy(&mut stream).contains("SMUGGLED"));
+
+        server.stop().unwrap();
+    }
+
+    #[test]
+    fn test_expect_continue() {
+        struct Auth;
+        impl Middleware for Auth {
+            fn pre(&self, req: &mut Request) -> MiddleResult {
+                match req.headers.has("Authorization") {
+                    true => MiddleResult::Continue,
+                    false => MiddleResult::Send(Response::new().status(401)),
+                }
+            }
+        }
+
+        let mut server = Server::<()>::new("localhost", 0).max_body_size(16);
+        server.route(Method::POST, "/", |req| {
+            Response::new().text(req.body_str())
+        });
+        Auth.attach(&mut server);
+
+        let server = server.start_background(1).unwrap();
+        let head = |headers: &str| {
+            let mut stream = TcpStream::connect(server.addr()).unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let request = format!("POST / HTTP/1.1\r\nExpect: 100-continue\r\n{headers}\r\n");
+            stream.write_all(request.as_bytes()).unwrap();
+            stream
+        };
+
+        // The client is told to continue before it sends the body
+        let mut stream = head("Authorization: 1234\r\nContent-Length: 5\r\nConnection: close\r\n");
+        let mut interim = [0; 25];
+        stream.read_exact(&mut interim).unwrap();
+        assert_eq!(&interim, b"HTTP/1.1 100 Continue\r\n\r\n");
+        stream.write_all(b"hello").unwrap();
+        let response = read(&mut stream);
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("hello"));
+
+        // Middleware can refuse the body before it is sent
+        let mut stream = head("Content-Length: 5\r\nConnection: keep-alive\r\n");
+        let response = read(&mut stream);
+        assert!(response.starts_with("HTTP/1.1 401 Unauthorized"));
+
+        // So can the body size limit
+        let mut stream = head("Authorization: 1234\r\nContent-Length: 17\r\n");
+        let response = read(&mut stream);
+        assert!(response.starts_with("HTTP/1.1 413"));
+
+        server.stop().unwrap();
+    }
+
+    #[test]
+    fn test_http_versions() {
+        let mut server = Server::<()>::new("localhost", 0);
+        server.route(Method::GET, "/", |req| Response::new().text(req.version));
+        server.route(Method::GET, "/stream", |_req| {
+            Response::new().stream(&b"streamed"[..])
+        });
+
+        let server = server.start_background(1).unwrap();
+        let addr = server.addr();
+
+        // HTTP/1.0 connections are closed by default
+        let response = send(addr, "GET / HTTP/1.0\r\n\r\n");
+        assert!(response.contains("Connection: close\r\n"));
+        assert!(response.ends_with("HTTP/1.0"));
+
+        // HTTP/1.0 connections stay open if the client sends `Connection: keep-alive`.
+        // HTTP/1.1 connections stay open by default, until the client sends a `close` token.
+        let response = send(
+            addr,
+            "GET / HTTP/1.0\r\nConnection: Keep-Alive\r\n\r\n\
+             GET / HTTP/1.1\r\n\r\n\
+             GET / HTTP/1.1\r\nConnection: TE, close\r\n\r\n",
+        );
+        let responses = responses(&response);
+        assert_eq!(responses.len(), 3);
+        assert!(responses[0].contains("Connection: keep-alive\r\n"));
+        assert!(!responses[1].contains("Connection:"));
+        assert!(responses[2].contains("Connection: close\r\n"));
+
+        // HTTP/1.0 clients don't get chunked responses, the connection is closed instead
+        let response = send(
+            addr,
+            "GET /stream HTTP/1.0\r\nConnection: keep-alive\r\n\r\n",
+        );
+        assert!(!response.contains("Transfer-Encoding"));
+        assert!(response.contains("Connection: close\r\n"));
+        assert!(response.ends_with("\r\n\r\nstreamed"));
+
+        // Only HTTP/1.0 and HTTP/1.1 are supported, other versions get a 505
+        let response = send(addr, "GET / HTTP/2.0\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 505"));
+
+        server.stop().unwrap();
+    }
+
+    #[test]
+    fn test_keep_alive_limits() {
+        let mut server = Server::<()>::new("localhost", 0)
+            .idle_timeout(Duration::from_secs(1))
+            .header_timeout(Duration::from_millis(200))
+            .max_requests(2);
+        server.route(Method::GET, "/", |_req| Response::new().text("Hello"));
+
+        let server = server.start_background(1).unwrap();
+        let addr = server.addr();
+
+        // The connection is closed after the max number of requests
+        let response = send(
+            addr,
+            "GET / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\n",
+        );
+        let responses = responses(&response);
+        assert_eq!(responses.len(), 2);
+        assert!(responses[0].contains("Keep-Alive: timeout=1, max=1\r\n"));
+        assert!(responses[1].contains("Connection: close\r\n"));
+        assert!(!responses[1].contains("Keep-Alive:"));
+
+        // Idle connections are closed after the idle timeout
+        let response = send(addr, "GET / HTTP/1.1\r\n\r\n");
+        assert_eq!(response.matches("HTTP/1.1 200 OK").count(), 1);
+
+        // Clients that are too slow sending the headers get a 408, even if they haven't sent anything yet
+        let response = send(addr, "GET / HTTP/1.1\r\nHost: localhost\r\n");
+        assert!(response.starts_with("HTTP/1.1 408"));
+        let response = send(addr, "");
+        assert!(response.starts_with("HTTP/1.1 408"));
+
+        server.stop().unwrap();
+
+        // Sub-second idle timeouts are rounded up to a whole second
+        let mut server = Server::<()>::new("localhost", 0).idle_timeout(Duration::from_millis(500));
+        server.route(Method::GET, "/", |_req| Response::new().text("Hello"));
+        let server = server.start_background(1).unwrap();
+        let response = send(
+            server.addr(),
+            "GET / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\nConnection: close\r\n\r\n",
+        );
+        assert!(response.contains("Keep-Alive: timeout=1\r\n"));
+
+        server.stop().unwrap();
+    }
+}
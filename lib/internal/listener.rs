@@ -0,0 +1,541 @@
+//! Listeners that accept incoming connections.
+
+#[cfg(target_os = "linux")]
+use std::os::fd::OwnedFd;
+#[cfg(unix)]
+use std::{
+    env,
+    fs::{self, Permissions},
+    ops::Range,
+    os::{
+        raw::c_int,
+        unix::{
+            fs::{FileTypeExt, PermissionsExt},
+            io::{FromRawFd, IntoRawFd, RawFd},
+            net::{UnixListener, UnixStream},
+        },
+    },
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use std::{
+    fmt::{self, Display},
+    io,
+    net::{SocketAddr, TcpListener, TcpStream},
+    time::Duration,
+};
+
+#[cfg(feature = "tls")]
+use crate::tls::TlsConfig;
+use crate::{internal::socket::Socket, trace, trace::emoji};
+
+#[cfg(unix)]
+const F_SETFD: c_int = 2;
+#[cfg(unix)]
+const FD_CLOEXEC: c_int = 1;
+
+#[cfg(unix)]
+extern "C" {
+    fn fcntl(fd: c_int, cmd: c_int, ...) -> c_int;
+}
+
+/// Set once the listening sockets passed to the process have been taken, so they can't end up owned twice.
+#[cfg(unix)]
+static FDS_TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// A bound listener.
+pub(crate) struct Listener {
+    socket: ListenSocket,
+    /// If connections start with a PROXY protocol header, see [`crate::Server::proxy_protocol`].
+    pub(crate) proxy_protocol: bool,
+}
+
+/// The listening socket of a [`Listener`].
+enum ListenSocket {
+    /// A TCP listener.
+    Tcp(TcpListener),
+    /// A TCP listener, where all connections are wrapped in TLS.
+    #[cfg(feature = "tls")]
+    Tls(TcpListener, TlsConfig),
+    /// A Unix domain socket listener.
+    /// If it was bound by afire, it contains the path of the socket file, which is removed when the listener is dropped.
+    /// Inherited listeners never remove their socket file.
+    #[cfg(unix)]
+    Unix(UnixListener, Option<PathBuf>),
+}
+
+/// A listening socket that was bound outside of afire, like by systemd or a parent process.
+pub(crate) enum Inherited {
+    /// A TCP listener.
+    Tcp(TcpListener),
+    /// A Unix domain socket listener.
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+/// The local address of a [`Listener`].
+#[derive(Debug, Clone)]
+pub(crate) enum ListenAddr {
+    Tcp(SocketAddr),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl Listener {
+    fn new(socket: ListenSocket) -> Self {
+        Self {
+            socket,
+            proxy_protocol: false,
+        }
+    }
+
+    /// Wraps an already bound TCP listener.
+    pub(crate) fn tcp(listener: TcpListener) -> Self {
+        Self::new(ListenSocket::Tcp(listener))
+    }
+
+    /// Wraps an already bound TCP listener, so that all its connections are wrapped in TLS.
+    /// Loads the certificate if it has not been loaded yet.
+    #[cfg(feature = "tls")]
+    pub(crate) fn tls(listener: TcpListener, config: TlsConfig) -> io::Result<Self> {
+        if !config.is_loaded() {
+            config.reload()?;
+        }
+
+        Ok(Self::new(ListenSocket::Tls(listener, config)))
+    }
+
+    /// Wraps an inherited Unix domain socket listener, whose socket file is never removed.
+    #[cfg(unix)]
+    pub(crate) fn unix(listener: UnixListener) -> Self {
+        Self::new(ListenSocket::Unix(listener, None))
+    }
+
+    /// Sets if connections start with a PROXY protocol header.
+    pub(crate) fn with_proxy_protocol(mut self, proxy_protocol: bool) -> Self {
+        self.proxy_protocol = proxy_protocol;
+        self
+    }
+
+    /// Binds a new Unix domain socket listener.
+    /// If a stale socket file from a previous run exists at `path` it is removed.
+    /// If `permissions` are supplied, they will be applied to the socket file.
+    #[cfg(unix)]
+    pub(crate) fn bind_unix(path: &Path, permissions: Option<u32>) -> io::Result<Self> {
+        remove_stale_socket(path)?;
+        let listener = UnixListener::bind(path)?;
+        let listener = Self::new(ListenSocket::Unix(listener, Some(path.to_path_buf())));
+
+        if let Some(mode) = permissions {
+            fs::set_permissions(path, Permissions::from_mode(mode))?;
+        }
+
+        Ok(listener)
+    }
+
+    /// Waits for a new connection.
+    pub(crate) fn accept(&self) -> io::Result<Socket> {
+        Ok(match &self.socket {
+            ListenSocket::Tcp(i) => Box::new(i.accept()?.0),
+            #[cfg(feature = "tls")]
+            ListenSocket::Tls(i, config) => config.accept(i.accept()?.0)?,
+            #[cfg(unix)]
+            ListenSocket::Unix(i, _) => Box::new(i.accept()?.0),
+        })
+    }
+
+    /// Creates a new handle to the listening socket, which can be passed to another process.
+    #[cfg(target_os = "linux")]
+    pub(crate) fn try_clone_fd(&self) -> io::Result<OwnedFd> {
+        Ok(match &self.socket {
+            ListenSocket::Tcp(i) => i.try_clone()?.into(),
+            #[cfg(feature = "tls")]
+            ListenSocket::Tls(i, _) => i.try_clone()?.into(),
+            ListenSocket::Unix(i, _) => i.try_clone()?.into(),
+        })
+    }
+
+    /// Keeps the socket file of the listener when it is dropped, as another process is now accepting connections on it.
+    pub(crate) fn keep_socket_file(&mut self) {
+        #[cfg(unix)]
+        if let ListenSocket::Unix(_, path) = &mut self.socket {
+            *path = None;
+        }
+    }
+
+    /// Gets the address the listener is bound to.
+    pub(crate) fn local_addr(&self) -> io::Result<ListenAddr> {
+        Ok(match &self.socket {
+            ListenSocket::Tcp(i) => ListenAddr::Tcp(i.local_addr()?),
+            #[cfg(feature = "tls")]
+            ListenSocket::Tls(i, _) => ListenAddr::Tcp(i.local_addr()?),
+            #[cfg(unix)]
+            ListenSocket::Unix(_, Some(path)) => ListenAddr::Unix(path.to_owned()),
+            #[cfg(unix)]
+            ListenSocket::Unix(i, None) => ListenAddr::Unix(
+                i.local_addr()?
+                    .as_pathname()
+                    .ok_or_else(|| {
+                        io::Error::new(io::ErrorKind::Unsupported, "Unnamed Unix socket")
+                    })?
+                    .to_path_buf(),
+            ),
+        })
+    }
+}
+
+#[cfg(unix)]
+impl Inherited {
+    /// Takes ownership of a listening socket from its raw file descriptor.
+    /// Works out if it is a TCP or Unix domain socket, by checking if it has an IP address.
+    ///
+    /// ## Safety
+    /// `fd` must be an open listening socket, which is not owned by anything else.
+    pub(crate) unsafe fn from_raw_fd(fd: RawFd) -> Self {
+        let listener = TcpListener::from_raw_fd(fd);
+        if listener.local_addr().is_ok() {
+            return Inherited::Tcp(listener);
+        }
+
+        Inherited::Unix(UnixListener::from_raw_fd(listener.into_raw_fd()))
+    }
+}
+
+/// Gets the file descriptors passed by systemd socket activation.
+/// Returns an empty range if `LISTEN_PID` is not the current process, or either variable is missing or invalid.
+/// See [sd_listen_fds(3)](https://www.freedesktop.org/software/systemd/man/latest/sd_listen_fds.html).
+#[cfg(unix)]
+pub(crate) fn listen_fds(pid: Option<&str>, fds: Option<&str>) -> Range<RawFd> {
+    const SD_LISTEN_FDS_START: RawFd = 3;
+
+    let valid = pid.and_then(|x| x.parse::<u32>().ok()) == Some(process::id());
+    let count = fds.and_then(|x| x.parse::<RawFd>().ok()).filter(|_| valid);
+    SD_LISTEN_FDS_START..SD_LISTEN_FDS_START + count.unwrap_or(0).max(0)
+}
+
+/// Takes ownership of the listening sockets passed to the process, with its pid in the `pid_var` environment variable and their count in `fds_var`.
+/// See [`listen_fds`], this is used for both systemd (`LISTEN_PID` / `LISTEN_FDS`) and hot restarts.
+///
+/// The sockets can only be taken once per process, after that this returns nothing.
+/// Like with `sd_listen_fds`, they are marked close-on-exec so they are not leaked to child processes.
+/// The environment variables are left in place, as changing them isn't sound once other threads may be running.
+/// Child processes still won't pick up the sockets, as the pid won't match theirs.
+#[cfg(unix)]
+pub(crate) fn take_listen_fds(pid_var: &str, fds_var: &str) -> Vec<Inherited> {
+    let pid = env::var(pid_var).ok();
+    let fds = env::var(fds_var).ok();
+    let fds = listen_fds(pid.as_deref(), fds.as_deref());
+    if fds.is_empty() || FDS_TAKEN.swap(true, Ordering::AcqRel) {
+        return Vec::new();
+    }
+
+    fds.map(|fd| unsafe {
+        fcntl(fd, F_SETFD, FD_CLOEXEC);
+        // SAFETY: the process that passed the sockets gave up ownership of them, and they are only taken once
+        Inherited::from_raw_fd(fd)
+    })
+    .collect()
+}
+
+#[cfg(unix)]
+impl Drop for Listener {
+    fn drop(&mut self) {
+        if let ListenSocket::Unix(_, Some(path)) = &self.socket {
+            trace!(Level::Debug, "Removing socket file {}", path.display());
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+impl ListenAddr {
+    /// Connects to the listener and immediately closes the connection.
+    /// Used to wake up a thread that is blocked accepting connections.
+    pub(crate) fn wake(&self) {
+        match self {
+            ListenAddr::Tcp(addr) => {
+                let mut addr = *addr;
+                if addr.ip().is_unspecified() {
+                    addr.set_ip(match addr {
+                        SocketAddr::V4(_) => [127, 0, 0, 1].into(),
+                        SocketAddr::V6(_) => [0, 0, 0, 0, 0, 0, 0, 1].into(),
+                    });
+                }
+
+                let _ = TcpStream::connect_timeout(&addr, Duration::from_secs(1));
+            }
+            #[cfg(unix)]
+            ListenAddr::Unix(path) => {
+                let _ = UnixStream::connect(path);
+            }
+        }
+    }
+}
+
+impl Display for ListenAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListenAddr::Tcp(i) => Display::fmt(i, f),
+            #[cfg(unix)]
+            ListenAddr::Unix(i) => write!(f, "unix:{}", i.display()),
+        }
+    }
+}
+
+/// Removes the socket file at `path` if no server is listening on it anymore.
+/// Returns an [`io::ErrorKind::AddrInUse`] error if another process is still accepting connections on it.
+/// Files that are not sockets are never removed.
+#[cfg(unix)]
+fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(i) => i,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    if !metadata.file_type().is_socket() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} exists and is not a socket", path.display()),
+        ));
+    }
+
+    if UnixStream::connect(path).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("{} is in use by another process", path.display()),
+        ));
+    }
+
+    trace!(
+        "{}Removing stale socket file {}",
+        emoji("🧹"),
+        path.display()
+    );
+    fs::remove_file(path)
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{TcpListener, TcpStream};
+    #[cfg(unix)]
+    use std::{
+        env, fs, io,
+        os::unix::{
+            io::IntoRawFd,
+            net::{UnixListener, UnixStream},
+        },
+        path::PathBuf,
+        process,
+    };
+
+    #[cfg(unix)]
+    use super::{listen_fds, Inherited, Listener};
+    use crate::{
+        internal::test_util::{get, request},
+        Method, Response, Server,
+    };
+
+    #[cfg(unix)]
+    fn socket_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("afire-{}-{name}.sock", process::id()))
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_remove_stale_socket() {
+        let path = socket_path("stale");
+        let _ = fs::remove_file(&path);
+
+        // Leave a socket file with nothing listening on it behind
+        drop(UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+
+        let listener = Listener::bind_unix(&path, None).unwrap();
+        drop(listener);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_socket_in_use() {
+        let path = socket_path("in-use");
+        let _ = fs::remove_file(&path);
+
+        let _listener = Listener::bind_unix(&path, None).unwrap();
+        let err = Listener::bind_unix(&path, None).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_not_a_socket() {
+        let path = socket_path("file");
+        fs::write(&path, "").unwrap();
+
+        let err = Listener::bind_unix(&path, None).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert!(path.exists());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_listen_fds() {
+        let pid = process::id().to_string();
+        assert_eq!(listen_fds(Some(&pid), Some("2")), 3..5);
+        assert!(listen_fds(Some(&pid), Some("0")).is_empty());
+        assert!(listen_fds(Some(&pid), Some("-1")).is_empty());
+        assert!(listen_fds(Some(&pid), None).is_empty());
+        assert!(listen_fds(Some("1"), Some("2")).is_empty());
+        assert!(listen_fds(None, Some("2")).is_empty());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_inherited_fd() {
+        let tcp = TcpListener::bind("127.0.0.1:0").unwrap();
+        let inherited = unsafe { Inherited::from_raw_fd(tcp.into_raw_fd()) };
+        assert!(matches!(inherited, Inherited::Tcp(_)));
+
+        let path = socket_path("inherited");
+        let _ = fs::remove_file(&path);
+        let unix = UnixListener::bind(&path).unwrap();
+        let inherited = unsafe { Inherited::from_raw_fd(unix.into_raw_fd()) };
+        assert!(matches!(inherited, Inherited::Unix(_)));
+
+        // Inherited listeners don't remove their socket file
+        let Inherited::Unix(unix) = inherited else {
+            unreachable!()
+        };
+        drop(Listener::unix(unix));
+        assert!(path.exists());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_unix_socket() {
+        let path = socket_path("server");
+        let mut server = Server::<()>::new("localhost", 0)
+            .unix_socket(&path)
+            .unix_socket_permissions(0o600);
+        server.route(Method::GET, "/", |req| {
+            Response::new().text(format!("unix: {}", req.address.is_unix()))
+        });
+
+        let server = server.start_background(1).unwrap();
+        assert_eq!(server.unix_path(), Some(path.as_path()));
+
+        let mut stream = UnixStream::connect(&path).unwrap();
+        assert!(request(&mut stream, "/").ends_with("unix: true"));
+
+        server.stop().unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_multiple_listeners() {
+        let mut server = Server::<()>::new("127.0.0.1", 0)
+            .listen("::1", 0)
+            .listen("localhost", 0);
+        server.route(Method::GET, "/", |_req| Response::new().text("Hello"));
+
+        let server = server.start_background(1).unwrap();
+        let addrs = server.addrs();
+        assert_eq!(addrs.len(), 3);
+        assert_eq!(addrs[0], server.addr());
+        assert!(addrs[1].is_ipv6());
+
+        for addr in addrs {
+            assert!(get(addr, "/").ends_with("Hello"));
+        }
+
+        server.stop().unwrap();
+    }
+
+    #[test]
+    fn test_dual_stack() {
+        let port = TcpListener::bind("[::]:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut server = Server::<()>::new("0.0.0.0", port).listen("::", port);
+        server.route(Method::GET, "/", |_req| Response::new().text("Hello"));
+
+        let server = server.start_background(1).unwrap();
+        for ip in ["127.0.0.1", "[::1]"] {
+            let mut stream = TcpStream::connect(format!("{ip}:{port}")).unwrap();
+            assert!(request(&mut stream, "/").ends_with("Hello"));
+        }
+
+        server.stop().unwrap();
+    }
+
+    #[test]
+    fn test_inherited_listener() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // The servers own address is not bound when it has an inherited listener
+        let mut server = Server::<()>::new("localhost", addr.port()).tcp_listener(listener);
+        server.route(Method::GET, "/", |_req| Response::new().text("Hello"));
+
+        let server = server.start_background(1).unwrap();
+        assert_eq!(server.addrs(), [addr]);
+
+        assert!(get(addr, "/").ends_with("Hello"));
+
+        server.stop().unwrap();
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_systemd() {
+        use std::{
+            os::{fd::AsRawFd, unix::process::CommandExt},
+            process::{Command, Stdio},
+        };
+
+        use crate::internal::restart;
+
+        // The socket activated process runs just this test again, serving on the listener passed to it
+        if env::var_os("LISTEN_FDS").is_some() {
+            let mut server = Server::<()>::new("localhost", 0).systemd();
+            assert_eq!(server.inherited.len(), 1);
+            assert!(Server::<()>::new("localhost", 0)
+                .systemd()
+                .inherited
+                .is_empty());
+
+            let shutdown = server.shutdown_handle();
+            server.route(Method::GET, "/", |_req| Response::new().text("activated"));
+            server.route(Method::GET, "/stop", move |_req| {
+                shutdown.shutdown();
+                Response::new().text("activated")
+            });
+            server.start_threaded(1).unwrap();
+            return;
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let fd = listener.as_raw_fd();
+
+        // Like systemd, the pid is only set once the process is forked, here by a shell that then executes the test
+        let mut command = Command::new("sh");
+        command
+            .args(["-c", r#"LISTEN_PID=$$ exec "$0" "$@""#])
+            .arg(env::current_exe().unwrap())
+            .args(["internal::listener::test::test_systemd", "--exact"])
+            .env("LISTEN_FDS", "1")
+            .stdout(Stdio::null());
+        unsafe { command.pre_exec(move || restart::pass_fds(&mut [fd])) };
+        let mut child = command.spawn().unwrap();
+
+        assert!(get(addr, "/").ends_with("activated"));
+        assert!(get(addr, "/stop").ends_with("activated"));
+        assert!(child.wait().unwrap().success());
+    }
+}
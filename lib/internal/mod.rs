@@ -3,7 +3,17 @@
 //! These may be helpful but may not remain compatible between updates.
 //! No guarantees are made about their stability, they are likely to be changed or removed.
 
+pub(crate) mod body;
 pub mod common;
 pub mod encoding;
 pub(crate) mod handle;
+pub(crate) mod listener;
 pub mod path;
+pub mod pattern;
+pub(crate) mod proxy;
+#[cfg(target_os = "linux")]
+pub(crate) mod restart;
+pub(crate) mod router;
+pub mod socket;
+#[cfg(test)]
+pub(crate) mod test_util;
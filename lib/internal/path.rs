@@ -1,6 +1,11 @@
 //! HTTP Path stuff
 
-use super::encoding::url;
+use std::{
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
+
+use super::{encoding::url, pattern::Pattern};
 
 /// Http Path
 #[derive(Debug, PartialEq, Eq)]
@@ -15,7 +20,7 @@ pub struct Path {
 /// Segment of a path
 ///
 /// Ex: `/hello/{name}` => [Normal::("hello"), Param::("name")]
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum PathPart {
     /// Normal Path Segment (/hi)
     Normal(String),
@@ -23,6 +28,20 @@ pub enum PathPart {
     /// Path param (/{name})
     Param(String),
 
+    /// Path param the segment has to satisfy a constraint for (/{id:u64})
+    Constrained(String, Constraint),
+
+    /// Path param that can be left out, optionally with a constraint (/{page?} or /{page?:u32}).
+    /// Only allowed at the end of a path.
+    Optional(String, Option<Constraint>),
+
+    /// Segment made up of text and params (/{name}.{ext})
+    Mixed(Vec<SegmentPart>),
+
+    /// Path param capturing the rest of the path, including `/` (/{*rest}).
+    /// Like [`PathPart::AnyAfter`], it has to match at least one segment and is only allowed at the end of a path.
+    CatchAll(String),
+
     /// Match anything for self and after
     AnyAfter,
 
@@ -30,17 +49,52 @@ pub enum PathPart {
     Any,
 }
 
+/// Piece of a [`PathPart::Mixed`] segment.
+///
+/// Ex: `{name}.{ext}` => [Param::("name"), Literal::("."), Param::("ext")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SegmentPart {
+    /// Text the segment has to contain
+    Literal(String),
+
+    /// Path param, with an optional constraint
+    Param(String, Option<Constraint>),
+}
+
 impl Path {
     /// Tokenize a new path
+    ///
+    /// Panics if the braces of a param are unbalanced, a param has an invalid constraint, or optional and catch-all params aren't at the end of the path.
     pub fn new(path: String) -> Path {
         let path = normalize_path(path);
         let mut out = Vec::new();
 
         // Split off into Path Parts
-        for i in path.split('/') {
+        for i in split_segments(&path) {
             out.push(PathPart::from_segment(i));
         }
 
+        for (i, part) in out.iter().enumerate() {
+            let rest = &out[i + 1..];
+            match part {
+                PathPart::CatchAll(_) if !rest.is_empty() => {
+                    panic!(
+                        "Catch-all params have to be at the end of the path: `{}`",
+                        path
+                    )
+                }
+                PathPart::Optional(..)
+                    if !rest.iter().all(|x| matches!(x, PathPart::Optional(..))) =>
+                {
+                    panic!(
+                        "Optional params have to be at the end of the path: `{}`",
+                        path
+                    )
+                }
+                _ => {}
+            }
+        }
+
         Path {
             raw: path,
             parts: out,
@@ -49,53 +103,470 @@ impl Path {
 
     /// Match Path, returns None if it doesn't match and the path params if it does
     pub fn match_path(&self, path: String) -> Option<Vec<(String, String)>> {
+        let path = normalize_path(path);
+        self.match_segments(&path.split('/').collect::<Vec<_>>())
+    }
+
+    /// Match the segments of an already normalized path.
+    /// Returns None if they don't match and the path params if they do.
+    pub(crate) fn match_segments(&self, mut segments: &[&str]) -> Option<Vec<(String, String)>> {
         if self.parts == [PathPart::AnyAfter] {
             return Some(Vec::new());
         }
 
-        let path = normalize_path(path);
+        // The root path is a single empty segment, which optional params treat as missing
+        if segments == [""] && matches!(self.parts.first(), Some(PathPart::Optional(..))) {
+            segments = &[];
+        }
+
         let mut out = Vec::new();
+        for (idx, i) in self.parts.iter().enumerate() {
+            let j = match segments.get(idx) {
+                Some(j) => j,
+                // Only optional params can come after this, as checked in `Path::new`
+                None if matches!(i, PathPart::Optional(..)) => break,
+                None => return None,
+            };
 
-        let path = path.split('/');
-        for (i, j) in self.parts.iter().zip(path.clone()) {
             match i {
                 PathPart::Normal(x) => {
                     if x != j {
                         return None;
                     }
                 }
-                PathPart::Param(x) => {
-                    out.push((x.to_owned(), url::decode(j).unwrap_or_else(|| j.to_owned())))
+                PathPart::Param(x) => out.push((x.to_owned(), decode(j))),
+                PathPart::Constrained(x, _) | PathPart::Optional(x, _) => {
+                    let value = decode(j);
+                    if !i.matches(&value) {
+                        return None;
+                    }
+                    out.push((x.to_owned(), value));
+                }
+                PathPart::Mixed(x) => out.extend(match_mixed(x, &decode(j))?),
+                PathPart::CatchAll(x) => {
+                    out.push((x.to_owned(), decode(&segments[idx..].join("/"))));
+                    return Some(out);
                 }
                 PathPart::AnyAfter => return Some(out),
                 PathPart::Any => {}
             }
         }
 
-        if path.count() != self.parts.len() {
+        if segments.len() > self.parts.len() {
             return None;
         }
 
         Some(out)
     }
+
+    /// Gets the params of the segments of a path the router already matched against this one, without checking it again.
+    /// `mixed` are the params of the [`PathPart::Mixed`] segments, in order, as found by the router.
+    pub(crate) fn params(
+        &self,
+        mut segments: &[&str],
+        mixed: Vec<(String, String)>,
+    ) -> Vec<(String, String)> {
+        if segments == [""] && matches!(self.parts.first(), Some(PathPart::Optional(..))) {
+            segments = &[];
+        }
+
+        let mut mixed = mixed.into_iter();
+        let mut out = Vec::new();
+        for (i, (part, segment)) in self.parts.iter().zip(segments).enumerate() {
+            match part {
+                PathPart::Param(x) | PathPart::Constrained(x, _) | PathPart::Optional(x, _) => {
+                    out.push((x.to_owned(), decode(segment)))
+                }
+                PathPart::Mixed(x) => {
+                    let params = x.iter().filter(|x| matches!(x, SegmentPart::Param(..)));
+                    out.extend(mixed.by_ref().take(params.count()));
+                }
+                PathPart::CatchAll(x) => {
+                    out.push((x.to_owned(), decode(&segments[i..].join("/"))));
+                    break;
+                }
+                PathPart::AnyAfter => break,
+                PathPart::Normal(_) | PathPart::Any => {}
+            }
+        }
+
+        out
+    }
 }
 
 impl PathPart {
     /// Decode Path Segment into PathPart
+    ///
+    /// Panics if the segment has unbalanced braces (like `{name`), a param with an invalid constraint (like `{slug:[a-z}` or `{id:int}`), or params that aren't separated by text (like `{a}{b}`).
     pub fn from_segment(seg: &str) -> PathPart {
         match seg {
-            "*" => PathPart::Any,
-            "**" => PathPart::AnyAfter,
-            x if x.starts_with('{') && x.ends_with('}') => PathPart::Param(
-                x.strip_prefix('{')
-                    .unwrap()
-                    .strip_suffix('}')
-                    .unwrap()
-                    .to_owned(),
-            ),
-            _ => PathPart::Normal(seg.to_owned()),
+            "*" => return PathPart::Any,
+            "**" => return PathPart::AnyAfter,
+            _ => {}
+        }
+
+        let pieces = split_params(seg);
+        match pieces.as_slice() {
+            [(true, param)] => PathPart::from_param(param),
+            x if x.iter().all(|(param, _)| !param) => PathPart::Normal(seg.to_owned()),
+            _ => {
+                if pieces.windows(2).any(|x| x[0].0 && x[1].0) {
+                    panic!("Params in `{}` have to be separated by text", seg);
+                }
+
+                let parts = pieces
+                    .iter()
+                    .map(|(param, x)| match param {
+                        false => SegmentPart::Literal((*x).to_owned()),
+                        true => match PathPart::from_param(x) {
+                            PathPart::Param(name) => SegmentPart::Param(name, None),
+                            PathPart::Constrained(name, i) => SegmentPart::Param(name, Some(i)),
+                            _ => panic!(
+                                "Optional and catch-all params have to be the whole segment: `{}`",
+                                seg
+                            ),
+                        },
+                    })
+                    .collect();
+                PathPart::Mixed(parts)
+            }
+        }
+    }
+
+    /// Decodes the inside of a `{...}` param.
+    fn from_param(param: &str) -> PathPart {
+        let (name, constraint) = match param.split_once(':') {
+            Some((name, constraint)) => {
+                let parsed = constraint.parse().unwrap_or_else(|_| {
+                    panic!(
+                        "Invalid constraint `{}` for path param `{}`",
+                        constraint, name
+                    )
+                });
+                (name, Some(parsed))
+            }
+            None => (param, None),
+        };
+
+        if let Some(name) = name.strip_prefix('*') {
+            if constraint.is_some() {
+                panic!("Catch-all param `{}` can't have a constraint", name);
+            }
+            return PathPart::CatchAll(name.to_owned());
+        }
+
+        if let Some(name) = name.strip_suffix('?') {
+            return PathPart::Optional(name.to_owned(), constraint);
+        }
+
+        match constraint {
+            Some(i) => PathPart::Constrained(name.to_owned(), i),
+            None => PathPart::Param(name.to_owned()),
+        }
+    }
+
+    /// Checks if a single (url decoded) segment satisfies the constraints of this part.
+    /// Only checks constraints and [`PathPart::Mixed`] segments, use [`Path::match_path`] to match a whole path.
+    pub fn matches(&self, value: &str) -> bool {
+        match self {
+            PathPart::Constrained(_, i) | PathPart::Optional(_, Some(i)) => i.matches(value),
+            PathPart::Mixed(i) => match_mixed(i, value).is_some(),
+            _ => true,
+        }
+    }
+}
+
+/// Longest value a param constrained to a type (not a pattern) can have in a [`PathPart::Mixed`] segment.
+/// Types can only be checked one value at a time, so without a limit trying every split of a long segment would take quadratic time.
+const MAX_TYPED_LEN: usize = 64;
+
+/// Matches a segment against the pieces of a [`PathPart::Mixed`], returning the params if it matches.
+/// Params take as much of the segment as they can, so `{name}.{ext}` splits `a.tar.gz` into `a.tar` and `gz`.
+/// If that leaves the rest of the segment unable to match, shorter values are used.
+///
+/// Params are never empty, unless they have a pattern that allows it.
+/// Each piece is checked against every position of the segment at once, so this takes linear time in the length of the segment.
+pub(crate) fn match_mixed(pieces: &[SegmentPart], value: &str) -> Option<Vec<(String, String)>> {
+    let len = value.len();
+
+    // `ok[i][j]` is true if the pieces from `i` on can match the segment from byte `j` on.
+    // Working this out for every position first avoids retrying the same splits over and over.
+    let mut ok = vec![vec![false; len + 1]; pieces.len() + 1];
+    ok[pieces.len()][len] = true;
+    for (i, piece) in pieces.iter().enumerate().rev() {
+        let next = &ok[i + 1];
+        ok[i] = match piece {
+            SegmentPart::Literal(x) => (0..=len)
+                .map(|j| {
+                    value
+                        .get(j..)
+                        .is_some_and(|rest| rest.starts_with(x.as_str()))
+                        && next[j + x.len()]
+                })
+                .collect(),
+            SegmentPart::Param(_, None) => {
+                // If the next piece can match from any position after `j`
+                let mut any_after = false;
+                let mut out = vec![false; len + 1];
+                for j in (0..=len).rev() {
+                    out[j] = any_after && value.is_char_boundary(j);
+                    any_after |= next[j];
+                }
+                out
+            }
+            SegmentPart::Param(_, Some(Constraint::Pattern(pattern))) => {
+                pattern.starts(value, next)
+            }
+            SegmentPart::Param(_, Some(constraint)) => (0..=len)
+                .map(|j| {
+                    value.is_char_boundary(j)
+                        && typed_ends(constraint, value, j, next).next().is_some()
+                })
+                .collect(),
+        };
+    }
+
+    if !ok[0][0] {
+        return None;
+    }
+
+    let mut out = Vec::new();
+    let mut start = 0;
+    for (i, piece) in pieces.iter().enumerate() {
+        let next = &ok[i + 1];
+        let end = match piece {
+            SegmentPart::Literal(x) => {
+                start += x.len();
+                continue;
+            }
+            SegmentPart::Param(_, None) => (start + 1..=len).rev().find(|&k| next[k]),
+            SegmentPart::Param(_, Some(Constraint::Pattern(pattern))) => {
+                let mut starts = vec![false; len + 1];
+                starts[start] = true;
+                let ends = pattern.ends(value, &starts);
+                (start..=len).rev().find(|&k| next[k] && ends[k])
+            }
+            SegmentPart::Param(_, Some(constraint)) => {
+                typed_ends(constraint, value, start, next).last()
+            }
+        }?;
+
+        if let SegmentPart::Param(name, _) = piece {
+            out.push((name.to_owned(), value[start..end].to_owned()));
         }
+        start = end;
     }
+
+    Some(out)
+}
+
+/// Finds where a param constrained to a type can end in a [`PathPart::Mixed`] segment, when it starts at `start`.
+/// Only ends the next piece can match from (set in `next`) are checked, up to [`MAX_TYPED_LEN`] bytes after the start.
+fn typed_ends<'a>(
+    constraint: &'a Constraint,
+    value: &'a str,
+    start: usize,
+    next: &'a [bool],
+) -> impl Iterator<Item = usize> + 'a {
+    (start + 1..=value.len().min(start + MAX_TYPED_LEN))
+        .filter(move |&k| next[k] && value.is_char_boundary(k))
+        .filter(move |&k| constraint.matches(&value[start..k]))
+}
+
+/// Constraint a path param has to satisfy for the route to match, set with `{name:constraint}`.
+/// If a segment doesn't satisfy it, the request falls through to other routes.
+///
+/// Constraints made up of only letters, digits and underscores are type names, any other constraint is a [`Pattern`].
+/// Unknown type names are rejected when the route is created.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Constraint {
+    /// `u8`, the value has to parse as one.
+    U8,
+    /// `u16`, the value has to parse as one.
+    U16,
+    /// `u32`, the value has to parse as one.
+    U32,
+    /// `u64`, the value has to parse as one.
+    U64,
+    /// `u128`, the value has to parse as one.
+    U128,
+    /// `usize`, the value has to parse as one.
+    Usize,
+    /// `i8`, the value has to parse as one.
+    I8,
+    /// `i16`, the value has to parse as one.
+    I16,
+    /// `i32`, the value has to parse as one.
+    I32,
+    /// `i64`, the value has to parse as one.
+    I64,
+    /// `i128`, the value has to parse as one.
+    I128,
+    /// `isize`, the value has to parse as one.
+    Isize,
+    /// `f32`, the value has to parse as one.
+    F32,
+    /// `f64`, the value has to parse as one.
+    F64,
+    /// `bool`, the value has to be `true` or `false`.
+    Bool,
+    /// `uuid`, the value has to be a UUID in its hyphenated form, like `67e55044-10b1-426f-9247-bb680e5fe0c8`.
+    Uuid,
+
+    /// The whole value has to match a [`Pattern`], like `[a-z-]+`.
+    Pattern(Pattern),
+}
+
+/// The type names of the [`Constraint`]s, other than patterns.
+const TYPES: &[(&str, Constraint)] = &[
+    ("u8", Constraint::U8),
+    ("u16", Constraint::U16),
+    ("u32", Constraint::U32),
+    ("u64", Constraint::U64),
+    ("u128", Constraint::U128),
+    ("usize", Constraint::Usize),
+    ("i8", Constraint::I8),
+    ("i16", Constraint::I16),
+    ("i32", Constraint::I32),
+    ("i64", Constraint::I64),
+    ("i128", Constraint::I128),
+    ("isize", Constraint::Isize),
+    ("f32", Constraint::F32),
+    ("f64", Constraint::F64),
+    ("bool", Constraint::Bool),
+    ("uuid", Constraint::Uuid),
+];
+
+impl Constraint {
+    /// Checks if a (url decoded) path param satisfies the constraint.
+    pub fn matches(&self, value: &str) -> bool {
+        fn parses<T: FromStr>(value: &str) -> bool {
+            value.parse::<T>().is_ok()
+        }
+
+        match self {
+            Constraint::U8 => parses::<u8>(value),
+            Constraint::U16 => parses::<u16>(value),
+            Constraint::U32 => parses::<u32>(value),
+            Constraint::U64 => parses::<u64>(value),
+            Constraint::U128 => parses::<u128>(value),
+            Constraint::Usize => parses::<usize>(value),
+            Constraint::I8 => parses::<i8>(value),
+            Constraint::I16 => parses::<i16>(value),
+            Constraint::I32 => parses::<i32>(value),
+            Constraint::I64 => parses::<i64>(value),
+            Constraint::I128 => parses::<i128>(value),
+            Constraint::Isize => parses::<isize>(value),
+            Constraint::F32 => parses::<f32>(value),
+            Constraint::F64 => parses::<f64>(value),
+            Constraint::Bool => parses::<bool>(value),
+            Constraint::Uuid => is_uuid(value),
+            Constraint::Pattern(pattern) => pattern.matches(value),
+        }
+    }
+}
+
+impl FromStr for Constraint {
+    type Err = ();
+
+    /// Parses a constraint, which is a type if it is only made up of letters, digits and underscores, and a [`Pattern`] otherwise.
+    /// Fails for unknown types and invalid patterns.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !s.is_empty() && s.chars().all(|x| x.is_ascii_alphanumeric() || x == '_') {
+            return (TYPES.iter().find(|(name, _)| *name == s))
+                .map(|(_, constraint)| constraint.to_owned())
+                .ok_or(());
+        }
+
+        Pattern::new(s).map(Constraint::Pattern).ok_or(())
+    }
+}
+
+impl Display for Constraint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Constraint::Pattern(pattern) => pattern.fmt(f),
+            _ => {
+                let (name, _) = TYPES.iter().find(|(_, x)| x == self).unwrap();
+                f.write_str(name)
+            }
+        }
+    }
+}
+
+/// Checks if a value is a UUID in its hyphenated form, like `67e55044-10b1-426f-9247-bb680e5fe0c8`.
+fn is_uuid(value: &str) -> bool {
+    let groups = value.split('-').map(str::len).collect::<Vec<_>>();
+    groups == [8, 4, 4, 4, 12] && value.chars().all(|x| x == '-' || x.is_ascii_hexdigit())
+}
+
+/// Url decodes a path segment, keeping it as is if it isn't valid.
+pub(crate) fn decode(segment: &str) -> String {
+    url::decode(segment).unwrap_or_else(|| segment.to_owned())
+}
+
+/// Splits a segment into its text and `{...}` params, with the text of the param and if it is one.
+/// Panics if the braces are unbalanced.
+fn split_params(seg: &str) -> Vec<(bool, &str)> {
+    let mut out = Vec::new();
+    let (mut depth, mut start) = (0_usize, 0);
+
+    for (i, chr) in seg.char_indices() {
+        match chr {
+            '{' => {
+                if depth == 0 {
+                    if i > start {
+                        out.push((false, &seg[start..i]));
+                    }
+                    start = i;
+                }
+                depth += 1;
+            }
+            '}' => {
+                depth = unbalanced(depth.checked_sub(1), seg);
+                if depth == 0 {
+                    out.push((true, &seg[start + 1..i]));
+                    start = i + 1;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    unbalanced(Some(depth).filter(|&x| x == 0), seg);
+    if start < seg.len() {
+        out.push((false, &seg[start..]));
+    }
+    out
+}
+
+/// Splits a route path into its segments.
+/// Slashes inside of `{}` are part of the segment, so they can be used in constraints.
+/// Panics if the braces are unbalanced, as the rest of the path would otherwise be silently taken as one segment.
+fn split_segments(path: &str) -> Vec<&str> {
+    let mut out = Vec::new();
+    let (mut depth, mut start) = (0_usize, 0);
+
+    for (i, chr) in path.char_indices() {
+        match chr {
+            '{' => depth += 1,
+            '}' => depth = unbalanced(depth.checked_sub(1), path),
+            '/' if depth == 0 => {
+                out.push(&path[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+
+    unbalanced(Some(depth).filter(|&x| x == 0), path);
+    out.push(&path[start..]);
+    out
+}
+
+/// Unwraps the depth of the braces while splitting `path`, which is None if they are unbalanced.
+fn unbalanced(depth: Option<usize>, path: &str) -> usize {
+    depth.unwrap_or_else(|| panic!("Unbalanced braces in path `{}`", path))
 }
 
 /// Normalize a Path
@@ -115,7 +586,7 @@ pub fn normalize_path(mut path: String) -> String {
 
 #[cfg(test)]
 mod test {
-    use super::{normalize_path, Path, PathPart};
+    use super::{normalize_path, Constraint, Path, PathPart, SegmentPart};
 
     #[test]
     fn test_path_new() {
@@ -206,6 +677,236 @@ mod test {
         assert_eq!(PathPart::from_segment("{}"), PathPart::Param("".to_owned()));
     }
 
+    #[test]
+    fn test_path_part_from_constrained() {
+        assert_eq!(
+            PathPart::from_segment("{id:u64}"),
+            PathPart::Constrained("id".to_owned(), Constraint::U64)
+        );
+
+        assert!(matches!(
+            PathPart::from_segment(r"{code:\d{4}}"),
+            PathPart::Constrained(name, Constraint::Pattern(_)) if name == "code"
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid constraint `[a-z` for path param `slug`")]
+    fn test_path_part_from_invalid_constraint() {
+        PathPart::from_segment("{slug:[a-z}");
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid constraint `int` for path param `id`")]
+    fn test_path_part_from_unknown_type() {
+        PathPart::from_segment("{id:int}");
+    }
+
+    #[test]
+    fn test_match_path_constrained() {
+        let path = Path::new("/user/{id:u64}".to_owned());
+        assert_eq!(
+            path.match_path("/user/42".to_owned()),
+            Some(vec![("id".to_owned(), "42".to_owned())])
+        );
+        assert_eq!(path.match_path("/user/-1".to_owned()), None);
+        assert_eq!(path.match_path("/user/me".to_owned()), None);
+
+        let path = Path::new("/post/{slug:[a-z-]+}".to_owned());
+        assert!(path.match_path("/post/hello-world".to_owned()).is_some());
+        assert!(path.match_path("/post/hello%20world".to_owned()).is_none());
+
+        // Slashes in constraints don't split the segment
+        let path = Path::new("/file/{name:[^/]+}".to_owned());
+        assert_eq!(path.parts.len(), 2);
+        assert!(path.match_path("/file/a.txt".to_owned()).is_some());
+    }
+
+    #[test]
+    fn test_path_part_from_optional_and_catch_all() {
+        assert_eq!(
+            PathPart::from_segment("{page?}"),
+            PathPart::Optional("page".to_owned(), None)
+        );
+
+        assert_eq!(
+            PathPart::from_segment("{page?:u32}"),
+            PathPart::Optional("page".to_owned(), Some(Constraint::U32))
+        );
+
+        assert_eq!(
+            PathPart::from_segment("{*rest}"),
+            PathPart::CatchAll("rest".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_path_part_from_mixed() {
+        assert_eq!(
+            PathPart::from_segment("{name}.{ext}"),
+            PathPart::Mixed(vec![
+                SegmentPart::Param("name".to_owned(), None),
+                SegmentPart::Literal(".".to_owned()),
+                SegmentPart::Param("ext".to_owned(), None),
+            ])
+        );
+
+        assert_eq!(
+            PathPart::from_segment("v{version:u8}"),
+            PathPart::Mixed(vec![
+                SegmentPart::Literal("v".to_owned()),
+                SegmentPart::Param("version".to_owned(), Some(Constraint::U8)),
+            ])
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Unbalanced braces in path `{name`")]
+    fn test_path_part_from_unclosed_param() {
+        PathPart::from_segment("{name");
+    }
+
+    #[test]
+    #[should_panic(expected = "Unbalanced braces in path `files/{name/raw`")]
+    fn test_path_unclosed_param() {
+        Path::new("/files/{name/raw".to_owned());
+    }
+
+    #[test]
+    #[should_panic(expected = "Unbalanced braces in path `files/name}`")]
+    fn test_path_unopened_param() {
+        Path::new("/files/name}".to_owned());
+    }
+
+    #[test]
+    #[should_panic(expected = "Params in `{a}{b}` have to be separated by text")]
+    fn test_path_part_from_adjacent_params() {
+        PathPart::from_segment("{a}{b}");
+    }
+
+    #[test]
+    #[should_panic(expected = "Optional params have to be at the end of the path")]
+    fn test_path_optional_not_at_end() {
+        Path::new("/{page?}/posts".to_owned());
+    }
+
+    #[test]
+    #[should_panic(expected = "Catch-all params have to be at the end of the path")]
+    fn test_path_catch_all_not_at_end() {
+        Path::new("/{*rest}/posts".to_owned());
+    }
+
+    #[test]
+    fn test_match_path_optional() {
+        let path = Path::new("/posts/{page?:u32}".to_owned());
+        assert_eq!(path.match_path("/posts".to_owned()), Some(vec![]));
+        assert_eq!(
+            path.match_path("/posts/2".to_owned()),
+            Some(vec![("page".to_owned(), "2".to_owned())])
+        );
+        assert_eq!(path.match_path("/posts/two".to_owned()), None);
+        assert_eq!(path.match_path("/posts/2/3".to_owned()), None);
+
+        let path = Path::new("/{page?}".to_owned());
+        assert_eq!(path.match_path("/".to_owned()), Some(vec![]));
+    }
+
+    #[test]
+    fn test_match_path_catch_all() {
+        let path = Path::new("/files/{*path}".to_owned());
+        assert_eq!(
+            path.match_path("/files/a/b%20c/d.txt".to_owned()),
+            Some(vec![("path".to_owned(), "a/b c/d.txt".to_owned())])
+        );
+        assert_eq!(path.match_path("/files".to_owned()), None);
+    }
+
+    #[test]
+    fn test_match_path_mixed() {
+        let path = Path::new("/files/{name}.{ext}".to_owned());
+        assert_eq!(
+            path.match_path("/files/archive.tar.gz".to_owned()),
+            Some(vec![
+                ("name".to_owned(), "archive.tar".to_owned()),
+                ("ext".to_owned(), "gz".to_owned())
+            ])
+        );
+        assert_eq!(path.match_path("/files/archive".to_owned()), None);
+        assert_eq!(path.match_path("/files/.gz".to_owned()), None);
+
+        // Shorter values are tried if the longest one doesn't satisfy a constraint
+        let path = Path::new(r"/{year:\d{4}}-{slug}".to_owned());
+        assert_eq!(
+            path.match_path("/2024-hello-world".to_owned()),
+            Some(vec![
+                ("year".to_owned(), "2024".to_owned()),
+                ("slug".to_owned(), "hello-world".to_owned())
+            ])
+        );
+
+        // Splits aren't retried, so this doesn't take forever
+        let path = Path::new("/{a}.{b}.{c}.{d}!".to_owned());
+        assert_eq!(path.match_path(".".repeat(2000)), None);
+
+        // Constrained params are checked against the whole segment at once
+        let path = Path::new("/{name:[a-z]+}.{ext}".to_owned());
+        assert_eq!(path.match_path("A.".repeat(64 * 1024)), None);
+        let value = format!("{}.b.c", "a".repeat(64 * 1024));
+        let params = path.match_path(value).unwrap();
+        assert_eq!(params[0].1.len(), 64 * 1024);
+        assert_eq!(params[1].1, "b.c");
+
+        let path = Path::new("/{id:u64}.{a:u8}.{b:f64}".to_owned());
+        assert_eq!(path.match_path("1.".repeat(64 * 1024)), None);
+        assert_eq!(
+            path.match_path("/12.3.4.5".to_owned()),
+            Some(vec![
+                ("id".to_owned(), "12".to_owned()),
+                ("a".to_owned(), "3".to_owned()),
+                ("b".to_owned(), "4.5".to_owned())
+            ])
+        );
+
+        // Patterns that allow an empty value can match one
+        let path = Path::new("/{a:[a-z]*}.{b}".to_owned());
+        assert_eq!(
+            path.match_path("/.b".to_owned()),
+            Some(vec![
+                ("a".to_owned(), "".to_owned()),
+                ("b".to_owned(), "b".to_owned())
+            ])
+        );
+    }
+
+    #[test]
+    fn test_constraint() {
+        let matches = |constraint: &str, value: &str| {
+            constraint.parse::<Constraint>().unwrap().matches(value)
+        };
+
+        assert!(matches("u8", "255"));
+        assert!(!matches("u8", "256"));
+        assert!(matches("i32", "-5"));
+        assert!(matches("f64", "1.5"));
+        assert!(matches("bool", "true"));
+        assert!(!matches("bool", "yes"));
+        assert!(matches("uuid", "67e55044-10b1-426f-9247-bb680e5fe0c8"));
+        assert!(!matches("uuid", "67e55044-10b1-426f-9247-bb680e5fe0c"));
+        assert!(!matches("uuid", "67e55044-10b1-426f-9247-bb680e5fe0cg"));
+        assert!(!matches("uuid", "67e5504410b1426f9247bb680e5fe0c8"));
+        assert!(matches("[a-z-]+", "a-b"));
+        assert!("(a|b)".parse::<Constraint>().is_err());
+
+        // Unknown types are rejected instead of being taken as a literal pattern
+        assert!("int".parse::<Constraint>().is_err());
+        assert!("U64".parse::<Constraint>().is_err());
+        assert_eq!("u64".parse::<Constraint>().unwrap().to_string(), "u64");
+        assert_eq!(
+            "[a-z]+".parse::<Constraint>().unwrap().to_string(),
+            "[a-z]+"
+        );
+    }
+
     #[test]
     fn test_path_part_from_any() {
         assert_eq!(PathPart::from_segment("*"), PathPart::Any);
@@ -0,0 +1,341 @@
+//! Minimal patterns used to constrain path params, like `{slug:[a-z-]+}`.
+//!
+//! Supports literal characters, `.` (any character), character classes (`[a-z_]`, `[^.]`) and the classes `\d`, `\w` and `\s`.
+//! Each can be followed by a `?`, `*`, `+`, `{n}`, `{n,}` or `{n,m}` quantifier.
+//! Other characters with a special meaning in regular expressions (`(`, `)`, `|`, `^`, `$`) have to be escaped with a `\`.
+//! A pattern always has to match the whole value.
+
+use std::{
+    fmt::{self, Display, Formatter},
+    mem,
+};
+
+/// A compiled pattern.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    raw: String,
+    atoms: Vec<Atom>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Atom {
+    class: Class,
+    min: usize,
+    max: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Class {
+    Any,
+    Char(char),
+    Set {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl Pattern {
+    /// Compiles a pattern.
+    /// Returns None if it is invalid or uses unsupported syntax.
+    pub fn new(pattern: &str) -> Option<Self> {
+        let mut chars = pattern.chars().peekable();
+        let mut atoms = Vec::new();
+
+        while let Some(chr) = chars.next() {
+            let class = match chr {
+                '.' => Class::Any,
+                '\\' => escape(chars.next()?),
+                '[' => {
+                    let negated = chars.next_if_eq(&'^').is_some();
+                    let mut ranges = Vec::new();
+                    loop {
+                        let start = match chars.next()? {
+                            ']' if !ranges.is_empty() => break,
+                            '\\' => match escape(chars.next()?) {
+                                Class::Char(i) => i,
+                                Class::Set { ranges: i, .. } => {
+                                    ranges.extend(i);
+                                    continue;
+                                }
+                                Class::Any => unreachable!(),
+                            },
+                            i => i,
+                        };
+
+                        let end = match chars.peek() {
+                            Some('-') => {
+                                chars.next();
+                                match chars.next()? {
+                                    // A `-` at the end of the class is literal
+                                    ']' => {
+                                        ranges.extend([(start, start), ('-', '-')]);
+                                        break;
+                                    }
+                                    '\\' => match escape(chars.next()?) {
+                                        Class::Char(i) => i,
+                                        _ => return None,
+                                    },
+                                    i => i,
+                                }
+                            }
+                            _ => start,
+                        };
+
+                        if end < start {
+                            return None;
+                        }
+                        ranges.push((start, end));
+                    }
+                    Class::Set { negated, ranges }
+                }
+                '(' | ')' | '|' | '^' | '$' | '?' | '*' | '+' | '{' | '}' | ']' => return None,
+                i => Class::Char(i),
+            };
+
+            let (min, max) = match chars.peek() {
+                Some('?') => (0, Some(1)),
+                Some('*') => (0, None),
+                Some('+') => (1, None),
+                Some('{') => {
+                    chars.next();
+                    let mut quantifier = String::new();
+                    loop {
+                        match chars.next()? {
+                            '}' => break,
+                            i => quantifier.push(i),
+                        }
+                    }
+
+                    let (min, max) = match quantifier.split_once(',') {
+                        Some((min, "")) => (min.parse().ok()?, None),
+                        Some((min, max)) => (min.parse().ok()?, Some(max.parse().ok()?)),
+                        None => {
+                            let n = quantifier.parse().ok()?;
+                            (n, Some(n))
+                        }
+                    };
+
+                    if max.is_some_and(|max| max < min) {
+                        return None;
+                    }
+                    atoms.push(Atom { class, min, max });
+                    continue;
+                }
+                _ => (1, Some(1)),
+            };
+
+            if min != 1 || max != Some(1) {
+                chars.next();
+            }
+            atoms.push(Atom { class, min, max });
+        }
+
+        Some(Self {
+            raw: pattern.to_owned(),
+            atoms,
+        })
+    }
+
+    /// Checks if the whole of `value` matches the pattern.
+    pub fn matches(&self, value: &str) -> bool {
+        let chars = value.chars().collect::<Vec<_>>();
+        let mut positions = vec![false; chars.len() + 1];
+        positions[0] = true;
+
+        run(self.atoms.iter(), &chars, &mut positions);
+        positions[chars.len()]
+    }
+
+    /// Finds the byte positions in `value` a match can end at, when starting from any of the positions set in `starts`.
+    /// `starts` has an entry for every byte of `value` and one for its end, only the ones at char boundaries are used.
+    pub(crate) fn ends(&self, value: &str, starts: &[bool]) -> Vec<bool> {
+        let bounds = boundaries(value);
+        let chars = value.chars().collect::<Vec<_>>();
+        let mut positions = bounds.iter().map(|&x| starts[x]).collect();
+
+        run(self.atoms.iter(), &chars, &mut positions);
+        let mut out = vec![false; value.len() + 1];
+        for (&i, found) in bounds.iter().zip(positions) {
+            out[i] = found;
+        }
+        out
+    }
+
+    /// Finds the byte positions in `value` a match can start at, to end at any of the positions set in `ends`.
+    /// Like [`Pattern::ends`], but matches the reversed atoms against the reversed value.
+    pub(crate) fn starts(&self, value: &str, ends: &[bool]) -> Vec<bool> {
+        let bounds = boundaries(value);
+        let chars = value.chars().rev().collect::<Vec<_>>();
+        let mut positions = bounds.iter().rev().map(|&x| ends[x]).collect();
+
+        run(self.atoms.iter().rev(), &chars, &mut positions);
+        let mut out = vec![false; value.len() + 1];
+        for (&i, found) in bounds.iter().rev().zip(positions) {
+            out[i] = found;
+        }
+        out
+    }
+}
+
+/// Matches `atoms` against `chars`, starting from every index set in `positions`, which is then replaced with the indices the matches can end at.
+///
+/// Every position is tracked at once instead of backtracking, so this takes O(atoms · chars) time for any pattern.
+/// The buffer for the next positions is allocated once, and reused for every atom.
+fn run<'a>(atoms: impl Iterator<Item = &'a Atom>, chars: &[char], positions: &mut Vec<bool>) {
+    let mut next = vec![false; chars.len() + 1];
+    for atom in atoms {
+        // Start of the run of characters matching the class that ends at `end`
+        let mut run = 0;
+        // Latest position the previous atoms end at, that leaves room for at least `min` repetitions before `end`
+        let mut start = None;
+
+        for end in 0..=chars.len() {
+            if end > 0 && !atom.class.matches(chars[end - 1]) {
+                run = end;
+            }
+
+            if let Some(i) = end.checked_sub(atom.min).filter(|&x| positions[x]) {
+                start = Some(i);
+            }
+
+            // The latest start is the one with the fewest repetitions, so if it is too far back for `max` the rest are too
+            next[end] =
+                start.is_some_and(|x| x >= run && atom.max.map_or(true, |max| end - x <= max));
+        }
+
+        mem::swap(positions, &mut next);
+    }
+}
+
+/// Gets the byte positions of the char boundaries in `value`, including its end.
+fn boundaries(value: &str) -> Vec<usize> {
+    (value.char_indices().map(|(i, _)| i))
+        .chain([value.len()])
+        .collect()
+}
+
+impl Class {
+    fn matches(&self, chr: char) -> bool {
+        match self {
+            Class::Any => true,
+            Class::Char(i) => *i == chr,
+            Class::Set { negated, ranges } => {
+                ranges
+                    .iter()
+                    .any(|(start, end)| (*start..=*end).contains(&chr))
+                    != *negated
+            }
+        }
+    }
+}
+
+impl Display for Pattern {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.raw)
+    }
+}
+
+/// Gets the class of an escaped character, like `\d`.
+fn escape(chr: char) -> Class {
+    let set = |ranges: &[(char, char)]| Class::Set {
+        negated: false,
+        ranges: ranges.to_vec(),
+    };
+
+    match chr {
+        'd' => set(&[('0', '9')]),
+        'w' => set(&[('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')]),
+        's' => set(&[(' ', ' '), ('\t', '\r')]),
+        i => Class::Char(i),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Pattern;
+
+    fn matches(pattern: &str, value: &str) -> bool {
+        Pattern::new(pattern).unwrap().matches(value)
+    }
+
+    #[test]
+    fn test_literal() {
+        assert!(matches("abc", "abc"));
+        assert!(!matches("abc", "abcd"));
+        assert!(!matches("abc", "ab"));
+        assert!(matches(r"a\.b", "a.b"));
+        assert!(!matches(r"a\.b", "acb"));
+        assert!(matches("a.b", "acb"));
+        assert!(matches("", ""));
+    }
+
+    #[test]
+    fn test_class() {
+        assert!(matches("[a-z-]+", "hello-world"));
+        assert!(!matches("[a-z-]+", "Hello-world"));
+        assert!(!matches("[a-z-]+", ""));
+        assert!(matches("[-a]", "-"));
+        assert!(matches("[^.]*", "abc"));
+        assert!(!matches("[^.]*", "a.c"));
+        assert!(matches(r"[\d_]+", "1_2"));
+        assert!(matches(r"\w+", "snake_case_1"));
+        assert!(!matches(r"\w+", "kebab-case"));
+        assert!(matches(r"a\sb", "a b"));
+    }
+
+    #[test]
+    fn test_quantifier() {
+        assert!(matches(r"\d{4}", "2024"));
+        assert!(!matches(r"\d{4}", "202"));
+        assert!(!matches(r"\d{4}", "20245"));
+        assert!(matches(r"\d{2,}", "12345"));
+        assert!(matches(r"[a-f]{1,3}", "abc"));
+        assert!(!matches(r"[a-f]{1,3}", "abcd"));
+        assert!(matches("colou?r", "color"));
+        assert!(matches("colou?r", "colour"));
+        assert!(matches("a*a*a*b", "aaaab"));
+        assert!(matches(r"v\d+\.\d+", "v1.23"));
+
+        // Every position is tracked at once, so this doesn't take forever
+        let value = "a".repeat(1000);
+        assert!(!matches("a*a*a*a*a*a*a*a*b", &value));
+        let value = "a".repeat(100_000);
+        assert!(!matches("a*a*a*a*a*a*a*a*b", &value));
+        assert!(matches("a*a+a{2}a{0,3}", &value));
+    }
+
+    #[test]
+    fn test_ends_and_starts() {
+        let positions = |x: &[usize], len: usize| {
+            let mut out = vec![false; len + 1];
+            x.iter().for_each(|&i| out[i] = true);
+            out
+        };
+        let set = |x: Vec<bool>| (0..x.len()).filter(|&i| x[i]).collect::<Vec<_>>();
+
+        let pattern = Pattern::new("[a-z]+").unwrap();
+        let value = "ab.cd";
+        assert_eq!(
+            set(pattern.ends(value, &positions(&[0, 3], 5))),
+            [1, 2, 4, 5]
+        );
+        assert_eq!(
+            set(pattern.starts(value, &positions(&[2, 5], 5))),
+            [0, 1, 3, 4]
+        );
+
+        // Positions are bytes, and only char boundaries are used
+        let pattern = Pattern::new("é.").unwrap();
+        assert_eq!(set(pattern.ends("aéb", &positions(&[1], 4))), [4]);
+        assert_eq!(set(pattern.starts("aéb", &positions(&[4], 4))), [1]);
+    }
+
+    #[test]
+    fn test_invalid() {
+        for pattern in [
+            "a|b", "(a)", "^a", "*", "a**", "[a-", "[z-a]", "[]", r"\", "a{", "a{x}",
+        ] {
+            assert_eq!(Pattern::new(pattern), None, "{}", pattern);
+        }
+    }
+}
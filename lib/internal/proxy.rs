@@ -0,0 +1,360 @@
+//! Parsing of the [PROXY protocol](https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt) header load balancers like HAProxy and AWS NLBs send at the start of a connection.
+//! It contains the address of the client, which would otherwise be lost as the connection comes from the proxy.
+//! Both the text (v1) and binary (v2) versions are supported.
+
+use std::{
+    convert::TryFrom,
+    io::{self, BufRead, Read},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    str,
+};
+
+use crate::{PeerAddr, Request};
+
+/// Signature every v2 header starts with.
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+/// Max length of a v1 header, including the CRLF.
+const V1_MAX_LENGTH: u64 = 107;
+
+/// Addresses a proxy sent with the PROXY protocol at the start of a connection.
+/// Only available on listeners with the PROXY protocol enabled, see [`crate::Server::proxy_protocol`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ProxyInfo {
+    /// Address of the proxy the connection actually came from.
+    pub peer: PeerAddr,
+    /// Address of the client, as seen by the proxy.
+    /// None if the proxy didn't send one, like for its own health checks.
+    pub source: Option<SocketAddr>,
+    /// Address the client connected to on the proxy.
+    pub destination: Option<SocketAddr>,
+}
+
+impl ProxyInfo {
+    /// Reads a PROXY protocol header of either version from the start of a connection.
+    /// Only the header is consumed, the rest of the connection is left in the reader.
+    /// Returns an [`io::ErrorKind::InvalidData`] error if the connection doesn't start with a valid header.
+    pub(crate) fn read(reader: &mut impl BufRead, peer: PeerAddr) -> io::Result<Self> {
+        // The shortest v1 header (`PROXY UNKNOWN\r\n`) is longer than the v2 signature
+        let mut start = [0; 12];
+        reader.read_exact(&mut start)?;
+
+        let (source, destination) = if start == V2_SIGNATURE {
+            read_v2(reader)?
+        } else if start.starts_with(b"PROXY ") {
+            read_v1(reader, &start)?
+        } else {
+            return Err(invalid("Missing PROXY protocol header"));
+        };
+
+        Ok(Self {
+            peer,
+            source,
+            destination,
+        })
+    }
+
+    /// Uses the client address sent by the proxy as the address of `req`, keeping the rest of the info in [`Request::proxy`].
+    pub(crate) fn apply(&self, req: &mut Request) {
+        if let Some(source) = self.source {
+            req.address = PeerAddr::Tcp(source);
+        }
+
+        req.proxy = Some(self.clone());
+    }
+}
+
+type Addrs = (Option<SocketAddr>, Option<SocketAddr>);
+
+/// Reads the rest of a v1 header, like `PROXY TCP4 192.168.0.1 10.0.0.1 56324 443\r\n`.
+fn read_v1(reader: &mut impl BufRead, start: &[u8]) -> io::Result<Addrs> {
+    let mut line = start.to_vec();
+    reader
+        .take(V1_MAX_LENGTH - start.len() as u64)
+        .read_until(b'\n', &mut line)?;
+
+    let line = line
+        .strip_suffix(b"\r\n")
+        .and_then(|x| str::from_utf8(x).ok())
+        .ok_or_else(|| invalid("Invalid PROXY protocol v1 header"))?;
+
+    let parts = line.split(' ').skip(1).collect::<Vec<_>>();
+    match parts.as_slice() {
+        ["UNKNOWN", ..] => Ok((None, None)),
+        [protocol @ ("TCP4" | "TCP6"), source, destination, source_port, destination_port] => {
+            let addr = |ip: &str, port: &str| -> Option<SocketAddr> {
+                let ip = match *protocol {
+                    "TCP4" => IpAddr::V4(ip.parse().ok()?),
+                    _ => IpAddr::V6(ip.parse().ok()?),
+                };
+                Some(SocketAddr::new(ip, port.parse().ok()?))
+            };
+
+            match (
+                addr(source, source_port),
+                addr(destination, destination_port),
+            ) {
+                (Some(source), Some(destination)) => Ok((Some(source), Some(destination))),
+                _ => Err(invalid("Invalid address in PROXY protocol v1 header")),
+            }
+        }
+        _ => Err(invalid("Invalid PROXY protocol v1 header")),
+    }
+}
+
+/// Reads the rest of a v2 header, after the signature.
+/// Addresses of `LOCAL` connections (health checks from the proxy) and families other than TCP over IPv4 or IPv6 are ignored.
+fn read_v2(reader: &mut impl BufRead) -> io::Result<Addrs> {
+    let mut head = [0; 4];
+    reader.read_exact(&mut head)?;
+    let [version_command, family, length @ ..] = head;
+
+    if version_command >> 4 != 2 {
+        return Err(invalid("Unsupported PROXY protocol version"));
+    }
+
+    let mut body = vec![0; u16::from_be_bytes(length) as usize];
+    reader.read_exact(&mut body)?;
+
+    let local = match version_command & 0x0F {
+        0 => true,
+        1 => false,
+        _ => return Err(invalid("Invalid PROXY protocol v2 command")),
+    };
+
+    // Any TLVs after the addresses are ignored
+    let port = |x: &[u8]| u16::from_be_bytes([x[0], x[1]]);
+    let addrs = match family {
+        0x11 if body.len() >= 12 => {
+            let ip = |x: &[u8]| IpAddr::V4(Ipv4Addr::new(x[0], x[1], x[2], x[3]));
+            (
+                SocketAddr::new(ip(&body[0..4]), port(&body[8..10])),
+                SocketAddr::new(ip(&body[4..8]), port(&body[10..12])),
+            )
+        }
+        0x21 if body.len() >= 36 => {
+            let ip = |x: &[u8]| IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(x).unwrap()));
+            (
+                SocketAddr::new(ip(&body[0..16]), port(&body[32..34])),
+                SocketAddr::new(ip(&body[16..32]), port(&body[34..36])),
+            )
+        }
+        0x11 | 0x21 => return Err(invalid("PROXY protocol v2 header too short")),
+        _ => return Ok((None, None)),
+    };
+
+    match local {
+        true => Ok((None, None)),
+        false => Ok((Some(addrs.0), Some(addrs.1))),
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{BufRead, Cursor, Write},
+        net::{SocketAddr, TcpListener, TcpStream},
+    };
+
+    use super::{ProxyInfo, V2_SIGNATURE};
+    use crate::{
+        internal::test_util::{get, read_lossy, request},
+        Method, PeerAddr, Response, Server,
+    };
+
+    fn read(header: &[u8]) -> Option<(Option<SocketAddr>, Option<SocketAddr>)> {
+        let mut reader = Cursor::new(header);
+        let peer = PeerAddr::Tcp("127.0.0.1:1234".parse().unwrap());
+        let info = ProxyInfo::read(&mut reader, peer).ok()?;
+        Some((info.source, info.destination))
+    }
+
+    fn v2(command: u8, family: u8, addrs: &[u8]) -> Vec<u8> {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.extend([0x20 | command, family]);
+        header.extend((addrs.len() as u16).to_be_bytes());
+        header.extend(addrs);
+        header
+    }
+
+    /// Connects to the server and sends a PROXY protocol header.
+    fn proxied(addr: SocketAddr) -> TcpStream {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(b"PROXY TCP4 192.168.0.1 10.0.0.1 56324 443\r\n")
+            .unwrap();
+        stream
+    }
+
+    #[test]
+    fn test_v1() {
+        let header = b"PROXY TCP4 192.168.0.1 10.0.0.1 56324 443\r\n";
+        assert_eq!(
+            read(header),
+            Some((
+                Some("192.168.0.1:56324".parse().unwrap()),
+                Some("10.0.0.1:443".parse().unwrap())
+            ))
+        );
+
+        let header = b"PROXY TCP6 2001:db8::1 ::1 56324 443\r\n";
+        assert_eq!(
+            read(header),
+            Some((
+                Some("[2001:db8::1]:56324".parse().unwrap()),
+                Some("[::1]:443".parse().unwrap())
+            ))
+        );
+
+        assert_eq!(read(b"PROXY UNKNOWN\r\n"), Some((None, None)));
+        assert_eq!(read(b"PROXY UNKNOWN ::1 ::1 1 2\r\n"), Some((None, None)));
+    }
+
+    #[test]
+    fn test_v1_invalid() {
+        assert_eq!(read(b"GET / HTTP/1.1\r\n\r\n"), None);
+        assert_eq!(read(b"PROXY TCP4 192.168.0.1 10.0.0.1 56324\r\n"), None);
+        assert_eq!(read(b"PROXY TCP4 ::1 10.0.0.1 56324 443\r\n"), None);
+        assert_eq!(read(b"PROXY TCP4 192.168.0.1 10.0.0.1 56324 443\n"), None);
+        assert_eq!(read(b"PROXY UDP4 192.168.0.1 10.0.0.1 56324 443\r\n"), None);
+
+        let mut long = b"PROXY UNKNOWN ".to_vec();
+        long.extend([b'a'; 100]);
+        long.extend(b"\r\n");
+        assert_eq!(read(&long), None);
+    }
+
+    #[test]
+    fn test_v2() {
+        let addrs = [192, 168, 0, 1, 10, 0, 0, 1, 0xDC, 0x04, 0x01, 0xBB];
+        assert_eq!(
+            read(&v2(1, 0x11, &addrs)),
+            Some((
+                Some("192.168.0.1:56324".parse().unwrap()),
+                Some("10.0.0.1:443".parse().unwrap())
+            ))
+        );
+
+        let mut addrs = [0; 36];
+        addrs[15] = 1;
+        addrs[31] = 2;
+        addrs[32..].copy_from_slice(&[0xDC, 0x04, 0x01, 0xBB]);
+        assert_eq!(
+            read(&v2(1, 0x21, &addrs)),
+            Some((
+                Some("[::1]:56324".parse().unwrap()),
+                Some("[::2]:443".parse().unwrap())
+            ))
+        );
+
+        // Health checks from the proxy and unsupported families have no addresses
+        assert_eq!(read(&v2(0, 0x11, &[0; 12])), Some((None, None)));
+        assert_eq!(read(&v2(1, 0x00, &[])), Some((None, None)));
+        assert_eq!(read(&v2(1, 0x31, &[0; 216])), Some((None, None)));
+    }
+
+    #[test]
+    fn test_v2_invalid() {
+        assert_eq!(read(&v2(1, 0x11, &[0; 8])), None);
+        assert_eq!(read(&v2(2, 0x11, &[0; 12])), None);
+
+        let mut header = v2(1, 0x11, &[0; 12]);
+        header[12] = 0x11;
+        assert_eq!(read(&header), None);
+
+        let mut header = v2(1, 0x11, &[0; 12]);
+        header.truncate(20);
+        assert_eq!(read(&header), None);
+    }
+
+    #[test]
+    fn test_leaves_request() {
+        let mut header = v2(1, 0x11, &[0; 16]);
+        header.extend(b"GET / HTTP/1.1\r\n");
+
+        let mut reader = Cursor::new(header);
+        let peer = PeerAddr::Unix(None);
+        ProxyInfo::read(&mut reader, peer).unwrap();
+
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "GET / HTTP/1.1\r\n");
+    }
+
+    #[test]
+    fn test_proxy_protocol() {
+        let mut server = Server::<()>::new("localhost", 0).proxy_protocol(true);
+        server.route(Method::GET, "/", |req| {
+            let proxy = req.proxy.as_ref().unwrap();
+            Response::new().text(format!("{} {:?}", req.address, proxy.destination))
+        });
+        let server = server.start_background(1).unwrap();
+
+        let response = request(&mut proxied(server.addr()), "/");
+        assert!(
+            response.ends_with("192.168.0.1:56324 Some(10.0.0.1:443)"),
+            "{}",
+            response
+        );
+
+        // Connections without a header are closed
+        let mut stream = TcpStream::connect(server.addr()).unwrap();
+        stream.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!(read_lossy(&mut stream), "");
+
+        server.stop().unwrap();
+    }
+
+    #[test]
+    fn test_proxy_protocol_per_listener() {
+        let mut server = Server::<()>::new("localhost", 0).listen_proxy("localhost", 0);
+        server.route(Method::GET, "/", |req| {
+            Response::new().text(format!("{} {}", req.address.ip(), req.proxy.is_some()))
+        });
+        let server = server.start_background(1).unwrap();
+        let addrs = server.addrs();
+
+        // The servers own address is used directly
+        assert!(get(addrs[0], "/").ends_with("127.0.0.1 false"));
+
+        // The extra address expects a header, and closes connections without one
+        assert!(request(&mut proxied(addrs[1]), "/").ends_with("192.168.0.1 true"));
+
+        let mut stream = TcpStream::connect(addrs[1]).unwrap();
+        stream.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!(read_lossy(&mut stream), "");
+
+        server.stop().unwrap();
+    }
+
+    #[test]
+    fn test_proxy_protocol_inherited() {
+        // Inherited listeners bound to an address keep its setting, the rest replace the servers own address
+        let main = TcpListener::bind("127.0.0.1:0").unwrap();
+        let proxy = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (main_addr, proxy_addr) = (main.local_addr().unwrap(), proxy.local_addr().unwrap());
+        let mut server = Server::<()>::new("127.0.0.1", 0)
+            .listen_proxy("127.0.0.1", proxy_addr.port())
+            .tcp_listener(proxy)
+            .tcp_listener(main);
+        server.route(Method::GET, "/", |req| {
+            Response::new().text(format!("{} {}", req.address.ip(), req.proxy.is_some()))
+        });
+        let server = server.start_background(1).unwrap();
+        assert_eq!(server.addrs(), [main_addr, proxy_addr]);
+
+        let response = read_lossy(&mut proxied(main_addr));
+        assert!(response.starts_with("HTTP/1.1 400"), "{}", response);
+        assert!(get(main_addr, "/").ends_with("127.0.0.1 false"));
+
+        assert!(request(&mut proxied(proxy_addr), "/").ends_with("192.168.0.1 true"));
+        let mut stream = TcpStream::connect(proxy_addr).unwrap();
+        stream.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!(read_lossy(&mut stream), "");
+
+        server.stop().unwrap();
+    }
+}
@@ -0,0 +1,336 @@
+//! Hot restarts, where the server re-executes itself and passes its listening sockets to the new process.
+//! The new process picks them up with [`crate::Server::hot_restart`], while the old one finishes its in-flight requests.
+//! Only supported on Linux.
+
+use std::{
+    collections::HashMap,
+    env,
+    ffi::{CString, OsStr, OsString},
+    io::{self, Read},
+    os::{
+        fd::{AsRawFd, IntoRawFd, OwnedFd, RawFd},
+        raw::{c_char, c_int},
+        unix::{ffi::OsStrExt, net::UnixStream, process::CommandExt},
+    },
+    path::PathBuf,
+    process::{self, Child, Command},
+    sync::{
+        atomic::{AtomicI32, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use crate::{
+    internal::{
+        common::ForceLock,
+        listener::{self, Inherited},
+    },
+    trace,
+    trace::emoji,
+    ShutdownHandle,
+};
+
+/// Environment variable with the number of listening sockets passed to the new process.
+const LISTEN_FDS: &str = "AFIRE_LISTEN_FDS";
+/// Environment variable with the pid of the new process, so the sockets are not picked up by any other process that inherits the environment.
+/// Like systemd does with `LISTEN_PID`, it is set in the forked child right before the new process is executed.
+const LISTEN_PID: &str = "AFIRE_LISTEN_PID";
+/// The first file descriptor the listening sockets are passed as, like with systemd.
+const LISTEN_FDS_START: RawFd = 3;
+
+const F_DUPFD: c_int = 0;
+#[cfg(any(target_arch = "mips", target_arch = "mips64"))]
+const SIGUSR2: c_int = 17;
+#[cfg(target_arch = "sparc64")]
+const SIGUSR2: c_int = 31;
+#[cfg(not(any(target_arch = "mips", target_arch = "mips64", target_arch = "sparc64")))]
+const SIGUSR2: c_int = 12;
+const SIG_ERR: usize = usize::MAX;
+
+extern "C" {
+    fn close(fd: c_int) -> c_int;
+    fn dup2(old: c_int, new: c_int) -> c_int;
+    fn execvpe(
+        file: *const c_char,
+        argv: *const *const c_char,
+        envp: *const *const c_char,
+    ) -> c_int;
+    fn fcntl(fd: c_int, cmd: c_int, ...) -> c_int;
+    fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+    fn write(fd: c_int, buf: *const u8, count: usize) -> isize;
+    fn __errno_location() -> *mut c_int;
+}
+
+/// Write end of the pipe the SIGUSR2 handler wakes the restart thread with.
+static SIGNAL_FD: AtomicI32 = AtomicI32::new(-1);
+/// The server restarted on SIGUSR2.
+static SIGNAL_SERVER: Mutex<Option<ShutdownHandle>> = Mutex::new(None);
+
+/// Creates the command used to re-execute the current process, with the same executable and arguments.
+pub(crate) fn command() -> io::Result<Command> {
+    // If the executable was replaced by a new build, Linux marks the old path as deleted
+    let exe = env::current_exe()?.into_os_string();
+    let exe = match exe.to_str().and_then(|x| x.strip_suffix(" (deleted)")) {
+        Some(i) => OsString::from(i),
+        None => exe,
+    };
+
+    let mut command = Command::new(PathBuf::from(exe));
+    command.args(env::args_os().skip(1));
+    Ok(command)
+}
+
+/// A command prepared to be executed in the forked child, so `AFIRE_LISTEN_PID` can be set to the pid of the new process.
+/// Everything is allocated before forking, as the child can only make async-signal-safe calls.
+struct Exec {
+    program: CString,
+    /// Only kept alive for `argv` and `envp`.
+    _args: Vec<CString>,
+    _env: Vec<CString>,
+    /// The `AFIRE_LISTEN_PID` variable, with room for the pid after the `=`.
+    pid: Vec<u8>,
+    argv: Vec<*const c_char>,
+    envp: Vec<*const c_char>,
+}
+
+// SAFETY: the pointers only point into the strings owned by the Exec itself
+unsafe impl Send for Exec {}
+unsafe impl Sync for Exec {}
+
+/// Spawns `command`, passing it the listening sockets in `fds`.
+/// They are passed as consecutive file descriptors starting at 3, with their count in `AFIRE_LISTEN_FDS` and the pid of the new process in `AFIRE_LISTEN_PID`.
+/// The environment of `command` is applied on top of the environment of the current process.
+pub(crate) fn spawn(mut command: Command, fds: &[OwnedFd]) -> io::Result<Child> {
+    let mut fds = fds.iter().map(|x| x.as_raw_fd()).collect::<Vec<_>>();
+    let mut exec = Exec::new(&command, fds.len())?;
+
+    // SAFETY: pass_fds and Exec::run only make async-signal-safe calls and do not allocate
+    unsafe {
+        command.pre_exec(move || {
+            pass_fds(&mut fds)?;
+            Err(exec.run())
+        })
+    };
+    command.spawn()
+}
+
+impl Exec {
+    fn new(command: &Command, fds: usize) -> io::Result<Self> {
+        let mut vars = env::vars_os().collect::<HashMap<_, _>>();
+        for (key, value) in command.get_envs() {
+            match value {
+                Some(value) => vars.insert(key.to_owned(), value.to_owned()),
+                None => vars.remove(key),
+            };
+        }
+        vars.remove(OsStr::new(LISTEN_PID));
+        vars.insert(LISTEN_FDS.into(), fds.to_string().into());
+
+        let program = c_string(command.get_program())?;
+        let args = (command.get_args().map(c_string)).collect::<io::Result<Vec<_>>>()?;
+        let env = vars
+            .iter()
+            .map(|(key, value)| {
+                let mut var = key.to_owned();
+                var.push("=");
+                var.push(value);
+                c_string(&var)
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        // Enough room for any pid, followed by the nul terminator
+        let mut pid = format!("{LISTEN_PID}=").into_bytes();
+        pid.resize(pid.len() + 21, 0);
+
+        let argv = (Some(&program).into_iter().chain(&args))
+            .map(|x| x.as_ptr())
+            .chain([std::ptr::null()])
+            .collect();
+        let envp = (env.iter().map(|x| x.as_ptr()))
+            .chain([pid.as_ptr().cast(), std::ptr::null()])
+            .collect();
+
+        Ok(Self {
+            program,
+            _args: args,
+            _env: env,
+            pid,
+            argv,
+            envp,
+        })
+    }
+
+    /// Fills in `AFIRE_LISTEN_PID` and executes the command, only returning if that failed.
+    /// Runs in the forked child.
+    fn run(&mut self) -> io::Error {
+        let start = LISTEN_PID.len() + 1;
+        let mut pid = process::id();
+        let mut digits = [0; 20];
+        let mut len = 0;
+        loop {
+            digits[len] = b'0' + (pid % 10) as u8;
+            len += 1;
+            pid /= 10;
+            if pid == 0 {
+                break;
+            }
+        }
+
+        for (i, digit) in digits[..len].iter().rev().enumerate() {
+            self.pid[start + i] = *digit;
+        }
+        self.pid[start + len] = 0;
+
+        unsafe {
+            execvpe(
+                self.program.as_ptr(),
+                self.argv.as_ptr(),
+                self.envp.as_ptr(),
+            )
+        };
+        io::Error::last_os_error()
+    }
+}
+
+/// Converts a string to a C string, failing if it contains a nul byte.
+fn c_string(string: &OsStr) -> io::Result<CString> {
+    CString::new(string.as_bytes()).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+/// Moves `fds` to consecutive file descriptors starting at 3, without close-on-exec, so they are inherited by the new process.
+/// Runs in the forked child before it executes the new process.
+pub(crate) fn pass_fds(fds: &mut [RawFd]) -> io::Result<()> {
+    let end = LISTEN_FDS_START + fds.len() as RawFd;
+
+    // Move every fd out of the way first, so none are overwritten before they are moved
+    for fd in fds.iter_mut() {
+        *fd = cvt(unsafe { fcntl(*fd, F_DUPFD, end) })?;
+    }
+
+    for (i, &fd) in fds.iter().enumerate() {
+        cvt(unsafe { dup2(fd, LISTEN_FDS_START + i as RawFd) })?;
+        unsafe { close(fd) };
+    }
+
+    Ok(())
+}
+
+/// Takes the listening sockets passed by the process that restarted into this one.
+/// They are only used if `AFIRE_LISTEN_PID` is the pid of this process, see [`listener::take_listen_fds`].
+pub(crate) fn listen_fds() -> Vec<Inherited> {
+    listener::take_listen_fds(LISTEN_PID, LISTEN_FDS)
+}
+
+/// Restarts the server of `handle` when the process receives SIGUSR2.
+/// Only one server per process can be restarted this way, later calls replace the server.
+pub(crate) fn on_signal(handle: ShutdownHandle) -> io::Result<()> {
+    let mut server = SIGNAL_SERVER.force_lock();
+    if server.is_some() {
+        *server = Some(handle);
+        return Ok(());
+    }
+
+    // Signal handlers can't do much safely, so the handler just wakes a thread to do the restart.
+    // Its end of the pipe doesn't block, if the pipe is full the thread already has a wake up waiting.
+    let (tx, mut rx) = UnixStream::pair()?;
+    tx.set_nonblocking(true)?;
+    thread::Builder::new()
+        .name("afire signal".to_owned())
+        .spawn(move || {
+            let mut buf = [0];
+            while rx.read(&mut buf).is_ok_and(|x| x > 0) {
+                trace!("{}Received SIGUSR2", emoji("🔄"));
+                let server = SIGNAL_SERVER.force_lock().clone();
+                if let Some(Err(e)) = server.map(|x| x.restart()) {
+                    trace!(Level::Error, "Error restarting server: {}", e);
+                }
+            }
+        })?;
+
+    SIGNAL_FD.store(tx.into_raw_fd(), Ordering::Release);
+    if unsafe { signal(SIGUSR2, on_sigusr2) } == SIG_ERR {
+        return Err(io::Error::last_os_error());
+    }
+
+    *server = Some(handle);
+    Ok(())
+}
+
+extern "C" fn on_sigusr2(_signal: c_int) {
+    // Keep errno as it was for the code that was interrupted
+    let fd = SIGNAL_FD.load(Ordering::Acquire);
+    unsafe {
+        let errno = *__errno_location();
+        write(fd, [1].as_ptr(), 1);
+        *__errno_location() = errno;
+    }
+}
+
+fn cvt(ret: c_int) -> io::Result<c_int> {
+    match ret {
+        -1 => Err(io::Error::last_os_error()),
+        i => Ok(i),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        env,
+        process::{self, Command, Stdio},
+        thread,
+        time::Duration,
+    };
+
+    use crate::{internal::test_util::get, Method, Response, Server};
+
+    #[test]
+    fn test_hot_restart() {
+        // The new process runs just this test again, serving on the listener passed to it
+        if env::var_os("AFIRE_LISTEN_FDS").is_some() {
+            let pid = process::id().to_string();
+            assert_eq!(env::var("AFIRE_LISTEN_PID").unwrap(), pid);
+            let mut server = Server::<()>::new("localhost", 0).hot_restart();
+            assert_eq!(server.inherited.len(), 1);
+
+            let shutdown = server.shutdown_handle();
+            let body = format!("new {}", pid);
+            server.route(Method::GET, "/", move |_req| Response::new().text(&body));
+            server.route(Method::GET, "/stop", move |_req| {
+                shutdown.shutdown();
+                Response::new().text(format!("new {}", pid))
+            });
+            server.start_threaded(1).unwrap();
+            return;
+        }
+
+        let mut server = Server::<()>::new("localhost", 0);
+        server.route(Method::GET, "/", |_req| Response::new().text("old"));
+        server.route(Method::GET, "/slow", |_req| {
+            thread::sleep(Duration::from_millis(500));
+            Response::new().text("old")
+        });
+
+        let server = server.start_background(2).unwrap();
+        let addr = server.addr();
+        let slow = thread::spawn(move || get(addr, "/slow"));
+        thread::sleep(Duration::from_millis(100));
+
+        let mut command = Command::new(env::current_exe().unwrap());
+        command
+            .args(["internal::restart::test::test_hot_restart", "--exact"])
+            .stdout(Stdio::null());
+        let mut child = server.shutdown_handle().restart_with(command).unwrap();
+
+        // The in-flight request finishes on the old process
+        assert!(slow.join().unwrap().ends_with("old"));
+        server.join().unwrap();
+
+        // The listener is still open in the new process, which got its own pid in AFIRE_LISTEN_PID
+        let new = format!("new {}", child.id());
+        assert!(get(addr, "/").ends_with(&new));
+        assert!(get(addr, "/stop").ends_with(&new));
+        assert!(child.wait().unwrap().success());
+    }
+}
@@ -0,0 +1,467 @@
+//! Finds the route a request is handled by.
+//! The routes are compiled into a tree of path segments when the server starts, so the cost of finding a route depends on the length of the path, not the number of routes.
+
+use std::collections::HashMap;
+
+use crate::{
+    path::{self, PathPart},
+    Method, Route,
+};
+
+/// Tree of the path segments of every route.
+/// Routes are referenced by their index in [`crate::Server::routes`], with later routes taking priority over earlier ones.
+#[derive(Debug, Default)]
+pub(crate) struct Router {
+    /// Node for the start of the path.
+    root: Node,
+    /// Number of routes the tree was built from.
+    routes: usize,
+}
+
+#[derive(Debug, Default)]
+struct Node {
+    /// Children for normal segments, by the segment.
+    normal: HashMap<String, Node>,
+    /// Child for `{param}`, `{param?}` and `*` segments, which all match any single segment.
+    wildcard: Option<Box<Node>>,
+    /// Children for segments that have to be checked against the request, like `{id:u64}` or `{name}.{ext}`.
+    /// Routes with the same segment at this point share a child.
+    checked: Vec<(PathPart, Node)>,
+    /// Routes whose path ends at this node.
+    routes: Vec<(Method, usize)>,
+    /// Routes with a `**` or `{*rest}` segment at this node, which match whatever comes after.
+    any_after: Vec<(Method, usize)>,
+    /// Highest index of any route at or below this node.
+    /// Used to skip branches that can't contain a route with a higher priority than the best match so far.
+    max: Option<usize>,
+}
+
+/// State of a search through the tree.
+#[derive(Default)]
+struct Search {
+    /// Index of the route with the highest priority found so far.
+    best: Option<usize>,
+    /// Params of the mixed segments on the way to the best route.
+    best_params: Vec<(String, String)>,
+    /// Params of the mixed segments on the way to the current node.
+    params: Vec<(String, String)>,
+}
+
+impl Router {
+    /// Compiles `routes` into a tree.
+    pub(crate) fn new<State>(routes: &[Route<State>]) -> Self {
+        let mut root = Node::default();
+        for (index, route) in routes.iter().enumerate() {
+            root.insert(&route.path.parts, route.method, index);
+        }
+
+        Self {
+            root,
+            routes: routes.len(),
+        }
+    }
+
+    /// Checks if the tree was built from a different number of routes, meaning it is out of date.
+    pub(crate) fn is_stale<State>(&self, routes: &[Route<State>]) -> bool {
+        self.routes != routes.len()
+    }
+
+    /// Finds the index of the route with the highest priority that matches a request with this method and path.
+    /// `segments` is the path with leading and trailing slashes removed, split on `/`.
+    ///
+    /// Also returns the params of the [`PathPart::Mixed`] segments, as they are already matched here.
+    /// The rest of the params are found with [`path::Path::params`].
+    pub(crate) fn find(
+        &self,
+        method: Method,
+        segments: &[&str],
+    ) -> Option<(usize, Vec<(String, String)>)> {
+        let mut search = Search::default();
+        self.root.find(method, segments, &mut search);
+
+        // The root path is a single empty segment, which optional params treat as missing
+        if segments == [""] {
+            self.root.find(method, &[], &mut search);
+        }
+        Some((search.best?, search.best_params))
+    }
+}
+
+impl Node {
+    fn insert(&mut self, parts: &[PathPart], method: Method, index: usize) {
+        self.max = Some(index);
+
+        let (part, rest) = match parts.split_first() {
+            Some(i) => i,
+            None => return self.routes.push((method, index)),
+        };
+
+        let child = match part {
+            PathPart::Normal(x) => self.normal.entry(x.to_owned()).or_default(),
+            PathPart::Param(_) | PathPart::Optional(_, None) | PathPart::Any => {
+                self.wildcard.get_or_insert_with(Default::default)
+            }
+            PathPart::Constrained(..) | PathPart::Optional(..) | PathPart::Mixed(_) => {
+                match self.checked.iter().position(|(x, _)| x == part) {
+                    Some(i) => &mut self.checked[i].1,
+                    None => {
+                        self.checked.push((part.to_owned(), Node::default()));
+                        &mut self.checked.last_mut().unwrap().1
+                    }
+                }
+            }
+            PathPart::AnyAfter | PathPart::CatchAll(_) => {
+                return self.any_after.push((method, index))
+            }
+        };
+
+        // Only optional params can come after an optional param, so the route can end here
+        if let PathPart::Optional(..) = part {
+            self.routes.push((method, index));
+        }
+        child.insert(rest, method, index);
+    }
+
+    fn find(&self, method: Method, segments: &[&str], search: &mut Search) {
+        if self.max <= search.best {
+            return;
+        }
+
+        let (segment, rest) = match segments.split_first() {
+            Some(i) => i,
+            None => return search.consider(&self.routes, method),
+        };
+
+        // Like every other part, `**` and `{*rest}` have to match at least one segment
+        search.consider(&self.any_after, method);
+        if let Some(child) = self.normal.get(*segment) {
+            child.find(method, rest, search);
+        }
+        if let Some(child) = &self.wildcard {
+            child.find(method, rest, search);
+        }
+
+        if !self.checked.is_empty() {
+            let value = path::decode(segment);
+            for (part, child) in &self.checked {
+                if child.max <= search.best {
+                    continue;
+                }
+
+                // The params of mixed segments are kept, so they don't have to be matched again
+                let len = search.params.len();
+                let matched = match part {
+                    PathPart::Mixed(x) => path::match_mixed(x, &value)
+                        .map(|x| search.params.extend(x))
+                        .is_some(),
+                    _ => part.matches(&value),
+                };
+
+                if matched {
+                    child.find(method, rest, search);
+                }
+                search.params.truncate(len);
+            }
+        }
+    }
+}
+
+impl Search {
+    /// Updates the best route if any of `routes` accept `method` and have a higher priority.
+    fn consider(&mut self, routes: &[(Method, usize)], method: Method) {
+        let matching = routes
+            .iter()
+            .filter(|(x, _)| *x == Method::ANY || *x == method)
+            .map(|(_, i)| *i)
+            .max();
+
+        if matching > self.best {
+            self.best = matching;
+            self.best_params = self.params.clone();
+        }
+    }
+}
+
+/// Splits a request path into its segments, the same way route paths are split.
+pub(crate) fn segments(path: &str) -> Vec<&str> {
+    path.trim_matches('/').split('/').collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{segments, Router};
+    use crate::{internal::test_util::get, Method, Response, Route, Server};
+
+    fn compile(routes: &[(Method, &str)]) -> Router {
+        let routes = routes
+            .iter()
+            .map(|(method, path)| {
+                Route::<()>::new(*method, path.to_string(), Box::new(|_| Response::new()))
+            })
+            .collect::<Vec<_>>();
+        Router::new(&routes)
+    }
+
+    fn find(router: &Router, method: Method, path: &str) -> Option<usize> {
+        router.find(method, &segments(path)).map(|x| x.0)
+    }
+
+    #[test]
+    fn test_segments() {
+        assert_eq!(segments("/"), vec![""]);
+        assert_eq!(segments(""), vec![""]);
+        assert_eq!(segments("//a/b//"), vec!["a", "b"]);
+        assert_eq!(segments("/a//b"), vec!["a", "", "b"]);
+    }
+
+    #[test]
+    fn test_normal() {
+        let router = compile(&[
+            (Method::GET, "/"),
+            (Method::GET, "/hello"),
+            (Method::GET, "/hello/world"),
+        ]);
+
+        assert_eq!(find(&router, Method::GET, "/"), Some(0));
+        assert_eq!(find(&router, Method::GET, ""), Some(0));
+        assert_eq!(find(&router, Method::GET, "/hello/"), Some(1));
+        assert_eq!(find(&router, Method::GET, "/hello/world"), Some(2));
+        assert_eq!(find(&router, Method::GET, "/Hello"), None);
+        assert_eq!(find(&router, Method::GET, "/hello/world/again"), None);
+    }
+
+    #[test]
+    fn test_method() {
+        let router = compile(&[
+            (Method::GET, "/"),
+            (Method::POST, "/"),
+            (Method::ANY, "/any"),
+        ]);
+
+        assert_eq!(find(&router, Method::GET, "/"), Some(0));
+        assert_eq!(find(&router, Method::POST, "/"), Some(1));
+        assert_eq!(find(&router, Method::PUT, "/"), None);
+        assert_eq!(find(&router, Method::DELETE, "/any"), Some(2));
+    }
+
+    #[test]
+    fn test_wildcards() {
+        let router = compile(&[
+            (Method::GET, "/user/{id}"),
+            (Method::GET, "/file/*/info"),
+            (Method::GET, "/static/**"),
+        ]);
+
+        assert_eq!(find(&router, Method::GET, "/user/1"), Some(0));
+        assert_eq!(find(&router, Method::GET, "/user/1/2"), None);
+        assert_eq!(find(&router, Method::GET, "/user"), None);
+        assert_eq!(find(&router, Method::GET, "/file/a/info"), Some(1));
+        assert_eq!(find(&router, Method::GET, "/file/a/b/info"), None);
+        assert_eq!(find(&router, Method::GET, "/static/a"), Some(2));
+        assert_eq!(find(&router, Method::GET, "/static/a/b/c"), Some(2));
+        assert_eq!(find(&router, Method::GET, "/static"), None);
+    }
+
+    #[test]
+    fn test_constrained() {
+        let router = compile(&[
+            (Method::GET, "/user/{name}"),
+            (Method::GET, "/user/{id:u64}"),
+            (Method::GET, "/user/{id:uuid}"),
+            (Method::GET, "/post/{slug:[a-z-]+}"),
+            (Method::GET, "/post/{id:u64}"),
+        ]);
+
+        assert_eq!(find(&router, Method::GET, "/user/42"), Some(1));
+        assert_eq!(
+            find(
+                &router,
+                Method::GET,
+                "/user/67e55044-10b1-426f-9247-bb680e5fe0c8"
+            ),
+            Some(2)
+        );
+        assert_eq!(find(&router, Method::GET, "/user/me"), Some(0));
+        assert_eq!(find(&router, Method::GET, "/post/hello-world"), Some(3));
+        assert_eq!(find(&router, Method::GET, "/post/1"), Some(4));
+        assert_eq!(find(&router, Method::GET, "/post/Hello"), None);
+    }
+
+    #[test]
+    fn test_optional() {
+        let router = compile(&[
+            (Method::GET, "/posts/{page?}"),
+            (Method::GET, "/{lang?:[a-z]{2}}/{page?:u32}"),
+        ]);
+
+        assert_eq!(find(&router, Method::GET, "/posts"), Some(0));
+        assert_eq!(find(&router, Method::GET, "/posts/2"), Some(0));
+        assert_eq!(find(&router, Method::GET, "/posts/2/3"), None);
+        assert_eq!(find(&router, Method::GET, "/"), Some(1));
+        assert_eq!(find(&router, Method::GET, "/en"), Some(1));
+        assert_eq!(find(&router, Method::GET, "/en/2"), Some(1));
+        assert_eq!(find(&router, Method::GET, "/en/two"), None);
+        assert_eq!(find(&router, Method::GET, "/english"), None);
+    }
+
+    #[test]
+    fn test_catch_all_and_mixed() {
+        let router = compile(&[
+            (Method::GET, "/files/{*path}"),
+            (Method::GET, "/files/{name}.{ext}"),
+            (Method::GET, "/files/{name}.{ext:u8}"),
+        ]);
+
+        assert_eq!(find(&router, Method::GET, "/files/a/b/c"), Some(0));
+        assert_eq!(find(&router, Method::GET, "/files"), None);
+        assert_eq!(find(&router, Method::GET, "/files/a.txt"), Some(1));
+        assert_eq!(find(&router, Method::GET, "/files/a.1"), Some(2));
+        assert_eq!(find(&router, Method::GET, "/files/a"), Some(0));
+    }
+
+    #[test]
+    fn test_mixed_params() {
+        let router = compile(&[
+            (Method::GET, "/files/{name}.{ext}"),
+            (Method::GET, "/files/{a}-{b}/raw"),
+            (Method::GET, "/files/{x}_{y}"),
+        ]);
+        let params = |path| router.find(Method::GET, &segments(path)).unwrap().1;
+        let owned = |x: &[(&str, &str)]| {
+            x.iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<Vec<_>>()
+        };
+
+        // The params of the route that was found are returned, not of the branches that were tried before
+        assert_eq!(
+            params("/files/a-b.txt"),
+            owned(&[("name", "a-b"), ("ext", "txt")])
+        );
+        assert_eq!(
+            params("/files/a-b.txt/raw"),
+            owned(&[("a", "a"), ("b", "b.txt")])
+        );
+        assert_eq!(
+            params("/files/a_b-c.d"),
+            owned(&[("x", "a"), ("y", "b-c.d")])
+        );
+    }
+
+    #[test]
+    fn test_any_after() {
+        let router = compile(&[(Method::ANY, "**")]);
+        assert_eq!(find(&router, Method::GET, "/"), Some(0));
+        assert_eq!(find(&router, Method::POST, "/a/b/c"), Some(0));
+
+        // Segments after `**` are ignored
+        let router = compile(&[(Method::GET, "/a/**/b")]);
+        assert_eq!(find(&router, Method::GET, "/a/c/d"), Some(0));
+    }
+
+    #[test]
+    fn test_priority() {
+        let router = compile(&[
+            (Method::GET, "/user/{id}"),
+            (Method::GET, "/user/me"),
+            (Method::ANY, "**"),
+            (Method::GET, "/user/{name}"),
+        ]);
+
+        // Later routes take priority, even over more specific ones
+        assert_eq!(find(&router, Method::GET, "/user/me"), Some(3));
+        assert_eq!(find(&router, Method::GET, "/user/1"), Some(3));
+        assert_eq!(find(&router, Method::POST, "/user/me"), Some(2));
+        assert_eq!(find(&router, Method::GET, "/"), Some(2));
+
+        let router = compile(&[(Method::GET, "/user/{id}"), (Method::GET, "/user/me")]);
+        assert_eq!(find(&router, Method::GET, "/user/me"), Some(1));
+        assert_eq!(find(&router, Method::GET, "/user/you"), Some(0));
+    }
+
+    #[test]
+    fn test_routing() {
+        let mut server = Server::<()>::new("localhost", 0);
+        server.route(Method::ANY, "**", |_req| Response::new().text("any"));
+        server.route(Method::GET, "/user/me", |_req| Response::new().text("me"));
+        server.route(Method::GET, "/user/{id}", |req| {
+            Response::new().text(format!("user {}", req.param("id").unwrap()))
+        });
+        server.route(Method::GET, "/user/{id}/posts", |req| {
+            Response::new().text(format!("posts {}", req.param("id").unwrap()))
+        });
+        let server = server.start_background(1).unwrap();
+
+        let addr = server.addr();
+        assert!(get(addr, "/user/1").ends_with("user 1"));
+        assert!(get(addr, "/user/a%20b/").ends_with("user a b"));
+        assert!(get(addr, "/user/2/posts").ends_with("posts 2"));
+        assert!(get(addr, "/user/2/comments").ends_with("any"));
+        assert!(get(addr, "/").ends_with("any"));
+
+        // The last matching route wins, even if an earlier one is more specific
+        assert!(get(addr, "/user/me").ends_with("user me"));
+
+        server.stop().unwrap();
+    }
+
+    #[test]
+    fn test_constrained_params() {
+        let mut server = Server::<()>::new("localhost", 0);
+        server.route(Method::GET, "/user/{name}", |req| {
+            Response::new().text(format!("name {}", req.param("name").unwrap()))
+        });
+        server.route(Method::GET, "/user/{id:u64}", |req| {
+            let id = req.param_as::<u64>("id").unwrap();
+            Response::new().text(format!("id {}", id + 1))
+        });
+        server.route(Method::GET, "/square/{n}", |req| {
+            match req.param_as::<i64>("n") {
+                Ok(n) => Response::new().text(n * n),
+                Err(e) => Response::new().status(400).text(e),
+            }
+        });
+        let server = server.start_background(1).unwrap();
+
+        let addr = server.addr();
+        assert!(get(addr, "/user/41").ends_with("id 42"));
+        assert!(get(addr, "/user/me").ends_with("name me"));
+        assert!(get(addr, "/user/-1").ends_with("name -1"));
+        assert!(get(addr, "/square/-3").ends_with('9'));
+
+        let response = get(addr, "/square/three");
+        assert!(response.starts_with("HTTP/1.1 400"));
+        assert!(response
+            .ends_with("Invalid value `three` for path param `n`: invalid digit found in string"));
+
+        server.stop().unwrap();
+    }
+
+    #[test]
+    fn test_path_params() {
+        let mut server = Server::<()>::new("localhost", 0);
+        server.route(Method::GET, "/files/{*path}", |req| {
+            Response::new().text(format!("path {}", req.param("path").unwrap()))
+        });
+        server.route(Method::GET, "/files/{name}.{ext}", |req| {
+            let (name, ext) = (req.param("name").unwrap(), req.param("ext").unwrap());
+            Response::new().text(format!("file {name} {ext}"))
+        });
+        server.route(Method::GET, "/posts/{page?:u32}", |req| {
+            let page = req.param_as::<u32>("page").unwrap_or(1);
+            Response::new().text(format!("page {page}"))
+        });
+        let server = server.start_background(1).unwrap();
+
+        let addr = server.addr();
+        assert!(get(addr, "/files/a/b%20c").ends_with("path a/b c"));
+        assert!(get(addr, "/files/a/b.txt").ends_with("path a/b.txt"));
+        assert!(get(addr, "/files/a.tar.gz").ends_with("file a.tar gz"));
+        assert!(get(addr, "/files/readme").ends_with("path readme"));
+        assert!(get(addr, "/posts").ends_with("page 1"));
+        assert!(get(addr, "/posts/3").ends_with("page 3"));
+        assert!(get(addr, "/posts/three").starts_with("HTTP/1.1 404"));
+
+        server.stop().unwrap();
+    }
+}
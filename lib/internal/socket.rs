@@ -0,0 +1,319 @@
+//! Connections that afire can handle requests from.
+//! Out of the box, connections can come in over TCP or, on unix platforms, Unix domain sockets.
+//! Other transports can be used by implementing [`Connection`].
+
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::{
+    fmt::{self, Display},
+    io::{self, BufReader, Read, Write},
+    net::{IpAddr, Ipv4Addr, Shutdown, SocketAddr, TcpStream},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::internal::common::ForceLock;
+
+/// A client connection.
+/// afire reads requests from, and writes responses to a connection.
+///
+/// It is implemented for [`TcpStream`] and [`UnixStream`], but it can be implemented for other transports too.
+/// Connections can then be handed to the server with [`crate::Server::handle_connection`].
+pub trait Connection: Read + Write + Send + 'static {
+    /// Gets the address of the peer this connection is connected to.
+    fn peer_addr(&self) -> io::Result<PeerAddr>;
+
+    /// Shuts down the read, write, or both halves of this connection.
+    fn shutdown(&self, how: Shutdown) -> io::Result<()>;
+
+    /// Creates a new independently owned handle to the underlying connection.
+    /// This is used to close connections on shutdown and for WebSockets.
+    /// Transports that can't be cloned should return an [`io::ErrorKind::Unsupported`] error.
+    fn try_clone(&self) -> io::Result<Socket>;
+
+    /// Sets the read timeout of the connection.
+    /// The default implementation does nothing.
+    fn set_read_timeout(&self, _timeout: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Sets the write timeout of the connection.
+    /// The default implementation does nothing.
+    fn set_write_timeout(&self, _timeout: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Moves the connection into or out of nonblocking mode.
+    /// Used to send responses without waiting on the client, like when turning it away because the server is overloaded.
+    /// The default implementation returns an [`io::ErrorKind::Unsupported`] error.
+    fn set_nonblocking(&self, _nonblocking: bool) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    /// Gets information about the TLS session, if the connection is encrypted.
+    /// The default implementation returns None.
+    fn tls_info(&self) -> Option<TlsInfo> {
+        None
+    }
+}
+
+/// A boxed [`Connection`], used throughout afire.
+pub type Socket = Box<dyn Connection>;
+
+/// Reads from a socket shared with the rest of the server, locking it for every read.
+/// Reads can be given a deadline, which limits how long a whole phase of reading a request (like reading its headers) can take.
+pub(crate) struct SharedSocket {
+    pub(crate) socket: Arc<Mutex<Socket>>,
+    /// Read timeout used for every read, see [`crate::Server::socket_timeout`].
+    timeout: Option<Duration>,
+    /// Point in time reads have to be finished by.
+    deadline: Option<Instant>,
+    /// If the read timeout of the socket was changed to meet a deadline.
+    timeout_changed: bool,
+}
+
+/// Buffered reader for a connection.
+/// It lives as long as the connection, so bytes buffered past the end of one request (pipelining) are kept for the next.
+pub(crate) type SocketReader = BufReader<SharedSocket>;
+
+/// The address of the peer on the other end of a [`Connection`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PeerAddr {
+    /// The peer connected over TCP.
+    Tcp(SocketAddr),
+    /// The peer connected over a Unix domain socket.
+    /// Contains the path the peer is bound to, which is usually `None` for clients.
+    Unix(Option<PathBuf>),
+}
+
+/// Information about the TLS session of a connection.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TlsInfo {
+    /// The server name the client asked for with [SNI](https://en.wikipedia.org/wiki/Server_Name_Indication), if any.
+    pub server_name: Option<String>,
+}
+
+impl Connection for TcpStream {
+    fn peer_addr(&self) -> io::Result<PeerAddr> {
+        Ok(PeerAddr::Tcp(TcpStream::peer_addr(self)?))
+    }
+
+    fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        TcpStream::shutdown(self, how)
+    }
+
+    fn try_clone(&self) -> io::Result<Socket> {
+        Ok(Box::new(TcpStream::try_clone(self)?))
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_write_timeout(self, timeout)
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        TcpStream::set_nonblocking(self, nonblocking)
+    }
+}
+
+#[cfg(unix)]
+impl Connection for UnixStream {
+    fn peer_addr(&self) -> io::Result<PeerAddr> {
+        Ok(PeerAddr::Unix(
+            UnixStream::peer_addr(self)?
+                .as_pathname()
+                .map(|x| x.to_path_buf()),
+        ))
+    }
+
+    fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        UnixStream::shutdown(self, how)
+    }
+
+    fn try_clone(&self) -> io::Result<Socket> {
+        Ok(Box::new(UnixStream::try_clone(self)?))
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UnixStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UnixStream::set_write_timeout(self, timeout)
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        UnixStream::set_nonblocking(self, nonblocking)
+    }
+}
+
+impl SharedSocket {
+    /// Creates a new SharedSocket, with the read timeout the socket was set up with.
+    pub(crate) fn new(socket: Arc<Mutex<Socket>>, timeout: Option<Duration>) -> Self {
+        Self {
+            socket,
+            timeout,
+            deadline: None,
+            timeout_changed: false,
+        }
+    }
+
+    /// Sets a deadline `timeout` from now, that all following reads have to be finished by.
+    /// Reads after the deadline fail with an [`io::ErrorKind::TimedOut`] error.
+    /// If `timeout` is None, any previous deadline is removed.
+    pub(crate) fn set_deadline(&mut self, timeout: Option<Duration>) {
+        self.deadline = timeout.map(|x| Instant::now() + x);
+    }
+}
+
+impl Read for SharedSocket {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut socket = self.socket.force_lock();
+        if let Some(deadline) = self.deadline {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+
+            socket.set_read_timeout(Some(self.timeout.map_or(left, |x| x.min(left))))?;
+            self.timeout_changed = true;
+        } else if self.timeout_changed {
+            socket.set_read_timeout(self.timeout)?;
+            self.timeout_changed = false;
+        }
+
+        socket.read(buf)
+    }
+}
+
+impl PeerAddr {
+    /// Gets the IP address of the peer.
+    /// Peers connected over a Unix domain socket are always on the same machine, so they will return the IPv4 loopback address (`127.0.0.1`).
+    pub fn ip(&self) -> IpAddr {
+        match self {
+            PeerAddr::Tcp(i) => i.ip(),
+            PeerAddr::Unix(_) => Ipv4Addr::LOCALHOST.into(),
+        }
+    }
+
+    /// Gets the socket address of the peer, if it connected over TCP.
+    pub fn socket_addr(&self) -> Option<SocketAddr> {
+        match self {
+            PeerAddr::Tcp(i) => Some(*i),
+            PeerAddr::Unix(_) => None,
+        }
+    }
+
+    /// Checks if the peer connected over a Unix domain socket.
+    pub fn is_unix(&self) -> bool {
+        matches!(self, PeerAddr::Unix(_))
+    }
+}
+
+impl From<SocketAddr> for PeerAddr {
+    fn from(addr: SocketAddr) -> Self {
+        PeerAddr::Tcp(addr)
+    }
+}
+
+impl Display for PeerAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PeerAddr::Tcp(i) => Display::fmt(i, f),
+            PeerAddr::Unix(Some(i)) => write!(f, "unix:{}", i.display()),
+            PeerAddr::Unix(None) => f.write_str("unix:(unnamed)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{self, Cursor, Read, Write},
+        net::{IpAddr, Ipv4Addr, Shutdown, SocketAddr},
+        sync::{Arc, Mutex},
+    };
+
+    use super::{Connection, PeerAddr, Socket};
+    use crate::{Method, Response, Server};
+
+    #[test]
+    fn test_peer_addr_ip() {
+        let addr = SocketAddr::new([10, 0, 0, 1].into(), 1234);
+        assert_eq!(
+            PeerAddr::Tcp(addr).ip(),
+            IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))
+        );
+        assert_eq!(PeerAddr::Unix(None).ip(), IpAddr::V4(Ipv4Addr::LOCALHOST));
+    }
+
+    #[test]
+    fn test_peer_addr_display() {
+        let addr = SocketAddr::new([10, 0, 0, 1].into(), 1234);
+        assert_eq!(PeerAddr::Tcp(addr).to_string(), "10.0.0.1:1234");
+        assert_eq!(
+            PeerAddr::Unix(Some("/tmp/afire.sock".into())).to_string(),
+            "unix:/tmp/afire.sock"
+        );
+        assert_eq!(PeerAddr::Unix(None).to_string(), "unix:(unnamed)");
+    }
+
+    #[test]
+    fn test_handle_in_memory_connection() {
+        struct MemoryConnection {
+            input: Cursor<Vec<u8>>,
+            output: Arc<Mutex<Vec<u8>>>,
+        }
+
+        impl Read for MemoryConnection {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                self.input.read(buf)
+            }
+        }
+
+        impl Write for MemoryConnection {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.output.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        impl Connection for MemoryConnection {
+            fn peer_addr(&self) -> io::Result<PeerAddr> {
+                Ok(PeerAddr::Tcp(([127, 0, 0, 1], 1234).into()))
+            }
+
+            fn shutdown(&self, _how: Shutdown) -> io::Result<()> {
+                Ok(())
+            }
+
+            fn try_clone(&self) -> io::Result<Socket> {
+                Err(io::ErrorKind::Unsupported.into())
+            }
+        }
+
+        let mut server = Server::<()>::new("localhost", 0);
+        server.route(Method::GET, "/", |req| {
+            Response::new().text(format!("Hello {}", req.address))
+        });
+
+        let output = Arc::new(Mutex::new(Vec::new()));
+        server
+            .handle_connection(MemoryConnection {
+                input: Cursor::new(b"GET / HTTP/1.1\r\n\r\n".to_vec()),
+                output: output.clone(),
+            })
+            .unwrap();
+
+        let output = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+        assert!(output.starts_with("HTTP/1.1 200 OK"));
+        assert!(output.ends_with("Hello 127.0.0.1:1234"));
+    }
+}
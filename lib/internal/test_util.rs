@@ -0,0 +1,43 @@
+//! Helpers for tests that talk to a running server over a socket.
+
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream},
+};
+
+/// Sends a `GET` request for `path` on the stream, and reads the response until the server closes the connection.
+pub(crate) fn request(stream: &mut (impl Read + Write), path: &str) -> String {
+    write!(stream, "GET {path} HTTP/1.1\r\nConnection: close\r\n\r\n").unwrap();
+    read(stream)
+}
+
+/// Sends a `GET` request for `path` on a new connection.
+pub(crate) fn get(addr: SocketAddr, path: &str) -> String {
+    request(&mut TcpStream::connect(addr).unwrap(), path)
+}
+
+/// Sends a raw request on a new connection, and reads everything the server sends back until it closes the connection.
+pub(crate) fn send(addr: SocketAddr, request: impl AsRef<[u8]>) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(request.as_ref()).unwrap();
+    read(&mut stream)
+}
+
+/// Reads from the stream until it is closed.
+pub(crate) fn read(stream: &mut impl Read) -> String {
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+/// Reads from the stream until it is closed or fails, for example because the server reset the connection.
+pub(crate) fn read_lossy(stream: &mut impl Read) -> String {
+    let mut response = Vec::new();
+    let _ = stream.read_to_end(&mut response);
+    String::from_utf8_lossy(&response).into_owned()
+}
+
+/// Splits the responses to pipelined requests that were all successful, returning everything after each status line.
+pub(crate) fn responses(response: &str) -> Vec<&str> {
+    response.split("HTTP/1.1 200 OK").skip(1).collect()
+}
@@ -26,26 +26,41 @@ use internal::{encoding, handle, path};
 #[macro_use]
 pub mod trace;
 pub mod error;
+mod group;
 mod http;
+mod limits;
 pub mod middleware;
 mod request;
 mod response;
 mod route;
 mod server;
+mod shutdown;
+#[cfg(feature = "tls")]
+pub mod tls;
 pub use self::{
     content_type::Content,
     cookie::{Cookie, SetCookie},
     error::Error,
+    group::Group,
     header::{Header, HeaderType},
-    http::{cookie, header, multipart, server_sent_events},
+    http::{cookie, header, multipart, server_sent_events, version},
+    internal::{
+        body::RequestBody,
+        proxy::ProxyInfo,
+        socket::{Connection, PeerAddr, TlsInfo},
+    },
+    limits::Limits,
     method::Method,
     middleware::Middleware,
     query::Query,
     request::Request,
     response::Response,
     route::Route,
-    server::Server,
+    server::{ListenAddress, Server},
+    shutdown::{BackgroundServer, ShutdownHandle},
     status::Status,
+    thread_pool::{OverflowPolicy, PoolMetrics},
+    version::HttpVersion,
 };
 
 /// The Prelude is a collection of very commonly used *things* in afire.
@@ -55,8 +70,8 @@ pub mod prelude {
         error::{self, Error},
         middleware::{MiddleResult, Middleware},
         server_sent_events::ServerSentEventsExt,
-        Content, Cookie, Header, HeaderType, Method, Query, Request, Response, Server, SetCookie,
-        Status,
+        Content, Cookie, Header, HeaderType, HttpVersion, Method, Query, Request, Response, Server,
+        SetCookie, Status,
     };
 }
 
@@ -84,7 +99,7 @@ pub mod extension {
         head::Head,
         logger::{self, Logger},
         ratelimit::RateLimiter,
-        real_ip::RealIp,
+        real_ip::{self, RealIp, TrustedProxies},
         request_id::RequestId,
         serve_static::{self, ServeStatic},
         trace::Trace,
@@ -0,0 +1,178 @@
+//! Limits on the size of incoming requests.
+
+/// Limits on the size of incoming requests.
+/// They protect the server from clients that try to make it allocate huge amounts of memory.
+///
+/// Limits are set server wide with [`crate::Server::limits`] (or the individual builder methods like [`crate::Server::max_body_size`]).
+/// All limits except `max_request_line` can be overridden for a single route, see [`crate::Route::max_body_size`].
+/// Requests exceeding a limit are rejected with the following status codes:
+///
+/// | Limit              | Status                                |
+/// | ------------------ | ------------------------------------- |
+/// | `max_request_line` | 414 URI Too Long                      |
+/// | `max_header_count` | 431 Request Header Fields Too Large   |
+/// | `max_header_size`  | 431 Request Header Fields Too Large   |
+/// | `max_body_size`    | 413 Payload Too Large                 |
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Limits {
+    /// Max length of the request line (`GET /path HTTP/1.1`) in bytes, not including the line ending.
+    /// Defaults to 8 KiB.
+    pub max_request_line: usize,
+
+    /// Max number of headers (including cookies) in a request.
+    /// Trailers of a chunked body are counted separately, with the same limit.
+    /// Defaults to 100.
+    pub max_header_count: usize,
+
+    /// Max length of a single header line in bytes, not including the line ending.
+    /// Defaults to 8 KiB.
+    pub max_header_size: usize,
+
+    /// Max size of a request body in bytes, after decoding chunked transfer-encoding.
+    /// Defaults to 8 MiB.
+    pub max_body_size: usize,
+}
+
+impl Limits {
+    /// Creates a new set of limits with the default values.
+    pub const fn new() -> Self {
+        Self {
+            max_request_line: 8 * 1024,
+            max_header_count: 100,
+            max_header_size: 8 * 1024,
+            max_body_size: 8 * 1024 * 1024,
+        }
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Per route overrides of the servers [`Limits`].
+/// Any limit that is not set falls back to the server wide value.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct RouteLimits {
+    pub(crate) max_header_count: Option<usize>,
+    pub(crate) max_header_size: Option<usize>,
+    pub(crate) max_body_size: Option<usize>,
+}
+
+impl RouteLimits {
+    /// Applies the overrides to the servers limits.
+    pub(crate) fn apply(&self, limits: Limits) -> Limits {
+        Limits {
+            max_request_line: limits.max_request_line,
+            max_header_count: self.max_header_count.unwrap_or(limits.max_header_count),
+            max_header_size: self.max_header_size.unwrap_or(limits.max_header_size),
+            max_body_size: self.max_body_size.unwrap_or(limits.max_body_size),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::Write,
+        net::{Shutdown, TcpStream},
+    };
+
+    use crate::{
+        internal::test_util::{read, send},
+        middleware::{MiddleResult, Middleware},
+        Method, Request, Response, Server,
+    };
+
+    #[test]
+    fn test_request_limits() {
+        let mut server = Server::<()>::new("localhost", 0)
+            .max_request_line(32)
+            .max_header_count(2)
+            .max_body_size(4);
+        server.route(Method::POST, "/", |req| {
+            Response::new().text(req.body_str())
+        });
+        server
+            .route(Method::POST, "/upload", |req| {
+                Response::new().text(req.body.len())
+            })
+            .max_body_size(16)
+            .max_header_count(3);
+
+        let server = server.start_background(1).unwrap();
+        // The requests don't close the connection, so the server is told there are no more of them
+        let send = |request: &str| {
+            let mut stream = TcpStream::connect(server.addr()).unwrap();
+            stream.write_all(request.as_bytes()).unwrap();
+            stream.shutdown(Shutdown::Write).unwrap();
+            read(&mut stream)
+        };
+
+        let status = |request: &str| send(request)[9..12].to_owned();
+        assert_eq!(
+            status("POST / HTTP/1.1\r\nContent-Length: 4\r\n\r\nabcd"),
+            "200"
+        );
+        assert_eq!(
+            status("POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nabcde"),
+            "413"
+        );
+        assert_eq!(
+            status("POST /upload HTTP/1.1\r\nContent-Length: 8\r\n\r\nabcdefgh"),
+            "200"
+        );
+        assert_eq!(
+            status(&format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(32))),
+            "414"
+        );
+        assert_eq!(
+            status("POST / HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n"),
+            "431"
+        );
+        assert_eq!(
+            status("POST /upload HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n"),
+            "200"
+        );
+
+        server.stop().unwrap();
+    }
+
+    #[test]
+    fn test_limits_before_middleware() {
+        struct Alias;
+        impl Middleware for Alias {
+            fn pre(&self, req: &mut Request) -> MiddleResult {
+                if req.path == "/alias" {
+                    req.path = "/upload".to_owned();
+                }
+                MiddleResult::Continue
+            }
+        }
+
+        let mut server = Server::<()>::new("localhost", 0).max_body_size(4);
+        server
+            .route(Method::POST, "/upload", |req| {
+                Response::new().text(req.body.len())
+            })
+            .max_body_size(16);
+        Alias.attach(&mut server);
+
+        let server = server.start_background(1).unwrap();
+        let post = |path: &str, body: &str| {
+            let request = format!(
+                "POST {path} HTTP/1.1\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            send(server.addr(), request)
+        };
+
+        // The rewritten request is handled by `/upload`, but read with the limits of the path it was sent to
+        assert!(post("/alias", "abcd").ends_with("\r\n\r\n4"));
+        assert!(post("/alias", "abcdefgh").starts_with("HTTP/1.1 413"));
+        assert!(post("/upload", "abcdefgh").ends_with("\r\n\r\n8"));
+
+        server.stop().unwrap();
+    }
+}
@@ -1,20 +1,26 @@
 use std::{
     borrow::Cow,
     cell::RefCell,
-    fmt::Debug,
-    io::{BufRead, BufReader, Read},
-    net::{SocketAddr, TcpStream},
+    fmt::{Debug, Display},
+    io::{self, BufRead, Read, Write},
+    result,
     str::FromStr,
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
 use crate::{
     consts::BUFF_SIZE,
     cookie::CookieJar,
-    error::{ParseError, Result, StreamError},
+    error::{ParamError, ParseError, Result, StreamError},
     header::{HeaderType, Headers},
-    internal::common::ForceLock,
-    Cookie, Error, Header, Method, Query,
+    internal::{
+        body::{BodyReader, Framing, RequestBody},
+        common::ForceLock,
+        socket::{Socket, SocketReader},
+    },
+    version::VersionError,
+    Cookie, Error, Header, HttpVersion, Method, PeerAddr, ProxyInfo, Query, Server, TlsInfo,
 };
 
 /// Http Request
@@ -26,13 +32,15 @@ pub struct Request {
     /// The query string is not included, its in the `query` field.
     pub path: String,
 
-    /// HTTP version string.
-    /// Should usually be "HTTP/1.1".
-    pub version: String,
+    /// HTTP version of the request.
+    pub version: HttpVersion,
 
     /// Path Params, filled by the router
     pub(crate) path_params: RefCell<Vec<(String, String)>>,
 
+    /// Name of the route handling the request, filled by the router
+    pub(crate) route_name: Option<String>,
+
     /// Request Query.
     pub query: Query,
 
@@ -44,22 +52,79 @@ pub struct Request {
     pub cookies: CookieJar,
 
     /// Request body, as a static byte vec.
+    /// Chunked bodies are decoded before being stored here.
+    /// Empty for routes with a streaming body (see [`crate::Route::stream_body`]), use [`Request::body_reader`] instead.
     pub body: Arc<Vec<u8>>,
 
-    /// Client socket address.
+    /// Trailers sent after a chunked request body.
+    /// Empty if the body was not chunked or no trailers were sent.
+    /// For streaming bodies, use [`RequestBody::trailers`] instead.
+    pub trailers: Headers,
+
+    /// Reader for the body, if it is streamed from the connection.
+    pub(crate) body_stream: Option<RequestBody>,
+
+    /// If the client sent `Expect: 100-continue` and is waiting for a `100 Continue` response before sending the body.
+    pub(crate) continue_pending: bool,
+
+    /// If the body should be read into `body` once the client has been told to continue.
+    pub(crate) body_deferred: bool,
+
+    /// Client address.
     /// If you are using a reverse proxy, this will be the address of the proxy (often localhost).
-    pub address: SocketAddr,
+    /// Clients connected over a Unix domain socket will have a [`PeerAddr::Unix`] address.
+    /// On listeners with the PROXY protocol enabled (See [`crate::Server::proxy_protocol`]), this is the client address sent by the proxy.
+    pub address: PeerAddr,
 
-    /// The raw tcp socket
-    pub socket: Arc<Mutex<TcpStream>>,
+    /// Addresses sent by the proxy with the PROXY protocol, including the address of the proxy itself.
+    /// Only set for connections to a listener with the PROXY protocol enabled, see [`crate::Server::proxy_protocol`].
+    pub proxy: Option<ProxyInfo>,
+
+    /// Information about the TLS session, if the request arrived over TLS.
+    pub tls: Option<TlsInfo>,
+
+    /// The raw socket
+    pub socket: Arc<Mutex<Socket>>,
 }
 
 impl Request {
-    pub(crate) fn keep_alive(&self) -> bool {
+    /// Checks if the client wants to keep the connection open after this request.
+    /// HTTP/1.1 connections are persistent unless the client sends `Connection: close`, while HTTP/1.0 connections are closed unless the client sends `Connection: keep-alive`.
+    pub fn keep_alive(&self) -> bool {
+        if self.has_connection_token("close") {
+            return false;
+        }
+
+        self.version.keep_alive_default() || self.has_connection_token("keep-alive")
+    }
+
+    /// Checks if the `Connection` header(s) of the request contain a token, ignoring case.
+    /// The header is a comma separated list, like `keep-alive, Upgrade`.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Request, Response, Method, Server};
+    /// # let mut server = Server::<()>::new("localhost", 8080);
+    /// server.route(Method::GET, "/ws", |req| {
+    ///     if !req.has_connection_token("upgrade") {
+    ///         return Response::new().status(400).text("Expected an upgrade");
+    ///     }
+    ///
+    ///     // ...
+    ///     # Response::new()
+    /// });
+    /// ```
+    pub fn has_connection_token(&self, token: &str) -> bool {
         self.headers
-            .get(HeaderType::Connection)
-            .map(|i| i.to_lowercase() == "keep-alive")
-            .unwrap_or(false)
+            .iter()
+            .filter(|x| x.name == HeaderType::Connection)
+            .flat_map(|x| x.value.split(','))
+            .any(|x| x.trim().eq_ignore_ascii_case(token))
+    }
+
+    /// Checks if the request arrived over an encrypted (TLS) connection.
+    pub fn is_tls(&self) -> bool {
+        self.tls.is_some()
     }
 
     /// Get a path parameter by its name.
@@ -91,6 +156,118 @@ impl Request {
             .map(|i| i.1.to_owned())
     }
 
+    /// Get a path parameter by its name, parsed into `T`.
+    /// Returns a [`ParamError`] if the route has no path param with this name, or its value can't be parsed.
+    ///
+    /// Path params can also be constrained to a type in the route path (like `{id:u64}`), so requests with values of the wrong type fall through to other routes instead.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Response, Method, Server, Status};
+    /// # let mut server = Server::<()>::new("localhost", 8080);
+    /// server.route(Method::GET, "/square/{number}", |req| {
+    ///     match req.param_as::<i64>("number") {
+    ///         Ok(number) => Response::new().text(number * number),
+    ///         Err(e) => Response::new().status(Status::BadRequest).text(e),
+    ///     }
+    /// });
+    /// ```
+    pub fn param_as<T>(&self, name: impl AsRef<str>) -> result::Result<T, ParamError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let name = name.as_ref();
+        let value = self
+            .param(name)
+            .ok_or_else(|| ParamError::Missing(name.to_owned()))?;
+
+        value.parse().map_err(|e: T::Err| ParamError::Invalid {
+            name: name.to_owned(),
+            error: e.to_string(),
+            value,
+        })
+    }
+
+    /// Gets the name of the route handling the request, if it has one (See [`crate::Route::name`]).
+    /// Available to the route handler and the middleware, except for the `pre` hooks of the servers middleware, which run before the route is known.
+    pub fn route_name(&self) -> Option<&str> {
+        self.route_name.as_deref()
+    }
+
+    /// Gets a reader over the body of the request.
+    /// For routes with a streaming body (see [`crate::Route::stream_body`]), this reads the body from the connection as it arrives.
+    /// All readers of a streaming body share their position, so each byte of the body can only be read once.
+    /// For all other routes, this reads from [`Request::body`].
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use std::io::Read;
+    /// # use afire::{Server, Response, Method};
+    /// # let mut server = Server::<()>::new("localhost", 8080);
+    /// server
+    ///     .route(Method::POST, "/count", |req| {
+    ///         // Count the newlines in the body without keeping it in memory
+    ///         let mut lines = 0;
+    ///         let mut buf = [0; 1024];
+    ///         let mut body = req.body_reader();
+    ///         loop {
+    ///             match body.read(&mut buf) {
+    ///                 Ok(0) => break,
+    ///                 Ok(n) => lines += buf[..n].iter().filter(|x| **x == b'\n').count(),
+    ///                 Err(_) => return Response::new().status(400),
+    ///             }
+    ///         }
+    ///
+    ///         Response::new().text(lines)
+    ///     })
+    ///     .stream_body();
+    /// ```
+    pub fn body_reader(&self) -> RequestBody {
+        match &self.body_stream {
+            Some(i) => i.share(),
+            None => RequestBody::buffered(self.body.clone(), self.trailers.clone()),
+        }
+    }
+
+    /// Sends a `100 Continue` response if the client is waiting for one, then reads the body if it is not streamed.
+    /// The body has to be read within `body_timeout` of sending the `100 Continue`.
+    pub(crate) fn continue_body(&mut self, body_timeout: Option<Duration>) -> Result<()> {
+        if !self.continue_pending {
+            return Ok(());
+        }
+
+        trace!(Level::Debug, "Sending 100 Continue");
+        self.socket
+            .force_lock()
+            .write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+        self.continue_pending = false;
+        if let Some(stream) = &self.body_stream {
+            stream.set_deadline(body_timeout);
+        }
+
+        if !self.body_deferred {
+            return Ok(());
+        }
+
+        self.body_deferred = false;
+        if let Some(stream) = self.body_stream.take() {
+            let (body, trailers) = stream.read_all()?;
+            self.body = Arc::new(body);
+            self.trailers = trailers;
+        }
+
+        Ok(())
+    }
+
+    /// Reads and discards the unread rest of a streaming body, so the next request can be read from the connection.
+    pub(crate) fn drain_body(&self) -> io::Result<()> {
+        match &self.body_stream {
+            Some(i) => i.drain(),
+            None => Ok(()),
+        }
+    }
+
     /// Gets the body of the request as a string.
     /// This uses the [`String::from_utf8_lossy`] method, so it will replace invalid UTF-8 characters with the unicode replacement character (�).
     /// If you want to use a different encoding or handle invalid characters, use a string method on the body field.
@@ -98,33 +275,46 @@ impl Request {
         String::from_utf8_lossy(&self.body)
     }
 
-    /// Read a request from a TcpStream.
-    pub(crate) fn from_socket(raw_stream: Arc<Mutex<TcpStream>>) -> Result<Self> {
-        let stream = raw_stream.force_lock();
+    /// Read a request from a connection's reader.
+    /// Any bytes read past the end of the request are left in the reader for the next request.
+    /// The size of the request is checked against the servers [`crate::Limits`], with the overrides of the route the request will be handled by.
+    /// If that route streams its body, the body is left in the reader for the handler to read.
+    pub(crate) fn from_socket<State>(
+        socket_reader: &Arc<Mutex<SocketReader>>,
+        server: &Server<State>,
+    ) -> Result<Self>
+    where
+        State: 'static + Send + Sync,
+    {
+        let mut reader = socket_reader.force_lock();
+        let reader = &mut *reader;
+        let raw_stream = reader.get_ref().socket.clone();
 
         trace!(Level::Debug, "Reading header");
-        let peer_addr = stream.peer_addr()?;
-        let mut reader = BufReader::new(&*stream);
-        let mut request_line = Vec::with_capacity(BUFF_SIZE);
-        reader
-            .read_until(10, &mut request_line)
-            .map_err(|_| StreamError::UnexpectedEof)?;
-
+        let request_line = read_line(
+            reader,
+            server.limits.max_request_line,
+            ParseError::RequestLineTooLong,
+        )?;
         let (method, path, query, version) = parse_request_line(&request_line)?;
+        // Middleware runs after the request is read, so it can't change the route the limits come from
+        let route = server.find_route(method, &path);
+        let limits = route.map_or(server.limits, |x| x.limits(server.limits));
+        let stream_body = route.is_some_and(|x| x.stream_body);
 
         let mut headers = Vec::new();
         let mut cookies = Vec::new();
-        loop {
-            let mut buff = Vec::with_capacity(BUFF_SIZE);
-            reader
-                .read_until(10, &mut buff)
-                .map_err(|_| StreamError::UnexpectedEof)?;
-            let line = String::from_utf8_lossy(&buff);
-            if line.len() <= 2 {
+        for i in 0.. {
+            let line = read_line(reader, limits.max_header_size, ParseError::HeaderTooLarge)?;
+            if line.is_empty() {
                 break;
             }
 
-            let header = Header::from_string(&line[..line.len() - 2])?;
+            if i >= limits.max_header_count {
+                return Err(ParseError::TooManyHeaders.into());
+            }
+
+            let header = Header::from_string(String::from_utf8_lossy(&line))?;
             if header.name != HeaderType::Cookie {
                 headers.push(header);
                 continue;
@@ -133,30 +323,56 @@ impl Request {
             cookies.extend(Cookie::from_string(&header.value));
         }
 
-        let content_len = headers
-            .iter()
-            .find(|i| i.name == HeaderType::ContentLength)
-            .map(|i| i.value.parse::<usize>().unwrap_or(0))
-            .unwrap_or(0);
-        let mut body = vec![0; content_len];
-
-        if content_len > 0 {
-            reader
-                .read_exact(&mut body)
-                .map_err(|_| StreamError::UnexpectedEof)?;
+        // The time to read the head is limited by the header timeout set when the connection started waiting for the request
+        reader.get_mut().set_deadline(server.body_timeout);
+
+        let headers = Headers(headers);
+        let framing = Framing::from_headers(&headers)?;
+        if let Framing::Length(i) = framing {
+            if i > limits.max_body_size as u64 {
+                return Err(ParseError::BodyTooLarge.into());
+            }
         }
 
+        // Clients sending `Expect: 100-continue` wait for the server before sending the body.
+        // The body is read once pre-middleware has run and a route has been found for the request.
+        let continue_pending = framing != Framing::None
+            && version >= HttpVersion::Http11
+            && headers
+                .get(HeaderType::Expect)
+                .is_some_and(|x| x.eq_ignore_ascii_case("100-continue"));
+
+        let mut body_reader = BodyReader::new(framing, limits);
+        let (body, trailers, body_stream) = if stream_body || continue_pending {
+            let stream = RequestBody::stream(socket_reader.clone(), body_reader);
+            (Vec::new(), Headers(Vec::new()), Some(stream))
+        } else {
+            let body = body_reader.read_all(reader)?;
+            (body, Headers(body_reader.take_trailers()), None)
+        };
+
+        let stream = raw_stream.force_lock();
+        let peer_addr = stream.peer_addr()?;
+        let tls = stream.tls_info();
         drop(stream);
+
         Ok(Self {
             method,
             path,
             version,
             path_params: RefCell::new(Vec::new()),
+            route_name: None,
             query,
-            headers: Headers(headers),
+            headers,
             cookies: CookieJar(cookies),
             body: Arc::new(body),
+            trailers,
+            body_stream,
+            continue_pending,
+            body_deferred: continue_pending && !stream_body,
             address: peer_addr,
+            proxy: None,
+            tls,
             socket: raw_stream,
         })
     }
@@ -169,17 +385,45 @@ impl Debug for Request {
             .field("path", &self.path)
             .field("version", &self.version)
             .field("path_params", &self.path_params.borrow())
+            .field("route_name", &self.route_name)
             .field("query", &self.query)
             .field("headers", &self.headers)
             .field("cookies", &*self.cookies)
             .field("body", &self.body)
+            .field("trailers", &self.trailers)
             .field("address", &self.address)
+            .field("proxy", &self.proxy)
+            .field("tls", &self.tls)
             .finish()
     }
 }
 
+/// Reads a line of at most `max` bytes (not including the line ending) from the reader.
+/// The line ending is not included in the returned line.
+/// If the connection is closed before the end of the line, whatever was read is returned.
+fn read_line(reader: &mut SocketReader, max: usize, error: ParseError) -> Result<Vec<u8>> {
+    let mut line = Vec::with_capacity(BUFF_SIZE);
+    reader
+        .take(max as u64 + 2)
+        .read_until(b'\n', &mut line)
+        .map_err(|e| StreamError::from_read(&e))?;
+
+    if line.last() == Some(&b'\n') {
+        line.pop();
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+    }
+
+    if line.len() > max {
+        return Err(error.into());
+    }
+
+    Ok(line)
+}
+
 /// Parse a request line into a method, path, query, and version
-pub(crate) fn parse_request_line(bytes: &[u8]) -> Result<(Method, String, Query, String)> {
+pub(crate) fn parse_request_line(bytes: &[u8]) -> Result<(Method, String, Query, HttpVersion)> {
     let request_line = String::from_utf8_lossy(bytes);
     let mut parts = request_line.split_whitespace();
 
@@ -220,7 +464,10 @@ pub(crate) fn parse_request_line(bytes: &[u8]) -> Result<(Method, String, Query,
 
     let query = Query::from_body(&final_query);
     let version = match parts.next() {
-        Some(i) => i.to_owned(),
+        Some(i) => HttpVersion::from_str(i).map_err(|e| match e {
+            VersionError::Invalid => ParseError::InvalidVersion,
+            VersionError::Unsupported => ParseError::UnsupportedVersion,
+        })?,
         None => return Err(Error::Parse(ParseError::NoVersion)),
     };
 
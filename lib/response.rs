@@ -1,15 +1,16 @@
 use std::cell::RefCell;
 use std::fmt::{self, Debug, Display, Formatter};
 use std::io::{ErrorKind, Read, Write};
-use std::net::TcpStream;
 use std::sync::{Arc, Mutex};
 
 use crate::consts;
 use crate::header::{HeaderType, Headers};
 use crate::http::status::Status;
 use crate::{
-    error::Result, header::headers_to_string, internal::handle::Writeable, Content, Header,
-    SetCookie,
+    error::Result,
+    header::headers_to_string,
+    internal::{common::ForceLock, handle::Writeable, socket::Socket},
+    Content, Header, HttpVersion, SetCookie,
 };
 
 /// Http Response
@@ -276,12 +277,22 @@ impl Response {
     }
 
     // TODO: Make crate local
-    /// Writes a Response to a TcpStream.
+    /// Writes a Response to a socket.
     /// Will take care of adding default headers and closing the connection if needed.
-    pub fn write(
+    pub fn write(&mut self, stream: Arc<Mutex<Socket>>, default_headers: &[Header]) -> Result<()> {
+        let keep_alive = self.flag != ResponseFlag::Close;
+        self.write_for(stream, default_headers, HttpVersion::Http11, keep_alive)
+    }
+
+    /// Writes a Response to a socket, for a client using HTTP `version`.
+    /// The `Connection` header is set to let the client know if the connection will be kept alive.
+    /// Clients that don't support chunked transfer-encoding get streaming bodies as is, so the connection must be closed afterwards.
+    pub(crate) fn write_for(
         &mut self,
-        stream: Arc<Mutex<TcpStream>>,
+        stream: Arc<Mutex<Socket>>,
         default_headers: &[Header],
+        version: HttpVersion,
+        keep_alive: bool,
     ) -> Result<()> {
         // Add default headers to response
         // Only the ones that aren't already in the response
@@ -292,18 +303,23 @@ impl Response {
         }
 
         let static_body = self.data.is_static();
+        let chunked = !static_body && version.supports_chunked();
 
         // Add content-length header to response if we are sending a static body
         if static_body && !self.headers.has(HeaderType::ContentLength) {
             self.headers.push(self.data.content_len());
         }
 
-        // Add Connection: close if response is set to close
-        if self.flag == ResponseFlag::Close && !self.headers.has(HeaderType::Connection) {
-            self.headers.push(Header::new("Connection", "close"));
+        // Let the client know if the connection will be closed or kept alive, if that's not what it expects
+        if !self.headers.has(HeaderType::Connection) {
+            if !keep_alive {
+                self.headers.push(Header::new("Connection", "close"));
+            } else if !version.keep_alive_default() {
+                self.headers.push(Header::new("Connection", "keep-alive"));
+            }
         }
 
-        if !static_body && !self.headers.has(HeaderType::TransferEncoding) {
+        if chunked && !self.headers.has(HeaderType::TransferEncoding) {
             self.headers
                 .push(Header::new("Transfer-Encoding", "chunked"));
         }
@@ -318,9 +334,8 @@ impl Response {
             headers_to_string(&self.headers)
         );
 
-        let mut stream = stream.lock().unwrap();
-        stream.write_all(response.as_bytes())?;
-        self.data.write(&mut stream)?;
+        stream.force_lock().write_all(response.as_bytes())?;
+        self.data.write(&stream, chunked)?;
 
         Ok(())
     }
@@ -338,7 +353,7 @@ impl ResponseBody {
     }
 
     /// Checks if the ResponseBody is static.
-    fn is_static(&self) -> bool {
+    pub(crate) fn is_static(&self) -> bool {
         matches!(self, ResponseBody::Static(_))
     }
 
@@ -352,11 +367,13 @@ impl ResponseBody {
         Header::new("Content-Length", len.to_string())
     }
 
-    /// Writes a ResponseBody to a TcpStream.
+    /// Writes a ResponseBody to a socket.
     /// Either in one go if it is static or in chunks if it is a stream.
-    fn write(&mut self, stream: &mut TcpStream) -> Result<()> {
+    /// Streams are only chunk encoded if `chunked` is set, otherwise the data is written as is.
+    /// The socket is only locked while writing, so streams can read from the connection (see [`crate::RequestBody`]).
+    fn write(&mut self, stream: &Mutex<Socket>, chunked: bool) -> Result<()> {
         match self {
-            ResponseBody::Static(data) => stream.write_all(data)?,
+            ResponseBody::Static(data) => stream.force_lock().write_all(data)?,
             ResponseBody::Stream(data) => {
                 let data = data.get_mut();
                 loop {
@@ -368,14 +385,21 @@ impl ResponseBody {
                         Err(e) => return Err(e.into()),
                     };
 
+                    if !chunked {
+                        stream.force_lock().write_all(&chunk[..read])?;
+                        continue;
+                    }
+
                     let mut section = format!("{read:X}\r\n").as_bytes().to_vec();
                     section.extend(&chunk[..read]);
                     section.extend(b"\r\n");
 
-                    stream.write_all(&section)?;
+                    stream.force_lock().write_all(&section)?;
                 }
 
-                stream.write_all(b"0\r\n\r\n")?;
+                if chunked {
+                    stream.force_lock().write_all(b"0\r\n\r\n")?;
+                }
             }
         };
 
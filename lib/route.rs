@@ -1,8 +1,13 @@
+use std::any::type_name;
 use std::fmt::{self, Debug};
-use std::rc::Rc;
 use std::sync::Arc;
 
-use crate::{path::Path, Method, Request, Response};
+use crate::{
+    limits::{Limits, RouteLimits},
+    path::Path,
+    trace::emoji,
+    Method, Middleware, Request, Response,
+};
 
 type StatelessRoute = Box<dyn Fn(&Request) -> Response + Send + Sync>;
 type StatefulRoute<State> = Box<dyn Fn(Arc<State>, &Request) -> Response + Send + Sync>;
@@ -14,18 +19,55 @@ pub enum RouteType<State> {
 
 /// Defines a route.
 ///
-/// You should not use this directly.
-/// It will be created automatically when using [`crate::Server::route`] or [`crate::Server::stateful_route`].
-#[derive(Debug)]
+/// It will be created automatically when using [`crate::Server::route`] or [`crate::Server::stateful_route`], which return a reference to the new route.
+/// This can be used to override the servers [`Limits`], attach middleware or set a name for just this route.
+///
+/// The limits and [`Route::stream_body`] are taken from the route matching the request as it was received, as they are needed to read it.
+/// Middleware runs after that, so changing the method or path of a request in middleware changes which route handles it, but not the limits it was read with.
+/// ## Example
+/// ```rust
+/// # use afire::{Server, Response, Method, Request};
+/// # use afire::middleware::{Middleware, MiddleResult};
+/// struct LogUploads;
+///
+/// impl Middleware for LogUploads {
+///     fn end(&self, req: &Request, _res: &Response) {
+///         println!("[{}] {} bytes", req.route_name().unwrap(), req.body.len());
+///     }
+/// }
+///
+/// # let mut server = Server::<()>::new("localhost", 8080);
+/// // Allow uploads of up to 100 MiB on just this route
+/// server
+///     .route(Method::POST, "/upload", |req| {
+///         Response::new().text(format!("Received {} bytes", req.body.len()))
+///     })
+///     .name("upload")
+///     .max_body_size(100 * 1024 * 1024)
+///     .middleware(LogUploads);
+/// ```
 pub struct Route<State> {
     /// Route Method (GET, POST, ANY, etc.)
-    method: Method,
+    pub(crate) method: Method,
 
     /// Route path, in its tokenized form.
-    path: Path,
+    pub(crate) path: Path,
 
     /// Route Handler, either stateless or stateful.
     pub(crate) handler: RouteType<State>,
+
+    /// Overrides of the servers request size limits.
+    pub(crate) limits: RouteLimits,
+
+    /// If the request body should be streamed to the handler instead of being buffered.
+    pub(crate) stream_body: bool,
+
+    /// Middleware that only runs for this route, in the order it runs.
+    /// Starts with the middleware of the groups the route is in (See [`crate::Group`]), followed by the routes own.
+    pub(crate) middleware: Vec<Arc<dyn Middleware + Send + Sync>>,
+
+    /// Name of the route, available to middleware and handlers through [`Request::route_name`].
+    pub(crate) name: Option<String>,
 }
 
 impl<State> Route<State> {
@@ -35,6 +77,10 @@ impl<State> Route<State> {
             method,
             path: Path::new(path),
             handler: RouteType::Stateless(handler),
+            limits: RouteLimits::default(),
+            stream_body: false,
+            middleware: Vec::new(),
+            name: None,
         }
     }
 
@@ -48,21 +94,111 @@ impl<State> Route<State> {
             method,
             path: Path::new(path),
             handler: RouteType::Stateful(handler),
+            limits: RouteLimits::default(),
+            stream_body: false,
+            middleware: Vec::new(),
+            name: None,
         }
     }
 
+    /// Set the max size of request bodies for this route, overriding [`Limits::max_body_size`].
+    pub fn max_body_size(&mut self, max_body_size: usize) -> &mut Self {
+        self.limits.max_body_size = Some(max_body_size);
+        self
+    }
+
+    /// Set the max number of headers in requests for this route, overriding [`Limits::max_header_count`].
+    pub fn max_header_count(&mut self, max_header_count: usize) -> &mut Self {
+        self.limits.max_header_count = Some(max_header_count);
+        self
+    }
+
+    /// Set the max size of a single header line in requests for this route, overriding [`Limits::max_header_size`].
+    pub fn max_header_size(&mut self, max_header_size: usize) -> &mut Self {
+        self.limits.max_header_size = Some(max_header_size);
+        self
+    }
+
+    /// Stream the request body to the handler instead of reading it into [`Request::body`] before the handler is called.
+    /// The handler can then read the body as it arrives with [`Request::body_reader`], which makes it possible to handle large uploads without keeping them in memory, or to reject them before they are read.
+    /// Any part of the body the handler does not read is discarded once the response is sent.
+    ///
+    /// [`Request::body`] will be empty for these routes, and the trailers of chunked bodies are only available through [`crate::RequestBody::trailers`].
+    /// The body is still limited by [`Limits::max_body_size`].
+    /// ## Example
+    /// ```rust
+    /// # use std::{fs::File, io};
+    /// # use afire::{Server, Response, Method};
+    /// # let mut server = Server::<()>::new("localhost", 8080);
+    /// server
+    ///     .route(Method::POST, "/upload", |req| {
+    ///         // Write the upload straight to a file
+    ///         let mut file = File::create("upload.bin").unwrap();
+    ///         let size = io::copy(&mut req.body_reader(), &mut file).unwrap();
+    ///         Response::new().text(format!("Received {} bytes", size))
+    ///     })
+    ///     .stream_body()
+    ///     .max_body_size(1024 * 1024 * 1024);
+    /// ```
+    pub fn stream_body(&mut self) -> &mut Self {
+        self.stream_body = true;
+        self
+    }
+
+    /// Attach middleware to just this route.
+    /// Its `pre` hooks run after the servers middleware and the middleware of the groups the route is in, right before the handler.
+    /// Its `post` and `end` hooks run first, before those of the groups and the server.
+    /// Like on the server, middleware attached last runs its `pre` hooks first, but its `post` and `end` hooks run in the reverse order.
+    ///
+    /// If the servers middleware aborts the `pre` hooks with [`crate::middleware::MiddleResult::Abort`], none of the route's hooks run.
+    ///
+    /// Unlike [`Middleware::attach`], this can't be overridden by the middleware.
+    pub fn middleware<T>(&mut self, middleware: T) -> &mut Self
+    where
+        T: Middleware + Send + Sync + 'static,
+    {
+        trace!(
+            "{}Adding Middleware {} to route {} {}",
+            emoji("📦"),
+            type_name::<T>(),
+            self.method,
+            self.path.raw
+        );
+
+        // Group middleware is added once the group is built, so this only has the routes own middleware
+        self.middleware.insert(0, Arc::new(middleware));
+        self
+    }
+
+    /// Set the name of the route, which is available to middleware and the handler through [`Request::route_name`].
+    /// Useful for logging or metrics, where the path of the request would be too specific.
+    pub fn name(&mut self, name: impl AsRef<str>) -> &mut Self {
+        self.name = Some(name.as_ref().to_owned());
+        self
+    }
+
+    /// Gets the limits for requests to this route, based on the servers limits.
+    pub(crate) fn limits(&self, limits: Limits) -> Limits {
+        self.limits.apply(limits)
+    }
+
     /// Checks if the route is stateful.
     pub(crate) fn is_stateful(&self) -> bool {
         matches!(self.handler, RouteType::Stateful(_))
     }
+}
 
-    /// Checks if a Request matches the route.
-    /// Returns the path parameters if it does.
-    pub(crate) fn matches(&self, req: Rc<Request>) -> Option<Vec<(String, String)>> {
-        if self.method != Method::ANY && self.method != req.method {
-            return None;
-        }
-        self.path.match_path(req.path.clone())
+impl<State> Debug for Route<State> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Route")
+            .field("method", &self.method)
+            .field("path", &self.path)
+            .field("handler", &self.handler)
+            .field("limits", &self.limits)
+            .field("stream_body", &self.stream_body)
+            .field("middleware", &self.middleware.len())
+            .field("name", &self.name)
+            .finish()
     }
 }
 
@@ -74,3 +210,63 @@ impl<State> Debug for RouteType<State> {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        internal::test_util::get,
+        middleware::{MiddleResult, Middleware},
+        Method, Request, Response, Server,
+    };
+
+    #[test]
+    fn test_route_middleware() {
+        /// Rejects requests to routes without a name, and adds its tag to the `Trace` header of responses.
+        struct Named(&'static str);
+
+        impl Middleware for Named {
+            fn pre(&self, req: &mut Request) -> MiddleResult {
+                match req.route_name() {
+                    Some(_) => MiddleResult::Continue,
+                    None => MiddleResult::Send(Response::new().status(403)),
+                }
+            }
+
+            fn post(&self, _req: &Request, res: &mut Response) -> MiddleResult {
+                match res.headers.get_mut("Trace") {
+                    Some(i) => i.push_str(self.0),
+                    None => res.headers.add("Trace", self.0),
+                }
+                MiddleResult::Continue
+            }
+        }
+
+        let mut server = Server::<()>::new("localhost", 0);
+        server.route(Method::GET, "/", |_req| Response::new().text("root"));
+        server.group("/api", |api| {
+            api.middleware(Named("g"));
+            api.route(Method::GET, "/{id}", |req| {
+                Response::new().text(format!(
+                    "{} {}",
+                    req.route_name().unwrap(),
+                    req.param("id").unwrap()
+                ))
+            })
+            .name("item")
+            .middleware(Named("2"))
+            .middleware(Named("1"));
+            api.route(Method::GET, "/", |_req| Response::new().text("unnamed"));
+        });
+        let server = server.start_background(1).unwrap();
+
+        let addr = server.addr();
+        let response = get(addr, "/api/5");
+        assert!(response.ends_with("item 5"), "{}", response);
+        assert!(response.contains("Trace: 21g\r\n"), "{}", response);
+
+        assert!(get(addr, "/api").starts_with("HTTP/1.1 403"));
+        assert!(get(addr, "/").ends_with("root"));
+
+        server.stop().unwrap();
+    }
+}
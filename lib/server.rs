@@ -1,32 +1,86 @@
 // Import STD libraries
 use std::any::type_name;
-use std::net::{IpAddr, SocketAddr, TcpListener};
+use std::io;
+use std::net::Shutdown;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener};
+#[cfg(unix)]
+use std::os::unix::{io::RawFd, net::UnixListener};
+use std::panic;
+#[cfg(any(unix, feature = "tls"))]
+use std::path::Path;
+#[cfg(unix)]
+use std::path::PathBuf;
 use std::rc::Rc;
 use std::str;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard};
+use std::thread;
 use std::time::Duration;
 
 // Import local files
+#[cfg(target_os = "linux")]
+use crate::internal::restart;
+#[cfg(feature = "tls")]
+use crate::tls::TlsConfig;
 use crate::{
-    error::Result, error::StartupError, handle::handle, header::Headers,
-    internal::common::ToHostAddress, thread_pool::ThreadPool, trace::emoji, Content, Header,
-    HeaderType, Method, Middleware, Request, Response, Route, Status, VERSION,
+    error::{AcceptError, AcceptErrorKind, Result, StartupError},
+    handle::handle,
+    header::Headers,
+    internal::{
+        common::ToHostAddress,
+        listener::{self, Inherited, Listener},
+        router::{self, Router},
+        socket::{Connection, Socket},
+    },
+    shutdown::{BackgroundServer, ShutdownHandle},
+    thread_pool::{OverflowPolicy, PoolMetrics, ThreadPool},
+    trace::emoji,
+    Content, Group, Header, HeaderType, Limits, Method, Middleware, Request, Response, Route,
+    Status, VERSION,
 };
 
 type ErrorHandler<State> =
     Box<dyn Fn(Option<Arc<State>>, &Box<Result<Rc<Request>>>, String) -> Response + Send + Sync>;
+type AcceptErrorHandler = Box<dyn Fn(&AcceptError) + Send + Sync>;
+type RouteMatch<'a, State> = (&'a Route<State>, Vec<(String, String)>);
+
+/// The first delay when backing off after running out of resources while accepting connections.
+const MIN_BACKOFF: Duration = Duration::from_millis(5);
+/// The longest delay when backing off after running out of resources while accepting connections.
+const MAX_BACKOFF: Duration = Duration::from_secs(1);
 
 /// Defines a server.
 pub struct Server<State: 'static + Send + Sync = ()> {
     /// Port to listen on.
     pub port: u16,
 
+    /// Path of a Unix domain socket to listen on.
+    /// If this is set, it is used instead of `ip` and `port`.
+    #[cfg(unix)]
+    pub unix_socket: Option<PathBuf>,
+
+    /// Permissions to set on the Unix domain socket file, for example `0o660`.
+    /// If this is None, the permissions are determined by the process's umask.
+    #[cfg(unix)]
+    pub unix_socket_permissions: Option<u32>,
+
     /// Ip address to listen on.
     pub ip: IpAddr,
 
+    /// Additional addresses to listen on, sharing the same routes.
+    /// Added with [`Server::listen`] or [`Server::listen_proxy`].
+    pub addresses: Vec<ListenAddress>,
+
+    /// Listeners that were bound outside of afire, used instead of `ip` and `port`.
+    /// Added with [`Server::tcp_listener`] or [`Server::systemd`].
+    pub(crate) inherited: Vec<Inherited>,
+
     /// Routes to handle.
     pub routes: Vec<Route<State>>,
 
+    /// The routes compiled into a tree, used to find the route for a request.
+    /// Built when the server starts.
+    pub(crate) router: RwLock<Router>,
+
     // Other stuff
     /// Middleware
     pub middleware: Vec<Box<dyn Middleware + Send + Sync>>,
@@ -37,6 +91,9 @@ pub struct Server<State: 'static + Send + Sync = ()> {
     /// Default response for internal server errors
     pub error_handler: ErrorHandler<State>,
 
+    /// Called with every error that occurs while accepting connections.
+    pub accept_error_handler: Option<AcceptErrorHandler>,
+
     /// Headers automatically added to every response.
     pub default_headers: Headers,
 
@@ -47,12 +104,81 @@ pub struct Server<State: 'static + Send + Sync = ()> {
 
     /// Socket Timeout
     pub socket_timeout: Option<Duration>,
+
+    /// How long a keep-alive connection can be idle between requests before it is closed.
+    pub idle_timeout: Option<Duration>,
+
+    /// How long a client has to send the request line and headers of a request.
+    pub header_timeout: Option<Duration>,
+
+    /// How long a client has to send the body of a request.
+    pub body_timeout: Option<Duration>,
+
+    /// Max number of requests handled on a single connection before it is closed.
+    pub max_requests: Option<usize>,
+
+    /// Max time a connection is kept open for.
+    /// Checked after each response, so in-flight requests are never cut off.
+    pub max_connection_age: Option<Duration>,
+
+    /// Limits on the size of incoming requests.
+    /// Some of these can be overridden per route, see [`Route::max_body_size`].
+    pub limits: Limits,
+
+    /// TLS config, if the server should accept HTTPS connections.
+    /// Only used for TCP listeners.
+    #[cfg(feature = "tls")]
+    pub tls: Option<TlsConfig>,
+
+    /// If connections to the servers own address start with a PROXY protocol header, which is used for the address of the client.
+    /// Also applies to a Unix domain socket or inherited listeners used in its place.
+    /// The [`Server::addresses`] have their own setting.
+    /// Disabled by default.
+    pub proxy_protocol: bool,
+
+    /// How long to wait for in-flight requests to finish after a shutdown is requested.
+    /// Connections still open after this are forcibly closed.
+    /// Defaults to 30 seconds.
+    pub shutdown_grace: Duration,
+
+    /// Max number of accepted connections waiting for a worker when using a threadpool.
+    /// Defaults to 1024.
+    pub queue_size: usize,
+
+    /// What to do with new connections when the queue is full.
+    /// Defaults to [`OverflowPolicy::Block`].
+    pub overflow_policy: OverflowPolicy,
+
+    /// Max number of worker threads the threadpool can grow to under load.
+    /// If not set (or lower than the number of threads the server was started with), the pool has a fixed size.
+    pub max_threads: Option<usize>,
+
+    /// How long a worker thread above the number the server was started with can be idle before it is stopped.
+    /// Defaults to 60 seconds.
+    pub thread_idle_timeout: Duration,
+
+    /// Handle used to stop the server.
+    pub(crate) shutdown: ShutdownHandle,
+
+    /// Metrics of the threadpool.
+    pub(crate) pool_metrics: PoolMetrics,
+}
+
+/// An additional address for a [`Server`] to listen on.
+/// Added with [`Server::listen`] or [`Server::listen_proxy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ListenAddress {
+    /// The address to bind.
+    pub addr: SocketAddr,
+    /// If connections start with a PROXY protocol header, see [`Server::proxy_protocol`].
+    pub proxy_protocol: bool,
 }
 
 /// Implementations for Server
 impl<State: Send + Sync> Server<State> {
     /// Creates a new server on the specified address and port.
-    /// `raw_ip` can be either an IP address or 'localhost', which expands to 127.0.0.1.
+    /// `raw_ip` can be an IP address (IPv4 or IPv6), 'localhost', which expands to 127.0.0.1, or a hostname to resolve.
+    /// More addresses to listen on can be added with [`Server::listen`].
     ///
     /// ## Example
     /// ```rust
@@ -66,7 +192,14 @@ impl<State: Send + Sync> Server<State> {
         Server {
             port,
             ip: raw_ip.to_address().unwrap(),
+            addresses: Vec::new(),
+            inherited: Vec::new(),
+            #[cfg(unix)]
+            unix_socket: None,
+            #[cfg(unix)]
+            unix_socket_permissions: None,
             routes: Vec::new(),
+            router: RwLock::default(),
             middleware: Vec::new(),
 
             error_handler: Box::new(|_state, _req, err| {
@@ -75,16 +208,34 @@ impl<State: Send + Sync> Server<State> {
                     .text(format!("Internal Server Error :/\nError: {err}"))
                     .content(Content::TXT)
             }),
+            accept_error_handler: None,
 
             default_headers: Headers(vec![Header::new("Server", format!("afire/{VERSION}"))]),
             keep_alive: true,
             socket_timeout: None,
+            idle_timeout: None,
+            header_timeout: None,
+            body_timeout: None,
+            max_requests: None,
+            max_connection_age: None,
+            limits: Limits::new(),
+            #[cfg(feature = "tls")]
+            tls: None,
+            proxy_protocol: false,
+            shutdown_grace: Duration::from_secs(30),
+            queue_size: 1024,
+            overflow_policy: OverflowPolicy::Block,
+            max_threads: None,
+            thread_idle_timeout: Duration::from_secs(60),
+            shutdown: ShutdownHandle::new(),
+            pool_metrics: PoolMetrics::default(),
             state: None,
         }
     }
 
     /// Starts the server without a threadpool.
-    /// This is blocking.
+    /// Connections are handled one at a time on the listener they were accepted on, with every listener after the first getting its own thread (See [`Server::listen`]).
+    /// This is blocking, it will only return once the server is shut down through its [`ShutdownHandle`] (See [`Server::shutdown_handle`]), or a fatal error occurs accepting connections (See [`Server::accept_error_handler`]).
     /// Will return an error if the server cant bind to the specified address, or of you are using stateful routes and have not set the state. (See [`Server::state`])
     ///
     /// ## Example
@@ -102,19 +253,24 @@ impl<State: Send + Sync> Server<State> {
     pub fn start(&self) -> Result<()> {
         trace!("{}Starting Server [{}:{}]", emoji("✨"), self.ip, self.port);
         self.check()?;
+        self.build_router();
 
-        let listener = TcpListener::bind(SocketAddr::new(self.ip, self.port))?;
-
-        for event in listener.incoming() {
-            handle(event?, self);
+        let mut listeners = self.bind()?;
+        let result = self.accept_all(&listeners, |socket, proxy| handle(socket, self, proxy));
+        if self.shutdown.is_restarting() {
+            listeners.iter_mut().for_each(Listener::keep_socket_file);
         }
 
-        // We should never get Here
-        unreachable!()
+        self.shutdown.wait(self.shutdown_grace);
+        self.shutdown.reset();
+        trace!("{}Server stopped", emoji("🛑"));
+        Ok(result?)
     }
 
     /// Start the server with a threadpool of `threads` threads.
+    /// If [`Server::max_threads`] is set, the pool grows while all workers are busy and shrinks back to `threads` once they are idle.
     /// Just like [`Server::start`], this is blocking.
+    /// Once a shutdown is requested, in-flight requests are given [`Server::shutdown_grace`] to finish before the workers are joined.
     /// Will return an error if the server cant bind to the specified address, or of you are using stateful routes and have not set the state. (See [`Server::state`])
     ///
     /// ## Example
@@ -140,18 +296,370 @@ impl<State: Send + Sync> Server<State> {
             threads
         );
         self.check()?;
+        self.build_router();
+
+        let listeners = self.bind()?;
+        Ok(self.serve_threaded(listeners, threads)?)
+    }
+
+    /// Starts the server with a threadpool of `threads` threads on a background thread.
+    /// Unlike [`Server::start_threaded`], this is not blocking.
+    /// The listener is bound before this returns, so the server is ready to accept connections right away.
+    ///
+    /// If the port is set to `0`, the OS will pick a free port, which can be read back with [`BackgroundServer::addr`].
+    /// This is useful for tests, where every test can start its own isolated server.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Server, Response, Method};
+    /// # use std::{io::{Read, Write}, net::TcpStream};
+    /// let mut server = Server::<()>::new("localhost", 0);
+    /// server.route(Method::GET, "/", |_req| Response::new().text("Hello"));
+    ///
+    /// // Start the server in the background
+    /// let server = server.start_background(1).unwrap();
+    ///
+    /// // Send it a request
+    /// let mut stream = TcpStream::connect(server.addr()).unwrap();
+    /// stream.write_all(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n").unwrap();
+    /// let mut response = String::new();
+    /// stream.read_to_string(&mut response).unwrap();
+    /// assert!(response.ends_with("Hello"));
+    ///
+    /// // Stop the server and wait for it to finish
+    /// server.stop().unwrap();
+    /// ```
+    pub fn start_background(self, threads: usize) -> Result<BackgroundServer> {
+        trace!(
+            "{}Starting Background Server [{}:{}] ({} threads)",
+            emoji("✨"),
+            self.ip,
+            self.port,
+            threads
+        );
+        self.check()?;
+        self.build_router();
+
+        let listeners = self.bind()?;
+        let addrs = listeners
+            .iter()
+            .map(|x| x.local_addr())
+            .collect::<io::Result<_>>()?;
+        let shutdown = self.shutdown.clone();
+        let metrics = self.pool_metrics.clone();
+        let thread = thread::Builder::new()
+            .name("afire server".to_owned())
+            .spawn(move || self.serve_threaded(listeners, threads))?;
+
+        Ok(BackgroundServer::new(addrs, shutdown, metrics, thread))
+    }
+
+    /// Handles requests from a single connection on the current thread, returning once the connection is closed.
+    /// This lets you serve requests over your own transports by implementing [`Connection`] for them.
+    /// It can also be used to test a server with in-memory connections.
+    /// Will return an error if you are using stateful routes and have not set the state. (See [`Server::state`])
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use afire::{Server, Response, Method};
+    /// # use std::net::TcpListener;
+    /// let mut server = Server::<()>::new("localhost", 8080);
+    /// server.route(Method::GET, "/", |_req| Response::new().text("Hello"));
+    ///
+    /// // Accept connections yourself
+    /// let listener = TcpListener::bind("localhost:8080").unwrap();
+    /// for stream in listener.incoming() {
+    ///     server.handle_connection(stream.unwrap()).unwrap();
+    /// }
+    /// ```
+    pub fn handle_connection(&self, connection: impl Connection) -> Result<()> {
+        self.check()?;
+        if self.router().is_stale(&self.routes) {
+            self.build_router();
+        }
 
-        let listener = TcpListener::bind(SocketAddr::new(self.ip, self.port))?;
-        let pool = ThreadPool::new(threads);
+        handle(Box::new(connection), self, self.proxy_protocol);
+        Ok(())
+    }
+
+    /// Binds the listeners, starting with the servers address (or Unix domain socket if one is set), followed by the [`Server::addresses`].
+    /// If the server has inherited listeners (See [`Server::tcp_listener`]), they are used in place of the servers own address.
+    /// Addresses an inherited listener is already bound to are not bound again.
+    ///
+    /// Unspecified IPv6 addresses (`[::]`) are bound before the rest, as on most systems they accept IPv4 connections too.
+    /// If binding the unspecified IPv4 address (`0.0.0.0`) on the same port then fails, it is skipped, as it is already covered.
+    fn bind(&self) -> Result<Vec<Listener>> {
+        let inherited = !self.inherited.is_empty();
+        #[cfg(unix)]
+        let unix = self.unix_socket.as_ref().filter(|_| !inherited);
+        #[cfg(not(unix))]
+        let unix = None::<()>;
+
+        // The servers own address is ordered first, then the extra addresses in the order they were added
+        let mut addrs = Vec::new();
+        if !inherited && unix.is_none() {
+            addrs.push((
+                0,
+                ListenAddress {
+                    addr: SocketAddr::new(self.ip, self.port),
+                    proxy_protocol: self.proxy_protocol,
+                },
+            ));
+        }
+
+        let covered = self
+            .inherited
+            .iter()
+            .filter_map(|x| match x {
+                Inherited::Tcp(i) => i.local_addr().ok(),
+                #[cfg(unix)]
+                Inherited::Unix(_) => None,
+            })
+            .collect::<Vec<_>>();
+        addrs.extend(
+            (self.addresses.iter().enumerate())
+                .map(|(i, &x)| (i + 1, x))
+                .filter(|(_, x)| !covered.contains(&x.addr)),
+        );
+        let dual_stack = |x: &SocketAddr| x.ip() == IpAddr::V6(Ipv6Addr::UNSPECIFIED);
+        let mut order = (0..addrs.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&x| !dual_stack(&addrs[x].1.addr));
+
+        let mut listeners = Vec::with_capacity(addrs.len() + self.inherited.len() + 1);
+        for i in &self.inherited {
+            // Listeners are cloned, so the server can be started more than once.
+            // Ones bound to one of the addresses keep its settings, the rest replace the servers own address.
+            let (key, listener, proxy_protocol) = match i {
+                Inherited::Tcp(i) => {
+                    let (key, proxy_protocol) = self
+                        .address_of(i)
+                        .map_or((0, self.proxy_protocol), |(key, x)| (key, x.proxy_protocol));
+                    (key, self.tcp_listener_for(i.try_clone()?)?, proxy_protocol)
+                }
+                #[cfg(unix)]
+                Inherited::Unix(i) => (0, Listener::unix(i.try_clone()?), self.proxy_protocol),
+            };
+            listeners.push((key, listener.with_proxy_protocol(proxy_protocol)));
+        }
+
+        #[cfg(unix)]
+        if let Some(path) = unix {
+            let listener = Listener::bind_unix(path, self.unix_socket_permissions)?;
+            listeners.push((0, listener.with_proxy_protocol(self.proxy_protocol)));
+        }
+
+        for i in order {
+            let (
+                key,
+                ListenAddress {
+                    addr,
+                    proxy_protocol,
+                },
+            ) = addrs[i];
+            let listener = match TcpListener::bind(addr).and_then(|x| self.tcp_listener_for(x)) {
+                Ok(listener) => listener.with_proxy_protocol(proxy_protocol),
+                Err(e)
+                    if e.kind() == io::ErrorKind::AddrInUse
+                        && addr.ip() == IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+                        && (addrs.iter().map(|x| &x.1.addr))
+                            .chain(&covered)
+                            .any(|x| dual_stack(x) && x.port() == addr.port()) =>
+                {
+                    trace!(
+                        "{}Skipping {}, already covered by dual-stack listener",
+                        emoji("👂"),
+                        addr
+                    );
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+            listeners.push((key, listener));
+        }
+
+        listeners.sort_by_key(|x| x.0);
+        let listeners = listeners.into_iter().map(|x| x.1).collect::<Vec<_>>();
+        for listener in &listeners {
+            let addr = listener.local_addr()?;
+            trace!("{}Listening on {}", emoji("👂"), addr);
+            self.shutdown.add_addr(addr);
+            #[cfg(target_os = "linux")]
+            self.shutdown.add_handoff(listener.try_clone_fd()?);
+        }
+
+        Ok(listeners)
+    }
+
+    /// Finds the entry of the [`Server::addresses`] an inherited listener is bound to, along with its position in the order of the listeners.
+    fn address_of(&self, listener: &TcpListener) -> Option<(usize, &ListenAddress)> {
+        let addr = listener.local_addr().ok()?;
+        (self.addresses.iter().enumerate())
+            .find(|(_, x)| x.addr == addr)
+            .map(|(i, x)| (i + 1, x))
+    }
+
+    /// Wraps a bound TCP listener, so it accepts TLS connections if the server has a TLS config.
+    fn tcp_listener_for(&self, listener: TcpListener) -> io::Result<Listener> {
+        #[cfg(feature = "tls")]
+        if let Some(tls) = &self.tls {
+            return Listener::tls(listener, tls.clone());
+        }
+
+        Ok(Listener::tcp(listener))
+    }
+
+    /// Accepts connections from all listeners until the server is shut down, passing them to `on_accept` along with if they start with a PROXY protocol header.
+    /// The first listener is served on the current thread, the rest each get their own thread.
+    /// If one listener has a fatal error, the server is shut down and the error is returned.
+    fn accept_all(
+        &self,
+        listeners: &[Listener],
+        on_accept: impl Fn(Socket, bool) + Sync,
+    ) -> io::Result<()> {
+        let (first, rest) = listeners.split_first().expect("No listeners");
+        let on_accept = &on_accept;
+
+        thread::scope(|s| {
+            let mut threads = Vec::with_capacity(rest.len());
+            for listener in rest {
+                let thread = thread::Builder::new()
+                    .name("afire listener".to_owned())
+                    .spawn_scoped(s, move || self.accept_loop(listener, on_accept));
+
+                match thread {
+                    Ok(i) => threads.push(i),
+                    Err(e) => {
+                        self.shutdown.shutdown();
+                        return Err(e);
+                    }
+                }
+            }
+
+            let mut result = self.accept_loop(first, on_accept);
+            for thread in threads {
+                result = result.and(thread.join().unwrap_or(Ok(())));
+            }
+
+            result
+        })
+    }
+
+    /// Accepts connections from a single listener until the server is shut down.
+    /// After a restart, a connection accepted while stopping may be from a client instead of the wake up connection, so it is still handled.
+    fn accept_loop(
+        &self,
+        listener: &Listener,
+        on_accept: &impl Fn(Socket, bool),
+    ) -> io::Result<()> {
+        let _guard = self.shutdown.accepting();
+        let mut backoff = None;
+
+        // A shutdown requested before the server started stops it right away
+        if self.shutdown.is_shutdown() {
+            return Ok(());
+        }
+
+        loop {
+            let event = listener.accept();
+            if self.shutdown.is_shutdown() {
+                if let (Ok(socket), true) = (event, self.shutdown.is_restarting()) {
+                    on_accept(socket, listener.proxy_protocol);
+                }
+
+                return Ok(());
+            }
+
+            match self.accepted(event, &mut backoff) {
+                Ok(Some(socket)) => on_accept(socket, listener.proxy_protocol),
+                Ok(None) => {}
+                Err(e) => {
+                    self.shutdown.shutdown();
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    /// Accepts connections from the listeners and handles them on a threadpool of `threads` threads, until the server is shut down.
+    /// If the queue of the pool is full, the connection is handled according to the [`Server::overflow_policy`].
+    fn serve_threaded(self, mut listeners: Vec<Listener>, threads: usize) -> io::Result<()> {
+        let pool = ThreadPool::new(
+            threads,
+            self.max_threads.unwrap_or(threads).max(threads),
+            self.queue_size,
+            self.thread_idle_timeout,
+            self.pool_metrics.clone(),
+        );
         let this = Arc::new(self);
 
-        for event in listener.incoming() {
+        let result = this.accept_all(&listeners, |socket, proxy| {
+            if this.overflow_policy != OverflowPolicy::Block && pool.is_full() {
+                this.overloaded(socket);
+                return;
+            }
+
             let this = this.clone();
-            pool.execute(move || handle(event.unwrap(), &this));
+            pool.execute(move || handle(socket, &this, proxy));
+        });
+
+        if this.shutdown.is_restarting() {
+            listeners.iter_mut().for_each(Listener::keep_socket_file);
         }
 
-        // We should never get Here
-        unreachable!()
+        this.shutdown.wait(this.shutdown_grace);
+        drop(pool);
+        this.shutdown.reset();
+        trace!("{}Server stopped", emoji("🛑"));
+        result
+    }
+
+    /// Handles the result of accepting a connection.
+    /// Errors are passed to the [`Server::accept_error_handler`], and depending on their [`AcceptErrorKind`]:
+    /// - Connection errors are skipped, returning `Ok(None)`.
+    /// - Running out of resources backs off (exponentially while it keeps happening) before returning `Ok(None)`.
+    /// - Fatal errors are returned, which stops the server.
+    fn accepted(
+        &self,
+        event: io::Result<Socket>,
+        backoff: &mut Option<Duration>,
+    ) -> io::Result<Option<Socket>> {
+        let mut error = match event {
+            Ok(socket) => {
+                *backoff = None;
+                return Ok(Some(socket));
+            }
+            Err(e) => AcceptError::new(e),
+        };
+
+        if error.kind == AcceptErrorKind::Resources {
+            let delay = backoff.map_or(MIN_BACKOFF, |x| (x * 2).min(MAX_BACKOFF));
+            *backoff = Some(delay);
+            error.backoff = Some(delay);
+        }
+
+        match error.kind {
+            AcceptErrorKind::Connection => {
+                trace!(Level::Debug, "{}", error);
+            }
+            _ => {
+                trace!(Level::Error, "{}", error);
+            }
+        }
+
+        if let Some(handler) = &self.accept_error_handler {
+            if let Err(e) = panic::catch_unwind(panic::AssertUnwindSafe(|| handler(&error))) {
+                trace!(Level::Error, "Accept error handler panicked: {:?}", e);
+            }
+        }
+
+        match error.kind {
+            AcceptErrorKind::Connection => Ok(None),
+            AcceptErrorKind::Resources => {
+                thread::sleep(error.backoff.unwrap_or_default());
+                Ok(None)
+            }
+            AcceptErrorKind::Fatal => Err(error.error),
+        }
     }
 
     /// Add a new default header to the server.
@@ -178,6 +686,254 @@ impl<State: Send + Sync> Server<State> {
         }
     }
 
+    /// Listen on another address, in addition to the one the server was created with.
+    /// All listeners share the same routes, middleware and threadpool.
+    /// Like [`Server::new`], `ip` can be an IP address, 'localhost' or a hostname, and will <u>panic</u> if it is invalid.
+    ///
+    /// On most systems a listener on the unspecified IPv6 address (`::`) also accepts IPv4 connections.
+    /// If the same port is also bound on `0.0.0.0`, that listener is skipped as it is already covered.
+    /// ## Example
+    /// ```rust,no_run
+    /// # use afire::Server;
+    /// let server = Server::<()>::new("0.0.0.0", 80)
+    ///     // Accept IPv6 connections too
+    ///     .listen("::", 80)
+    ///     // And serve an admin port only accessible locally
+    ///     .listen("localhost", 8081);
+    /// ```
+    pub fn listen(mut self, ip: impl ToHostAddress, port: u16) -> Self {
+        let addr = SocketAddr::new(ip.to_address().unwrap(), port);
+        trace!("{}Adding listener on {}", emoji("👂"), addr);
+
+        self.addresses.push(ListenAddress {
+            addr,
+            proxy_protocol: false,
+        });
+        self
+    }
+
+    /// Like [`Server::listen`], but connections to the address must start with a PROXY protocol header (See [`Server::proxy_protocol`]).
+    /// Lets the server accept connections both from a load balancer and directly from clients, on different addresses.
+    /// ## Example
+    /// ```rust,no_run
+    /// # use afire::Server;
+    /// // Serve clients directly on port 80, and the load balancer on port 8080
+    /// let server = Server::<()>::new("0.0.0.0", 80)
+    ///     .listen_proxy("10.0.0.2", 8080);
+    /// ```
+    pub fn listen_proxy(mut self, ip: impl ToHostAddress, port: u16) -> Self {
+        let addr = SocketAddr::new(ip.to_address().unwrap(), port);
+        trace!("{}Adding PROXY protocol listener on {}", emoji("👂"), addr);
+
+        self.addresses.push(ListenAddress {
+            addr,
+            proxy_protocol: true,
+        });
+        self
+    }
+
+    /// Accept connections from a TCP listener that was already bound, instead of binding the servers IP and port.
+    /// Useful for privilege separation, where the listener is bound before dropping privileges.
+    /// Addresses added with [`Server::listen`] are still listened on.
+    /// ## Example
+    /// ```rust,no_run
+    /// # use afire::Server;
+    /// # use std::net::TcpListener;
+    /// let listener = TcpListener::bind("0.0.0.0:80").unwrap();
+    /// /* Drop privileges */
+    ///
+    /// let server = Server::<()>::new("0.0.0.0", 80).tcp_listener(listener);
+    /// ```
+    pub fn tcp_listener(mut self, listener: TcpListener) -> Self {
+        trace!(
+            "{}Adding inherited listener {:?}",
+            emoji("👂"),
+            listener.local_addr()
+        );
+
+        self.inherited.push(Inherited::Tcp(listener));
+        self
+    }
+
+    /// Accept connections from a Unix domain socket listener that was already bound, instead of binding the servers IP and port.
+    /// Unlike with [`Server::unix_socket`], the socket file is not removed when the server stops.
+    #[cfg(unix)]
+    pub fn unix_listener(mut self, listener: UnixListener) -> Self {
+        trace!(
+            "{}Adding inherited listener {:?}",
+            emoji("👂"),
+            listener.local_addr()
+        );
+
+        self.inherited.push(Inherited::Unix(listener));
+        self
+    }
+
+    /// Accept connections from a listening socket passed to the process as a raw file descriptor, instead of binding the servers IP and port.
+    /// Both TCP and Unix domain sockets are supported.
+    ///
+    /// ## Safety
+    /// `fd` must be an open listening socket, and the server takes ownership of it, so it must not be used or closed by anything else.
+    #[cfg(unix)]
+    pub unsafe fn listener_fd(mut self, fd: RawFd) -> Self {
+        trace!("{}Adding inherited listener fd {}", emoji("👂"), fd);
+
+        self.inherited.push(Inherited::from_raw_fd(fd));
+        self
+    }
+
+    /// Pick up listening sockets passed by systemd socket activation (`LISTEN_FDS` and `LISTEN_PID`), using them instead of binding the servers IP and port.
+    /// If the process was not socket activated, this does nothing and the server binds its address as usual.
+    ///
+    /// The sockets are only picked up once per process, so calling this on another server does nothing.
+    /// The environment variables are left in place, as changing them isn't sound once other threads may be running.
+    /// Child processes won't pick up the sockets, as `LISTEN_PID` doesn't match their pid.
+    /// ## Example
+    /// ```rust,no_run
+    /// # use afire::Server;
+    /// // Binds 0.0.0.0:8080 unless systemd passed a socket
+    /// let server = Server::<()>::new("0.0.0.0", 8080).systemd();
+    /// ```
+    #[cfg(unix)]
+    pub fn systemd(mut self) -> Self {
+        let listeners = listener::take_listen_fds("LISTEN_PID", "LISTEN_FDS");
+        if !listeners.is_empty() {
+            trace!("{}Using {} systemd socket(s)", emoji("👂"), listeners.len());
+        }
+
+        self.inherited.extend(listeners);
+        self
+    }
+
+    /// Enable hot restarts, which let a new build of the server take over without refusing any connections.
+    /// The server is restarted with [`ShutdownHandle::restart`], or when the process receives `SIGUSR2`.
+    ///
+    /// If this process was started by a restart, the listening sockets of the old process are used instead of binding the servers address.
+    /// They are only used if `AFIRE_LISTEN_PID` is the pid of this process, and only once per process.
+    /// Only one server per process can be restarted with `SIGUSR2`.
+    /// Only supported on Linux.
+    /// ## Example
+    /// ```rust,no_run
+    /// # use afire::Server;
+    /// // Run `kill -USR2 <pid>` after deploying a new build to switch to it
+    /// let server = Server::<()>::new("0.0.0.0", 8080).hot_restart();
+    /// server.start_threaded(4).unwrap();
+    /// ```
+    #[cfg(target_os = "linux")]
+    pub fn hot_restart(mut self) -> Self {
+        let listeners = restart::listen_fds();
+        if !listeners.is_empty() {
+            trace!(
+                "{}Using {} socket(s) from restart",
+                emoji("🔄"),
+                listeners.len()
+            );
+        }
+
+        self.inherited.extend(listeners);
+
+        if let Err(e) = restart::on_signal(self.shutdown.clone()) {
+            trace!(Level::Error, "Error handling SIGUSR2: {}", e);
+        }
+
+        self
+    }
+
+    /// Listen on a Unix domain socket at `path` instead of the servers IP and port.
+    /// Addresses added with [`Server::listen`] are still listened on.
+    /// If a stale socket file is left over from a previous run, it will be removed before binding.
+    /// The socket file is removed again once the server stops.
+    ///
+    /// Requests received over a Unix domain socket will have a [`crate::PeerAddr::Unix`] address.
+    /// ## Example
+    /// ```rust,no_run
+    /// # use afire::Server;
+    /// let server = Server::<()>::new("localhost", 8080)
+    ///     // Listen on a Unix domain socket, only accessible by the owner and group
+    ///     .unix_socket("/run/afire/afire.sock")
+    ///     .unix_socket_permissions(0o660);
+    /// ```
+    #[cfg(unix)]
+    pub fn unix_socket(self, path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref().to_path_buf();
+        trace!("{}Setting Unix socket to {}", emoji("🔌"), path.display());
+
+        Server {
+            unix_socket: Some(path),
+            ..self
+        }
+    }
+
+    /// Set the permissions of the Unix domain socket file (see [`Server::unix_socket`]), for example `0o660`.
+    /// By default, the permissions are determined by the process's umask.
+    #[cfg(unix)]
+    pub fn unix_socket_permissions(self, mode: u32) -> Self {
+        trace!(
+            "{}Setting Unix socket permissions to {:o}",
+            emoji("🔒"),
+            mode
+        );
+
+        Server {
+            unix_socket_permissions: Some(mode),
+            ..self
+        }
+    }
+
+    /// Serve HTTPS instead of HTTP, using a PEM encoded certificate chain and private key.
+    /// The files are loaded when the server starts, and can be reloaded without restarting the server with [`TlsConfig::reload`].
+    /// Only available with the `tls` feature.
+    ///
+    /// Requests received over TLS will have their [`Request::tls`] field set.
+    /// ## Example
+    /// ```rust,no_run
+    /// # use afire::Server;
+    /// let server = Server::<()>::new("localhost", 8443)
+    ///     .tls("cert.pem", "key.pem");
+    ///
+    /// // Keep a handle to reload the certificate once it is renewed
+    /// let tls = server.tls.clone().unwrap();
+    /// ```
+    #[cfg(feature = "tls")]
+    pub fn tls(self, cert_path: impl AsRef<Path>, key_path: impl AsRef<Path>) -> Self {
+        trace!("{}Enabling TLS", emoji("🔐"));
+
+        Server {
+            tls: Some(TlsConfig::new(cert_path, key_path)),
+            ..self
+        }
+    }
+
+    /// Expect every connection to start with a [PROXY protocol](https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt) header (v1 or v2), like the ones sent by HAProxy or AWS NLBs.
+    /// The client address from the header is used as the [`Request::address`], so extensions like the logger and rate limiter see the real client.
+    /// The addresses of the proxy are kept in [`Request::proxy`].
+    ///
+    /// Connections without a valid header are closed, so only enable this if all connections come through the proxy.
+    /// Otherwise clients could spoof their address.
+    ///
+    /// This applies to the servers own address, or the Unix domain socket or inherited listeners used in its place.
+    /// Other addresses have their own setting, see [`Server::listen_proxy`].
+    /// Can't be used on a TCP listener together with `Server::tls` (with the `tls` feature), terminate TLS on the proxy instead.
+    /// ## Example
+    /// ```rust
+    /// # use afire::Server;
+    /// // Only reachable through the load balancer
+    /// let server = Server::<()>::new("10.0.0.2", 8080)
+    ///     .proxy_protocol(true);
+    /// ```
+    pub fn proxy_protocol(self, proxy_protocol: bool) -> Self {
+        trace!(
+            "{}Setting PROXY protocol to {}",
+            emoji("🔌"),
+            proxy_protocol
+        );
+
+        Server {
+            proxy_protocol,
+            ..self
+        }
+    }
+
     /// Set the timeout for the socket.
     /// This will ensure that the server will not hang on a request for too long.
     /// By default there is no timeout.
@@ -204,6 +960,364 @@ impl<State: Send + Sync> Server<State> {
         }
     }
 
+    /// Set how long a keep-alive connection can be idle between requests before it is closed.
+    /// Responses on keep-alive connections let the client know with a `Keep-Alive: timeout=<seconds>` header.
+    /// By default there is no idle timeout.
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use std::time::Duration;
+    /// # use afire::Server;
+    /// let mut server = Server::<()>::new("localhost", 8080)
+    ///     // Close connections that have been idle for 5 seconds
+    ///     .idle_timeout(Duration::from_secs(5));
+    /// ```
+    pub fn idle_timeout(self, idle_timeout: Duration) -> Self {
+        trace!("{}Setting Idle timeout to {:?}", emoji("⏳"), idle_timeout);
+
+        Server {
+            idle_timeout: Some(idle_timeout),
+            ..self
+        }
+    }
+
+    /// Set how long a client has to send the request line and headers of a request.
+    /// For the first request on a connection the time starts when the connection is accepted, for later requests when the first byte arrives.
+    /// Clients that are too slow get a `408 Request Timeout` response.
+    /// By default there is no header timeout.
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use std::time::Duration;
+    /// # use afire::Server;
+    /// let mut server = Server::<()>::new("localhost", 8080)
+    ///     .header_timeout(Duration::from_secs(10));
+    /// ```
+    pub fn header_timeout(self, header_timeout: Duration) -> Self {
+        trace!(
+            "{}Setting Header timeout to {:?}",
+            emoji("⏳"),
+            header_timeout
+        );
+
+        Server {
+            header_timeout: Some(header_timeout),
+            ..self
+        }
+    }
+
+    /// Set how long a client has to send the body of a request, starting after the headers are read (or the `100 Continue` is sent).
+    /// This also limits how long a route with a streaming body can spend reading it.
+    /// Clients that are too slow get a `408 Request Timeout` response.
+    /// By default there is no body timeout.
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use std::time::Duration;
+    /// # use afire::Server;
+    /// let mut server = Server::<()>::new("localhost", 8080)
+    ///     .body_timeout(Duration::from_secs(30));
+    /// ```
+    pub fn body_timeout(self, body_timeout: Duration) -> Self {
+        trace!("{}Setting Body timeout to {:?}", emoji("⏳"), body_timeout);
+
+        Server {
+            body_timeout: Some(body_timeout),
+            ..self
+        }
+    }
+
+    /// Set the max number of requests handled on a single connection.
+    /// The response to the last request has a `Connection: close` header, earlier ones let the client know how many are left with a `Keep-Alive: max=<requests>` header.
+    /// By default there is no limit.
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use afire::Server;
+    /// let mut server = Server::<()>::new("localhost", 8080)
+    ///     .max_requests(100);
+    /// ```
+    pub fn max_requests(self, max_requests: usize) -> Self {
+        trace!("{}Setting Max requests to {}", emoji("🔁"), max_requests);
+
+        Server {
+            max_requests: Some(max_requests),
+            ..self
+        }
+    }
+
+    /// Set the max time a connection is kept open for.
+    /// Once a connection is older than this, it is closed after the current response is sent.
+    /// Useful for spreading clients over new instances behind a load balancer.
+    /// By default there is no limit.
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use std::time::Duration;
+    /// # use afire::Server;
+    /// let mut server = Server::<()>::new("localhost", 8080)
+    ///     .max_connection_age(Duration::from_secs(60 * 10));
+    /// ```
+    pub fn max_connection_age(self, max_connection_age: Duration) -> Self {
+        trace!(
+            "{}Setting Max connection age to {:?}",
+            emoji("⏳"),
+            max_connection_age
+        );
+
+        Server {
+            max_connection_age: Some(max_connection_age),
+            ..self
+        }
+    }
+
+    /// Set the max number of accepted connections waiting for a worker when using a threadpool.
+    /// Once the queue is full, new connections are handled according to the [`Server::overflow_policy`].
+    /// By default 1024 connections can be queued.
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use afire::Server;
+    /// let mut server = Server::<()>::new("localhost", 8080)
+    ///     .queue_size(64);
+    /// ```
+    pub fn queue_size(self, queue_size: usize) -> Self {
+        trace!("{}Setting Queue size to {}", emoji("📦"), queue_size);
+
+        Server { queue_size, ..self }
+    }
+
+    /// Set what to do with new connections when the threadpool's queue is full.
+    /// See [`OverflowPolicy`] for the options, by default the server stops accepting connections until there is room in the queue.
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use std::time::Duration;
+    /// # use afire::{Server, OverflowPolicy};
+    /// let mut server = Server::<()>::new("localhost", 8080)
+    ///     .queue_size(64)
+    ///     // Shed load with a 503 response
+    ///     .overflow_policy(OverflowPolicy::Reject {
+    ///         retry_after: Duration::from_secs(5),
+    ///     });
+    /// ```
+    pub fn overflow_policy(self, overflow_policy: OverflowPolicy) -> Self {
+        trace!(
+            "{}Setting Overflow policy to {:?}",
+            emoji("📦"),
+            overflow_policy
+        );
+
+        Server {
+            overflow_policy,
+            ..self
+        }
+    }
+
+    /// Set the max number of worker threads the threadpool can grow to while all workers are busy.
+    /// Useful when some routes tie up a worker for a long time, like server-sent events or large uploads.
+    /// Workers above the number the server was started with are stopped after being idle for [`Server::thread_idle_timeout`].
+    /// By default the pool has a fixed size.
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use afire::Server;
+    /// let server = Server::<()>::new("localhost", 8080)
+    ///     .max_threads(64);
+    ///
+    /// // Start with 8 workers, growing up to 64 under load
+    /// server.start_threaded(8).unwrap();
+    /// ```
+    pub fn max_threads(self, max_threads: usize) -> Self {
+        trace!("{}Setting Max threads to {}", emoji("🧵"), max_threads);
+
+        Server {
+            max_threads: Some(max_threads),
+            ..self
+        }
+    }
+
+    /// Set how long a worker thread above the number the server was started with can be idle before it is stopped.
+    /// Only used if [`Server::max_threads`] is set.
+    /// Defaults to 60 seconds.
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use std::time::Duration;
+    /// # use afire::Server;
+    /// let server = Server::<()>::new("localhost", 8080)
+    ///     .max_threads(64)
+    ///     .thread_idle_timeout(Duration::from_secs(10));
+    /// ```
+    pub fn thread_idle_timeout(self, thread_idle_timeout: Duration) -> Self {
+        trace!(
+            "{}Setting Thread idle timeout to {:?}",
+            emoji("🧵"),
+            thread_idle_timeout
+        );
+
+        Server {
+            thread_idle_timeout,
+            ..self
+        }
+    }
+
+    /// Gets the metrics of the threadpool, like how many workers are busy and how many connections are waiting for one.
+    /// Like [`Server::shutdown_handle`], this has to be called before the server is started, servers started in the background also have [`BackgroundServer::pool_metrics`].
+    /// See [`PoolMetrics`] for an example.
+    pub fn pool_metrics(&self) -> PoolMetrics {
+        self.pool_metrics.clone()
+    }
+
+    /// Set the limits on the size of incoming requests.
+    /// See [`Limits`] for the defaults and the responses sent when a limit is exceeded.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Server, Limits};
+    /// // Create a server for localhost on port 8080
+    /// let mut server = Server::<()>::new("localhost", 8080)
+    ///     // Only allow small requests
+    ///     .limits(Limits {
+    ///         max_header_count: 20,
+    ///         max_body_size: 64 * 1024,
+    ///         ..Limits::default()
+    ///     });
+    /// ```
+    pub fn limits(self, limits: Limits) -> Self {
+        trace!("{}Setting request limits to {:?}", emoji("📏"), limits);
+
+        Server { limits, ..self }
+    }
+
+    /// Set the max length of the request line (`GET /path HTTP/1.1`), see [`Limits::max_request_line`].
+    /// Requests with a longer request line are rejected with a 414 URI Too Long.
+    pub fn max_request_line(self, max_request_line: usize) -> Self {
+        let limits = Limits {
+            max_request_line,
+            ..self.limits
+        };
+        self.limits(limits)
+    }
+
+    /// Set the max number of headers in a request, see [`Limits::max_header_count`].
+    /// Requests with more headers are rejected with a 431 Request Header Fields Too Large.
+    pub fn max_header_count(self, max_header_count: usize) -> Self {
+        let limits = Limits {
+            max_header_count,
+            ..self.limits
+        };
+        self.limits(limits)
+    }
+
+    /// Set the max length of a single header line, see [`Limits::max_header_size`].
+    /// Requests with a longer header are rejected with a 431 Request Header Fields Too Large.
+    pub fn max_header_size(self, max_header_size: usize) -> Self {
+        let limits = Limits {
+            max_header_size,
+            ..self.limits
+        };
+        self.limits(limits)
+    }
+
+    /// Set the max size of a request body, see [`Limits::max_body_size`].
+    /// Requests with a larger body are rejected with a 413 Payload Too Large.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use afire::Server;
+    /// // Create a server for localhost on port 8080
+    /// let mut server = Server::<()>::new("localhost", 8080)
+    ///     // Allow bodies of up to 1 MiB
+    ///     .max_body_size(1024 * 1024);
+    /// ```
+    pub fn max_body_size(self, max_body_size: usize) -> Self {
+        let limits = Limits {
+            max_body_size,
+            ..self.limits
+        };
+        self.limits(limits)
+    }
+
+    /// Finds the route a request with this method and path will be handled by.
+    /// Used to apply route options, like limits, before the whole request has been read.
+    pub(crate) fn find_route(&self, method: Method, path: &str) -> Option<&Route<State>> {
+        let (index, _) = self.router().find(method, &router::segments(path))?;
+        self.routes.get(index)
+    }
+
+    /// Finds the route a request with this method and path will be handled by, along with its path params.
+    pub(crate) fn match_route(&self, method: Method, path: &str) -> Option<RouteMatch<'_, State>> {
+        let segments = router::segments(path);
+        let (index, mixed) = self.router().find(method, &segments)?;
+        let route = self.routes.get(index)?;
+        Some((route, route.path.params(&segments, mixed)))
+    }
+
+    /// Compiles the routes into the tree used to find the route for each request.
+    fn build_router(&self) {
+        trace!(
+            Level::Debug,
+            "{}Building router for {} routes",
+            emoji("🌳"),
+            self.routes.len()
+        );
+        *self.router.write().unwrap_or_else(|e| e.into_inner()) = Router::new(&self.routes);
+    }
+
+    fn router(&self) -> RwLockReadGuard<'_, Router> {
+        self.router.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Set how long in-flight requests have to finish once a shutdown is requested.
+    /// After this period, any connections that are still open will be closed.
+    /// By default this is 30 seconds.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use afire::Server;
+    /// // Create a server for localhost on port 8080
+    /// let mut server = Server::<()>::new("localhost", 8080)
+    ///     // Give requests 5 seconds to finish on shutdown
+    ///     .shutdown_grace(Duration::from_secs(5));
+    /// ```
+    pub fn shutdown_grace(self, shutdown_grace: Duration) -> Self {
+        trace!(
+            "{}Setting shutdown grace period to {:?}",
+            emoji("⏳"),
+            shutdown_grace
+        );
+
+        Server {
+            shutdown_grace,
+            ..self
+        }
+    }
+
+    /// Gets a handle that can be used to stop the server once it is started.
+    /// The handle can be cloned and moved into other threads or route handlers.
+    /// See [`ShutdownHandle`] for more information.
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use std::{thread, time::Duration};
+    /// # use afire::Server;
+    /// let server = Server::<()>::new("localhost", 8080);
+    /// let shutdown = server.shutdown_handle();
+    ///
+    /// // Stop the server after 10 seconds
+    /// thread::spawn(move || {
+    ///     thread::sleep(Duration::from_secs(10));
+    ///     shutdown.shutdown();
+    /// });
+    ///
+    /// server.start_threaded(4).unwrap();
+    /// ```
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
     /// Set the keep alive state of the server.
     /// This will determine if the server will keep the connection alive after a request.
     /// By default this is true.
@@ -281,9 +1395,36 @@ impl<State: Send + Sync> Server<State> {
         self.error_handler = Box::new(res);
     }
 
+    /// Set a handler that is called with every error that occurs while accepting connections.
+    /// Use it to observe events like running out of file descriptors, see [`AcceptErrorKind`] for how each kind is handled.
+    /// The errors are also logged through afire's tracing.
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Server, error::AcceptErrorKind};
+    /// # let mut server = Server::<()>::new("localhost", 8080);
+    /// server.accept_error_handler(|err| {
+    ///     if err.kind == AcceptErrorKind::Resources {
+    ///         eprintln!("Out of file descriptors: {}", err.error);
+    ///     }
+    /// });
+    /// ```
+    pub fn accept_error_handler(&mut self, handler: impl Fn(&AcceptError) + Send + Sync + 'static) {
+        trace!("{}Setting Accept Error Handler", emoji("✌"));
+
+        self.accept_error_handler = Some(Box::new(handler));
+    }
+
     /// Create a new route.
     /// The path can contain parameters, which are defined with `{...}`, as well as wildcards, which are defined with `*`.
     /// (`**` lets you math anything after the wildcard, including `/`)
+    /// Parameters can be constrained to a type (`{id:u64}`, `{id:uuid}`) or pattern (`{slug:[a-z-]+}`), requests that don't satisfy the constraint fall through to other routes.
+    /// See [`crate::internal::path::Constraint`] for the supported types and [`crate::internal::pattern`] for the pattern syntax.
+    /// Parameters at the end of the path can be optional (`/posts/{page?}`), and `{*rest}` captures the rest of the path, including `/`.
+    /// A segment can also mix text and parameters, like `/files/{name}.{ext}`.
+    /// If multiple routes match a request, the one defined last is used.
+    ///
+    /// Returns a reference to the new [`Route`], which can be used to override the servers [`Limits`] for this route.
+    /// Panics if the path is invalid, like unbalanced braces, a parameter with an invalid constraint or unknown type, or an optional parameter before a required segment.
     /// ## Example
     /// ```rust
     /// # use afire::{Server, Response, Header, Method, Content};
@@ -302,13 +1443,13 @@ impl<State: Send + Sync> Server<State> {
         method: Method,
         path: impl AsRef<str>,
         handler: impl Fn(&Request) -> Response + Send + Sync + 'static,
-    ) -> &mut Self {
+    ) -> &mut Route<State> {
         let path = path.as_ref().to_owned();
         trace!("{}Adding Route {} {}", emoji("🚗"), method, path);
 
         self.routes
             .push(Route::new(method, path, Box::new(handler)));
-        self
+        self.routes.last_mut().unwrap()
     }
 
     /// Create a new stateful route.
@@ -316,6 +1457,8 @@ impl<State: Send + Sync> Server<State> {
     /// (See [`Server::state`])
     ///
     /// Note: If you add a stateful route, you must also set the state or starting the sever will return an error.
+    ///
+    /// Returns a reference to the new [`Route`], which can be used to override the servers [`Limits`] for this route.
     /// ## Example
     /// ```rust
     /// # use afire::{Server, Response, Header, Method};
@@ -333,13 +1476,29 @@ impl<State: Send + Sync> Server<State> {
         method: Method,
         path: impl AsRef<str>,
         handler: impl Fn(Arc<State>, &Request) -> Response + Send + Sync + 'static,
-    ) -> &mut Self {
+    ) -> &mut Route<State> {
         let path = path.as_ref().to_owned();
         trace!("{}Adding Route {} {}", emoji("🚗"), method, path);
 
         self.routes
             .push(Route::new_stateful(method, path, Box::new(handler)));
-        self
+        self.routes.last_mut().unwrap()
+    }
+
+    /// Create a group of routes sharing a path prefix and middleware.
+    /// The routes added to the [`Group`] in `builder` have `prefix` added to their path, and middleware attached to the group only runs for requests to them.
+    /// Groups can be nested, see [`Group`] for more details.
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Server, Response, Method};
+    /// # let mut server = Server::<()>::new("localhost", 8080);
+    /// server.group("/api", |api| {
+    ///     // Handles `/api/status`
+    ///     api.route(Method::GET, "/status", |_req| Response::new().text("OK"));
+    /// });
+    /// ```
+    pub fn group(&mut self, prefix: impl AsRef<str>, builder: impl FnOnce(&mut Group<State>)) {
+        Group::build(self, prefix.as_ref().to_owned(), builder);
     }
 
     /// Gets a reference to the current server state set outside of stateful routes.
@@ -357,6 +1516,35 @@ impl<State: Send + Sync> Server<State> {
         self.state.as_ref().unwrap().clone()
     }
 
+    /// Turns away a connection because the threadpool's queue is full.
+    /// With [`OverflowPolicy::Reject`] a `503 Service Unavailable` response is sent first, if it can be written without blocking.
+    fn overloaded(&self, socket: Socket) {
+        trace!(Level::Debug, "Queue full, turning away connection");
+        self.pool_metrics.reject();
+
+        // This runs on the accept loop, so a slow client can't be waited on.
+        // If the response doesn't fit in the socket's send buffer right away (or needs a TLS handshake first) the connection is just closed.
+        if let (OverflowPolicy::Reject { retry_after }, Ok(())) =
+            (self.overflow_policy, socket.set_nonblocking(true))
+        {
+            let socket = Arc::new(Mutex::new(socket));
+            let mut res = Response::new()
+                .status(Status::ServiceUnavailable)
+                .header(HeaderType::RetryAfter, retry_after.as_secs().to_string())
+                .text("Service Unavailable")
+                .close();
+
+            if let Err(e) = res.write(socket.clone(), &self.default_headers) {
+                trace!(Level::Debug, "Error writing to socket: {:?}", e);
+            }
+
+            let _ = socket.lock().unwrap().shutdown(Shutdown::Both);
+            return;
+        }
+
+        let _ = socket.shutdown(Shutdown::Both);
+    }
+
     fn check(&self) -> Result<()> {
         if self.state.is_none() && self.routes.iter().any(|x| x.is_stateful()) {
             return Err(StartupError::NoState.into());
@@ -366,6 +1554,109 @@ impl<State: Send + Sync> Server<State> {
             return Err(StartupError::InvalidSocketTimeout.into());
         }
 
+        let timeouts = [
+            self.idle_timeout,
+            self.header_timeout,
+            self.body_timeout,
+            self.max_connection_age,
+        ];
+        if timeouts.contains(&Some(Duration::ZERO)) || self.max_requests == Some(0) {
+            return Err(StartupError::InvalidConnectionLimit.into());
+        }
+
+        if self.queue_size == 0 {
+            return Err(StartupError::InvalidQueueSize.into());
+        }
+
+        // TLS is only used on TCP listeners, so the servers own address only conflicts if it isn't replaced by Unix domain sockets
+        #[cfg(feature = "tls")]
+        if self.tls.is_some() {
+            #[cfg(unix)]
+            let own_tcp = match self.inherited.is_empty() {
+                true => self.unix_socket.is_none(),
+                false => (self.inherited.iter())
+                    .any(|x| matches!(x, Inherited::Tcp(i) if self.address_of(i).is_none())),
+            };
+            #[cfg(not(unix))]
+            let own_tcp = true;
+
+            if (self.proxy_protocol && own_tcp) || self.addresses.iter().any(|x| x.proxy_protocol) {
+                return Err(StartupError::ProxyProtocolTls.into());
+            }
+        }
+
         Ok(())
     }
 }
+
+#[cfg(all(test, unix))]
+mod test {
+    use crate::Server;
+
+    #[test]
+    fn test_accept_errors() {
+        use std::{
+            io,
+            sync::{Arc, Mutex},
+        };
+
+        use super::MIN_BACKOFF;
+        use crate::error::AcceptErrorKind;
+
+        let mut server = Server::<()>::new("localhost", 0);
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let handler_events = events.clone();
+        server.accept_error_handler(move |err| {
+            handler_events.lock().unwrap().push((err.kind, err.backoff))
+        });
+
+        let mut backoff = None;
+        let error = |code| Err(io::Error::from_raw_os_error(code));
+
+        // EMFILE backs off exponentially
+        assert!(server.accepted(error(24), &mut backoff).unwrap().is_none());
+        assert!(server.accepted(error(24), &mut backoff).unwrap().is_none());
+        assert_eq!(backoff, Some(MIN_BACKOFF * 2));
+
+        // ECONNABORTED is skipped
+        let aborted = Err(io::ErrorKind::ConnectionAborted.into());
+        assert!(server.accepted(aborted, &mut backoff).unwrap().is_none());
+
+        // EBADF stops the server
+        assert!(server.accepted(error(9), &mut backoff).is_err());
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                (AcceptErrorKind::Resources, Some(MIN_BACKOFF)),
+                (AcceptErrorKind::Resources, Some(MIN_BACKOFF * 2)),
+                (AcceptErrorKind::Connection, None),
+                (AcceptErrorKind::Fatal, None),
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "tls")]
+    fn test_proxy_protocol_tls() {
+        use crate::{error::StartupError, Error};
+
+        let tls = |server: Server| server.tls("cert.pem", "key.pem");
+        let conflict = |server: Server| {
+            let err = server.check().unwrap_err();
+            matches!(err, Error::Startup(StartupError::ProxyProtocolTls))
+        };
+
+        assert!(conflict(tls(
+            Server::new("localhost", 0).proxy_protocol(true)
+        )));
+        assert!(conflict(tls(
+            Server::new("localhost", 0).listen_proxy("localhost", 0)
+        )));
+
+        // TLS isn't used on Unix domain sockets, so they can still expect a header
+        let path = std::env::temp_dir().join("afire-proxy-tls.sock");
+        let server = tls(Server::new("localhost", 0).proxy_protocol(true)).unix_socket(path);
+        assert!(server.check().is_ok());
+    }
+}
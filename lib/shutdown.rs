@@ -0,0 +1,511 @@
+//! Graceful shutdown support.
+//! A [`ShutdownHandle`] can be obtained from a server with [`crate::Server::shutdown_handle`] before starting it.
+//! Servers started with [`crate::Server::start_background`] are controlled with a [`BackgroundServer`].
+
+#[cfg(unix)]
+use std::path::Path;
+use std::{
+    collections::HashMap,
+    io,
+    net::{Shutdown, SocketAddr},
+    panic,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+#[cfg(target_os = "linux")]
+use std::{
+    os::fd::OwnedFd,
+    process::{Child, Command},
+    thread,
+};
+
+#[cfg(target_os = "linux")]
+use crate::internal::restart;
+use crate::{
+    error::Result,
+    internal::{common::ForceLock, listener::ListenAddr, socket::Socket},
+    trace::emoji,
+    PoolMetrics,
+};
+
+/// Handle used to stop a running server.
+/// Can be cloned and sent across threads, including into route handlers.
+///
+/// Calling [`ShutdownHandle::shutdown`] will make the server stop accepting new connections.
+/// In-flight requests will be allowed to finish, and idle keep-alive connections will be closed.
+/// After the servers grace period (see [`crate::Server::shutdown_grace`]) any remaining connections will be forcibly closed.
+/// Once all workers have stopped, the call to [`crate::Server::start`] or [`crate::Server::start_threaded`] will return `Ok(())`.
+///
+/// ## Example
+/// ```rust,no_run
+/// # use afire::{Server, Response, Method};
+/// let mut server = Server::<()>::new("localhost", 8080);
+/// let shutdown = server.shutdown_handle();
+///
+/// // Stop the server when this route is requested
+/// server.route(Method::POST, "/shutdown", move |_req| {
+///     shutdown.shutdown();
+///     Response::new().text("Goodbye!")
+/// });
+///
+/// // This will return once the server has been shut down
+/// server.start_threaded(4).unwrap();
+/// ```
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    inner: Arc<ShutdownState>,
+}
+
+struct ShutdownState {
+    /// Whether a shutdown has been requested.
+    shutdown: AtomicBool,
+    /// Whether the listeners have been passed to a new process, see [`ShutdownHandle::restart`].
+    restarting: AtomicBool,
+    /// The addresses the listeners are bound to.
+    /// Used to wake up the accept loops.
+    addrs: Mutex<Vec<ListenAddr>>,
+    /// Handles to the listening sockets, which are passed to the new process on a restart.
+    #[cfg(target_os = "linux")]
+    handoff: Mutex<Vec<OwnedFd>>,
+    /// The number of running accept loops.
+    accepting: AtomicUsize,
+    /// The currently open connections.
+    connections: Mutex<HashMap<u64, TrackedConnection>>,
+    /// Notified every time a connection is closed.
+    closed: Condvar,
+    /// The id to give the next connection.
+    next_id: AtomicU64,
+}
+
+/// A connection that is being handled by the server.
+struct TrackedConnection {
+    /// A clone of the socket, used to close it on shutdown.
+    socket: Socket,
+    /// If the connection is waiting for another request on a keep-alive connection.
+    idle: bool,
+}
+
+/// A server running on a background thread.
+/// Returned by [`crate::Server::start_background`].
+///
+/// Dropping this will not stop the server, use [`BackgroundServer::stop`] for that.
+pub struct BackgroundServer {
+    /// The addresses the server is bound to, starting with its main address.
+    addrs: Vec<ListenAddr>,
+    /// Handle used to stop the server.
+    shutdown: ShutdownHandle,
+    /// Metrics of the servers threadpool.
+    metrics: PoolMetrics,
+    /// The thread running the accept loop.
+    thread: JoinHandle<io::Result<()>>,
+}
+
+/// Removes a connection from the tracked connections when dropped.
+pub(crate) struct ConnectionGuard {
+    id: u64,
+    inner: Arc<ShutdownState>,
+}
+
+/// Marks an accept loop as stopped when dropped.
+pub(crate) struct AcceptGuard {
+    inner: Arc<ShutdownState>,
+}
+
+impl ShutdownHandle {
+    pub(crate) fn new() -> Self {
+        Self {
+            inner: Arc::new(ShutdownState {
+                shutdown: AtomicBool::new(false),
+                restarting: AtomicBool::new(false),
+                addrs: Mutex::new(Vec::new()),
+                #[cfg(target_os = "linux")]
+                handoff: Mutex::new(Vec::new()),
+                accepting: AtomicUsize::new(0),
+                connections: Mutex::new(HashMap::new()),
+                closed: Condvar::new(),
+                next_id: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Requests that the server shuts down.
+    /// This does not block, the server will stop accepting connections and finish handling in-flight requests in the background.
+    /// If the server has not been started yet, it will stop as soon as it is started.
+    pub fn shutdown(&self) {
+        if self.inner.shutdown.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        trace!("{}Shutting down server", emoji("🛑"));
+
+        // Close idle keep-alive connections
+        for i in self
+            .inner
+            .connections
+            .force_lock()
+            .values()
+            .filter(|x| x.idle)
+        {
+            let _ = i.socket.shutdown(Shutdown::Both);
+        }
+
+        // Wake up the accept loops by connecting to the listeners
+        for addr in self.inner.addrs.force_lock().iter() {
+            addr.wake();
+        }
+    }
+
+    /// Checks if a shutdown has been requested.
+    pub fn is_shutdown(&self) -> bool {
+        self.inner.shutdown.load(Ordering::Acquire)
+    }
+
+    /// Restarts the server without refusing any connections, by re-executing the current process with the same arguments.
+    /// The listening sockets are passed to the new process, which picks them up with [`crate::Server::hot_restart`].
+    /// This process then shuts down like with [`ShutdownHandle::shutdown`], finishing its in-flight requests while the new process accepts new connections.
+    /// Connections that arrive before the new process is ready wait in the listen backlog, so none are refused.
+    ///
+    /// Returns the new process, or an error if the server is not running or the process could not be spawned.
+    /// Only supported on Linux.
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use afire::{Server, Response, Method};
+    /// let mut server = Server::<()>::new("localhost", 8080).hot_restart();
+    /// let shutdown = server.shutdown_handle();
+    ///
+    /// // Restart into the current build of the binary when this route is requested
+    /// server.route(Method::POST, "/restart", move |_req| {
+    ///     shutdown.restart().unwrap();
+    ///     Response::new().text("Restarting")
+    /// });
+    ///
+    /// server.start_threaded(4).unwrap();
+    /// ```
+    #[cfg(target_os = "linux")]
+    pub fn restart(&self) -> io::Result<Child> {
+        self.restart_with(restart::command()?)
+    }
+
+    /// Like [`ShutdownHandle::restart`], but runs `command` as the new process instead of re-executing the current one.
+    /// The listening sockets are passed as file descriptors starting at 3, with their count in the `AFIRE_LISTEN_FDS` environment variable.
+    /// Like systemds `LISTEN_PID`, `AFIRE_LISTEN_PID` is set to the pid of the new process, and the sockets are only used by the process it names.
+    #[cfg(target_os = "linux")]
+    pub fn restart_with(&self, command: Command) -> io::Result<Child> {
+        let handoff = self.inner.handoff.force_lock();
+        if handoff.is_empty() || self.is_shutdown() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "Server is not running",
+            ));
+        }
+
+        let child = restart::spawn(command, &handoff)?;
+        drop(handoff);
+        trace!(
+            "{}Restarting server, new process is {}",
+            emoji("🔄"),
+            child.id()
+        );
+
+        self.inner.restarting.store(true, Ordering::Release);
+        self.shutdown();
+
+        // The new process accepts on the same sockets, so it can take the connections meant to wake up the accept loops.
+        // Keep waking them until they have all stopped.
+        let inner = self.inner.clone();
+        thread::Builder::new()
+            .name("afire restart".to_owned())
+            .spawn(move || {
+                while inner.accepting.load(Ordering::Acquire) > 0 {
+                    for addr in inner.addrs.force_lock().iter() {
+                        addr.wake();
+                    }
+                    thread::sleep(Duration::from_millis(10));
+                }
+            })?;
+
+        Ok(child)
+    }
+
+    /// Checks if the listeners have been passed to a new process by [`ShutdownHandle::restart`].
+    pub(crate) fn is_restarting(&self) -> bool {
+        self.inner.restarting.load(Ordering::Acquire)
+    }
+
+    /// Called by the server once a listener is bound.
+    pub(crate) fn add_addr(&self, addr: ListenAddr) {
+        self.inner.addrs.force_lock().push(addr);
+    }
+
+    /// Called by the server once a listener is bound, with a handle to pass to the new process on a restart.
+    #[cfg(target_os = "linux")]
+    pub(crate) fn add_handoff(&self, fd: OwnedFd) {
+        self.inner.handoff.force_lock().push(fd);
+    }
+
+    /// Marks an accept loop as running, until the returned guard is dropped.
+    pub(crate) fn accepting(&self) -> AcceptGuard {
+        self.inner.accepting.fetch_add(1, Ordering::AcqRel);
+        AcceptGuard {
+            inner: self.inner.clone(),
+        }
+    }
+
+    /// Starts tracking a connection.
+    /// Returns None if the socket could not be cloned.
+    pub(crate) fn track(&self, socket: &Socket) -> Option<ConnectionGuard> {
+        let socket = socket.try_clone().ok()?;
+        let id = self.inner.next_id.fetch_add(1, Ordering::Relaxed);
+        self.inner.connections.force_lock().insert(
+            id,
+            TrackedConnection {
+                socket,
+                idle: false,
+            },
+        );
+
+        Some(ConnectionGuard {
+            id,
+            inner: self.inner.clone(),
+        })
+    }
+
+    /// Waits for all open connections to close.
+    /// If they are still open after `grace`, they are forcibly closed.
+    pub(crate) fn wait(&self, grace: Duration) {
+        let connections = self.inner.connections.force_lock();
+        let (connections, timeout) = self
+            .inner
+            .closed
+            .wait_timeout_while(connections, grace, |x| !x.is_empty())
+            .unwrap_or_else(|e| e.into_inner());
+
+        if timeout.timed_out() {
+            trace!(
+                "{}Grace period elapsed, closing {} connections",
+                emoji("🛑"),
+                connections.len()
+            );
+
+            for i in connections.values() {
+                let _ = i.socket.shutdown(Shutdown::Both);
+            }
+        }
+    }
+
+    /// Resets the handle so the server can be started again.
+    pub(crate) fn reset(&self) {
+        self.inner.addrs.force_lock().clear();
+        #[cfg(target_os = "linux")]
+        self.inner.handoff.force_lock().clear();
+        self.inner.restarting.store(false, Ordering::Release);
+        self.inner.shutdown.store(false, Ordering::Release);
+    }
+}
+
+impl ConnectionGuard {
+    /// Marks the connection as idle or active.
+    /// Returns false if the connection is being marked idle while the server is shutting down, in which case it should be closed.
+    pub(crate) fn set_idle(&self, idle: bool) -> bool {
+        let mut connections = self.inner.connections.force_lock();
+        if idle && self.inner.shutdown.load(Ordering::Acquire) {
+            return false;
+        }
+
+        if let Some(i) = connections.get_mut(&self.id) {
+            i.idle = idle;
+        }
+
+        true
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.inner.connections.force_lock().remove(&self.id);
+        self.inner.closed.notify_all();
+    }
+}
+
+impl Drop for AcceptGuard {
+    fn drop(&mut self) {
+        self.inner.accepting.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+impl BackgroundServer {
+    pub(crate) fn new(
+        addrs: Vec<ListenAddr>,
+        shutdown: ShutdownHandle,
+        metrics: PoolMetrics,
+        thread: JoinHandle<io::Result<()>>,
+    ) -> Self {
+        Self {
+            addrs,
+            shutdown,
+            metrics,
+            thread,
+        }
+    }
+
+    /// Gets the address the server is actually bound to.
+    /// If the server was created with port `0`, this will contain the port picked by the OS.
+    /// For servers with multiple listeners this is the address the server was created with, see [`BackgroundServer::addrs`] for the rest.
+    /// Will <u>panic</u> if the server is listening on a Unix domain socket, see [`BackgroundServer::unix_path`].
+    pub fn addr(&self) -> SocketAddr {
+        match &self.addrs[0] {
+            ListenAddr::Tcp(i) => *i,
+            #[cfg(unix)]
+            ListenAddr::Unix(_) => panic!("Server is listening on a Unix domain socket"),
+        }
+    }
+
+    /// Gets the addresses of all TCP listeners of the server, in the order they were added.
+    /// Addresses that were already covered by a dual-stack IPv6 listener are not included.
+    pub fn addrs(&self) -> Vec<SocketAddr> {
+        self.addrs
+            .iter()
+            .filter_map(|x| match x {
+                ListenAddr::Tcp(i) => Some(*i),
+                #[cfg(unix)]
+                ListenAddr::Unix(_) => None,
+            })
+            .collect()
+    }
+
+    /// Gets the path of the socket file, if the server is listening on a Unix domain socket.
+    #[cfg(unix)]
+    pub fn unix_path(&self) -> Option<&Path> {
+        self.addrs.iter().find_map(|x| match x {
+            ListenAddr::Unix(i) => Some(i.as_path()),
+            _ => None,
+        })
+    }
+
+    /// Gets a handle that can be used to stop the server from other threads.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Gets the metrics of the servers threadpool, like how many workers are busy.
+    pub fn pool_metrics(&self) -> PoolMetrics {
+        self.metrics.clone()
+    }
+
+    /// Requests a shutdown and waits for the server to stop.
+    /// See [`ShutdownHandle`] for what happens to in-flight requests.
+    pub fn stop(self) -> Result<()> {
+        self.shutdown.shutdown();
+        self.join()
+    }
+
+    /// Waits for the server to stop, without requesting a shutdown.
+    /// Returns the result of the servers accept loop.
+    pub fn join(self) -> Result<()> {
+        match self.thread.join() {
+            Ok(i) => Ok(i?),
+            Err(e) => panic::resume_unwind(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::Read,
+        net::TcpStream,
+        sync::mpsc,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        internal::test_util::{get, request},
+        Method, Response, Server,
+    };
+
+    #[test]
+    fn test_background_ephemeral_port() {
+        let mut server = Server::<()>::new("localhost", 0);
+        server.route(Method::GET, "/", |_req| Response::new().text("Hello"));
+
+        let server = server.start_background(1).unwrap();
+        assert_ne!(server.addr().port(), 0);
+
+        assert!(get(server.addr(), "/").ends_with("Hello"));
+
+        let addr = server.addr();
+        server.stop().unwrap();
+        assert!(TcpStream::connect(addr).is_err());
+    }
+
+    #[test]
+    fn test_shutdown_finishes_in_flight() {
+        let mut server = Server::<()>::new("localhost", 0);
+        server.route(Method::GET, "/slow", |_req| {
+            thread::sleep(Duration::from_millis(200));
+            Response::new().text("Slow")
+        });
+
+        let server = server.start_background(2).unwrap();
+        let mut stream = TcpStream::connect(server.addr()).unwrap();
+        let client = thread::spawn(move || request(&mut stream, "/slow"));
+
+        thread::sleep(Duration::from_millis(50));
+        server.stop().unwrap();
+
+        let response = client.join().unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("Slow"));
+    }
+
+    #[test]
+    fn test_shutdown_grace_period() {
+        let mut server =
+            Server::<()>::new("localhost", 0).shutdown_grace(Duration::from_millis(300));
+        server.route(Method::GET, "/slow", |_req| {
+            thread::sleep(Duration::from_millis(100));
+            Response::new().text("Slow")
+        });
+
+        let server = server.start_background(2).unwrap();
+        let addr = server.addr();
+
+        // Open a connection that never sends a request, so it is still open when the grace period ends
+        let mut stalled = TcpStream::connect(addr).unwrap();
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let client = thread::spawn(move || request(&mut stream, "/slow"));
+        thread::sleep(Duration::from_millis(30));
+
+        let start = Instant::now();
+        server.stop().unwrap();
+        let elapsed = start.elapsed();
+
+        // The in-flight request finishes, while the stalled connection is closed once the grace period is over
+        let response = client.join().unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("Slow"));
+        assert!(elapsed >= Duration::from_millis(250), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(2), "{:?}", elapsed);
+        assert_eq!(stalled.read(&mut [0; 1]).unwrap_or(0), 0);
+
+        assert!(TcpStream::connect(addr).is_err());
+    }
+
+    #[test]
+    fn test_shutdown_before_start() {
+        // The server stops as soon as it has started, instead of waiting for a connection
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let server = Server::<()>::new("localhost", 0);
+            server.shutdown_handle().shutdown();
+            tx.send(server.start().is_ok()).unwrap();
+        });
+        assert!(rx.recv_timeout(Duration::from_secs(5)).unwrap());
+    }
+}
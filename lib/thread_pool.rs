@@ -1,96 +1,470 @@
 //! A thread pool implementation.
 //! Used for handling multiple connections at once.
 
-use std::sync::{mpsc, Arc, Mutex};
+use std::collections::VecDeque;
+use std::panic;
+use std::sync::{
+    atomic::{AtomicU64, AtomicUsize, Ordering},
+    Arc, Condvar, LockResult, Mutex,
+};
 use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
-use crate::internal::common::ForceLock;
+use crate::{
+    internal::common::{any_string, ForceLock},
+    trace,
+};
 
-/// Messages that can be handled by the pool's workers.
-enum Message {
-    /// Stops the worker.
-    Kill,
-    /// A job to be executed by the worker.
-    Job(Box<dyn FnOnce() + 'static + Send>),
+/// A job to be executed by a worker, along with the time it was queued.
+struct Job {
+    queued: Instant,
+    run: Box<dyn FnOnce() + 'static + Send>,
+}
+
+/// What to do with new connections when the queue of the thread pool is full.
+/// Set with [`crate::Server::overflow_policy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Respond with `503 Service Unavailable` and a `Retry-After` header, then close the connection.
+    Reject {
+        /// How long the client should wait before retrying, sent in the `Retry-After` header in whole seconds.
+        retry_after: Duration,
+    },
+    /// Close the connection without sending a response.
+    Close,
+    /// Stop accepting new connections until there is room in the queue.
+    /// Clients wait in the OS's listen backlog instead.
+    /// This is the default.
+    Block,
+}
+
+/// Metrics of the thread pool handling connections, used to find out if the server is saturated.
+/// Can be obtained with [`crate::Server::pool_metrics`] before starting the server or [`crate::BackgroundServer::pool_metrics`] while it runs, and cloned and sent across threads.
+///
+/// ## Example
+/// ```rust,no_run
+/// # use std::{thread, time::Duration};
+/// # use afire::Server;
+/// let server = Server::<()>::new("localhost", 8080).queue_size(64);
+/// let metrics = server.pool_metrics();
+///
+/// thread::spawn(move || loop {
+///     thread::sleep(Duration::from_secs(10));
+///     if metrics.queued() > 48 {
+///         eprintln!("Server is saturated, average wait {:?}", metrics.average_wait());
+///     }
+/// });
+///
+/// server.start_threaded(4).unwrap();
+/// ```
+#[derive(Clone, Default)]
+pub struct PoolMetrics {
+    inner: Arc<PoolStats>,
+}
+
+#[derive(Default)]
+struct PoolStats {
+    /// Worker threads currently alive.
+    workers: AtomicUsize,
+    /// Workers currently running a job.
+    busy: AtomicUsize,
+    /// Connections waiting in the queue.
+    queued: AtomicUsize,
+    /// Connections taken out of the queue by a worker.
+    handled: AtomicU64,
+    /// Connections rejected or closed because the queue was full.
+    rejected: AtomicU64,
+    /// Total time connections waited in the queue, in microseconds.
+    wait_total: AtomicU64,
+    /// Longest time a connection waited in the queue, in microseconds.
+    wait_max: AtomicU64,
 }
 
 /// A thread pool.
+/// Starts with `min` workers, and grows up to `max` workers while jobs are waiting for a worker.
+/// Workers above the minimum are stopped once they have been idle for `idle_timeout`.
 pub(crate) struct ThreadPool {
-    /// The number of threads in the pool.
-    threads: usize,
+    /// State shared with the workers.
+    shared: Arc<Shared>,
+    /// Max number of worker threads.
+    max: usize,
     /// Handle to each worker thread.
-    workers: Vec<Worker>,
-    /// The channel used to send messages to the workers.
-    sender: mpsc::Sender<Message>,
+    workers: Mutex<Vec<JoinHandle<()>>>,
+    /// The id to give the next worker.
+    next_id: AtomicUsize,
 }
 
-/// A worker thread.
-/// Contains a handle to the thread, and an id.
-struct Worker {
-    _id: usize,
-    handle: Option<JoinHandle<()>>,
+/// State shared between the pool and its workers.
+struct Shared {
+    /// The queued jobs.
+    queue: Mutex<Queue>,
+    /// Notified when a job is queued or the pool is closed.
+    available: Condvar,
+    /// Notified when a job is taken out of the queue.
+    space: Condvar,
+    /// Max number of jobs waiting for a worker.
+    queue_size: usize,
+    /// Number of workers that are never stopped for being idle.
+    min: usize,
+    /// How long a worker above the minimum can be idle before it is stopped.
+    idle_timeout: Duration,
+    /// Metrics shared with the server.
+    metrics: PoolMetrics,
+}
+
+struct Queue {
+    jobs: VecDeque<Job>,
+    /// Set when the pool is dropped, workers stop once the queue is empty.
+    closed: bool,
 }
 
 impl ThreadPool {
-    /// Creates a new thread pool with the specified number of threads.
-    /// Panics if `size` is 0.
-    pub(crate) fn new(size: usize) -> Self {
-        assert!(size > 0);
+    /// Creates a new thread pool with between `min` and `max` worker threads, and a queue that holds at most `queue_size` jobs.
+    /// Panics if `min` or `queue_size` is 0, or `max` is less than `min`.
+    pub(crate) fn new(
+        min: usize,
+        max: usize,
+        queue_size: usize,
+        idle_timeout: Duration,
+        metrics: PoolMetrics,
+    ) -> Self {
+        assert!(min > 0 && max >= min);
+        assert!(queue_size > 0);
 
-        let (tx, rx) = mpsc::channel();
-        let mut workers = Vec::with_capacity(size);
+        let pool = Self {
+            shared: Arc::new(Shared {
+                queue: Mutex::new(Queue {
+                    jobs: VecDeque::with_capacity(queue_size.min(1024)),
+                    closed: false,
+                }),
+                available: Condvar::new(),
+                space: Condvar::new(),
+                queue_size,
+                min,
+                idle_timeout,
+                metrics,
+            }),
+            max,
+            workers: Mutex::new(Vec::with_capacity(max)),
+            next_id: AtomicUsize::new(0),
+        };
 
-        let receiver = Arc::new(Mutex::new(rx));
-        for i in 0..size {
-            workers.push(Worker::new(i, Arc::clone(&receiver)));
+        for _ in 0..min {
+            pool.spawn();
         }
 
-        Self {
-            threads: size,
-            sender: tx,
-            workers,
-        }
+        pool
+    }
+
+    /// Checks if the queue is full, meaning [`ThreadPool::execute`] would block.
+    /// Only accurate if jobs are added from a single thread, as other threads could fill the queue after the check.
+    pub(crate) fn is_full(&self) -> bool {
+        self.shared.metrics.queued() >= self.shared.queue_size
     }
 
     /// Executes a job on the thread pool.
+    /// Blocks until there is room in the queue.
+    /// If there are more jobs waiting than idle workers, a new worker is started (up to the max).
     pub(crate) fn execute(&self, f: impl FnOnce() + 'static + Send) {
-        let job = Message::Job(Box::new(f));
-        self.sender.send(job).unwrap();
+        let shared = &*self.shared;
+        let metrics = &shared.metrics;
+        let mut queue = shared.queue.force_lock();
+        while queue.jobs.len() >= shared.queue_size {
+            queue = wait(shared.space.wait(queue));
+        }
+
+        queue.jobs.push_back(Job {
+            queued: Instant::now(),
+            run: Box::new(f),
+        });
+        metrics.inner.queued.fetch_add(1, Ordering::Relaxed);
+        let grow = metrics.queued() > metrics.idle() && metrics.workers() < self.max;
+        drop(queue);
+
+        shared.available.notify_one();
+        if grow {
+            self.spawn();
+        }
     }
-}
 
-impl Worker {
-    /// Creates a new worker thread.
-    fn new(id: usize, rx: Arc<Mutex<mpsc::Receiver<Message>>>) -> Self {
+    /// Starts a new worker thread.
+    fn spawn(&self) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let shared = self.shared.clone();
+        shared.metrics.inner.workers.fetch_add(1, Ordering::Relaxed);
+
         let handle = thread::Builder::new()
             .name(format!("Worker {id}"))
-            .spawn(move || loop {
-                let job = rx.force_lock().recv().unwrap();
-                match job {
-                    Message::Job(job) => job(),
-                    Message::Kill => break,
-                }
-            })
+            .spawn(move || shared.work())
             .expect("Error creating worker thread");
 
-        Self {
-            _id: id,
-            handle: Some(handle),
+        let mut workers = self.workers.force_lock();
+        workers.retain(|x| !x.is_finished());
+        workers.push(handle);
+    }
+}
+
+impl Shared {
+    /// The worker loop.
+    /// Runs jobs until the pool is closed, or the worker is idle for too long while there are more than `min` workers.
+    /// Panicking jobs are caught, so they never stop the worker.
+    fn work(&self) {
+        let stats = &self.metrics.inner;
+        let mut queue = self.queue.force_lock();
+        loop {
+            if let Some(job) = queue.jobs.pop_front() {
+                self.metrics.dequeue(job.queued.elapsed());
+                stats.busy.fetch_add(1, Ordering::Relaxed);
+                drop(queue);
+                self.space.notify_one();
+
+                if let Err(e) = panic::catch_unwind(panic::AssertUnwindSafe(job.run)) {
+                    trace!(Level::Error, "Worker job panicked: {}", any_string(e));
+                }
+
+                stats.busy.fetch_sub(1, Ordering::Relaxed);
+                queue = self.queue.force_lock();
+                continue;
+            }
+
+            if queue.closed {
+                stats.workers.fetch_sub(1, Ordering::Relaxed);
+                break;
+            }
+
+            let (guard, timeout) = wait(self.available.wait_timeout(queue, self.idle_timeout));
+            queue = guard;
+
+            let min = self.min;
+            if timeout.timed_out()
+                && queue.jobs.is_empty()
+                && !queue.closed
+                && stats
+                    .workers
+                    .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |x| match x > min {
+                        true => Some(x - 1),
+                        false => None,
+                    })
+                    .is_ok()
+            {
+                trace!(Level::Debug, "Stopping idle worker");
+                break;
+            }
+        }
+    }
+}
+
+impl PoolMetrics {
+    /// Gets the number of worker threads currently alive.
+    pub fn workers(&self) -> usize {
+        self.inner.workers.load(Ordering::Relaxed)
+    }
+
+    /// Gets the number of workers currently handling a connection.
+    pub fn busy(&self) -> usize {
+        self.inner.busy.load(Ordering::Relaxed)
+    }
+
+    /// Gets the number of workers waiting for a connection.
+    pub fn idle(&self) -> usize {
+        self.workers().saturating_sub(self.busy())
+    }
+
+    /// Gets the number of connections currently waiting for a worker.
+    pub fn queued(&self) -> usize {
+        self.inner.queued.load(Ordering::Relaxed)
+    }
+
+    /// Gets the number of connections that have been picked up by a worker.
+    pub fn handled(&self) -> u64 {
+        self.inner.handled.load(Ordering::Relaxed)
+    }
+
+    /// Gets the number of connections that were rejected or closed because the queue was full.
+    /// Always 0 with [`OverflowPolicy::Block`].
+    pub fn rejected(&self) -> u64 {
+        self.inner.rejected.load(Ordering::Relaxed)
+    }
+
+    /// Gets the average time connections waited in the queue before a worker picked them up.
+    pub fn average_wait(&self) -> Duration {
+        let handled = self.handled();
+        if handled == 0 {
+            return Duration::ZERO;
         }
+
+        Duration::from_micros(self.inner.wait_total.load(Ordering::Relaxed) / handled)
+    }
+
+    /// Gets the longest time a connection waited in the queue before a worker picked it up.
+    pub fn max_wait(&self) -> Duration {
+        Duration::from_micros(self.inner.wait_max.load(Ordering::Relaxed))
+    }
+
+    /// Records a connection that was rejected because the queue was full.
+    pub(crate) fn reject(&self) {
+        self.inner.rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records a job being taken out of the queue after waiting for `wait`.
+    fn dequeue(&self, wait: Duration) {
+        let wait = wait.as_micros() as u64;
+        let stats = &self.inner;
+        stats.queued.fetch_sub(1, Ordering::Relaxed);
+        stats.wait_total.fetch_add(wait, Ordering::Relaxed);
+        stats.wait_max.fetch_max(wait, Ordering::Relaxed);
+        stats.handled.fetch_add(1, Ordering::Relaxed);
     }
 }
 
 impl Drop for ThreadPool {
-    /// Stops all workers with a [`Message::Kill`] message, and waits for them to finish.
+    /// Closes the queue and waits for the workers to finish the remaining jobs.
     fn drop(&mut self) {
-        for _ in 0..self.threads {
-            self.sender.send(Message::Kill).unwrap();
+        self.shared.queue.force_lock().closed = true;
+        self.shared.available.notify_all();
+
+        for worker in self.workers.force_lock().drain(..) {
+            let _ = worker.join();
         }
+    }
+}
 
-        for worker in &mut self.workers {
-            if let Some(thread) = worker.handle.take() {
-                thread.join().unwrap();
-            }
+/// Gets the guard back from a condvar wait, even if the mutex was poisoned.
+fn wait<T>(result: LockResult<T>) -> T {
+    result.unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{Read, Write},
+        net::TcpStream,
+        sync::{mpsc, Arc, Barrier},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::{PoolMetrics, ThreadPool};
+    use crate::{
+        internal::test_util::{read, request},
+        Method, OverflowPolicy, Response, Server,
+    };
+
+    fn wait_for(f: impl Fn() -> bool) {
+        let start = Instant::now();
+        while !f() {
+            assert!(start.elapsed() < Duration::from_secs(5), "timed out");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn test_bounded_queue() {
+        let metrics = PoolMetrics::default();
+        let pool = ThreadPool::new(1, 1, 2, Duration::from_secs(60), metrics.clone());
+        let barrier = Arc::new(Barrier::new(2));
+        let (tx, rx) = mpsc::channel();
+
+        // Keep the only worker busy
+        let worker_barrier = barrier.clone();
+        pool.execute(move || {
+            worker_barrier.wait();
+        });
+
+        wait_for(|| metrics.busy() == 1);
+        for i in 0..2 {
+            let tx = tx.clone();
+            pool.execute(move || tx.send(i).unwrap());
         }
+
+        assert_eq!(metrics.queued(), 2);
+        assert!(pool.is_full());
+
+        thread::sleep(Duration::from_millis(10));
+        barrier.wait();
+        assert_eq!(rx.recv().unwrap(), 0);
+        assert_eq!(rx.recv().unwrap(), 1);
+
+        assert!(!pool.is_full());
+        assert_eq!(metrics.handled(), 3);
+        assert!(metrics.max_wait() >= Duration::from_millis(10));
+        assert!(metrics.average_wait() <= metrics.max_wait());
+    }
+
+    #[test]
+    fn test_dynamic_size() {
+        let metrics = PoolMetrics::default();
+        let pool = ThreadPool::new(1, 3, 8, Duration::from_millis(50), metrics.clone());
+        let barrier = Arc::new(Barrier::new(4));
+        assert_eq!(metrics.workers(), 1);
+
+        // The pool grows while all workers are busy
+        for _ in 0..3 {
+            let barrier = barrier.clone();
+            pool.execute(move || {
+                barrier.wait();
+            });
+        }
+
+        wait_for(|| metrics.busy() == 3);
+        assert_eq!(metrics.workers(), 3);
+        assert_eq!(metrics.idle(), 0);
+
+        // And shrinks back to the minimum once they are idle
+        barrier.wait();
+        wait_for(|| metrics.workers() == 1);
+        assert_eq!(metrics.busy(), 0);
+        assert_eq!(metrics.idle(), 1);
+    }
+
+    #[test]
+    fn test_panicking_job() {
+        let metrics = PoolMetrics::default();
+        let pool = ThreadPool::new(1, 1, 8, Duration::from_secs(60), metrics.clone());
+        let (tx, rx) = mpsc::channel();
+
+        pool.execute(|| panic!("Job panicked"));
+        pool.execute(move || tx.send(()).unwrap());
+
+        rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(metrics.workers(), 1);
+        assert_eq!(metrics.handled(), 2);
+    }
+
+    #[test]
+    fn test_overflow_reject() {
+        let mut server = Server::<()>::new("localhost", 0)
+            .queue_size(1)
+            .overflow_policy(OverflowPolicy::Reject {
+                retry_after: Duration::from_secs(5),
+            });
+        server.route(Method::GET, "/", |_req| Response::new().text("Hello"));
+        let metrics = server.pool_metrics();
+
+        let server = server.start_background(1).unwrap();
+
+        // Keep the only worker busy with a keep-alive connection
+        let mut busy = TcpStream::connect(server.addr()).unwrap();
+        busy.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+        let mut buf = [0; 1024];
+        assert!(busy.read(&mut buf).unwrap() > 0);
+
+        // The next connection waits in the queue
+        let mut queued = TcpStream::connect(server.addr()).unwrap();
+        wait_for(|| metrics.queued() > 0);
+
+        // Which is now full, so further connections are rejected
+        let response = read(&mut TcpStream::connect(server.addr()).unwrap());
+        assert!(response.starts_with("HTTP/1.1 503"));
+        assert!(response.contains("Retry-After: 5\r\n"));
+        assert_eq!(metrics.rejected(), 1);
+
+        // Once the worker is free, the queued connection is handled
+        drop(busy);
+        assert!(request(&mut queued, "/").ends_with("Hello"));
+        assert_eq!(metrics.queued(), 0);
+        assert_eq!(metrics.handled(), 2);
+
+        server.stop().unwrap();
     }
 }
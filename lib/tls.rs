@@ -0,0 +1,381 @@
+//! TLS (HTTPS) support, backed by [rustls](https://docs.rs/rustls).
+//! Only available with the `tls` feature.
+//!
+//! Enable it on a server with [`crate::Server::tls`].
+//! The certificate and key are loaded from PEM files when the server starts, and can be reloaded at any time with [`TlsConfig::reload`].
+//!
+//! ## Example
+//! ```rust,no_run
+//! # use afire::{Server, Response, Method};
+//! let mut server = Server::<()>::new("localhost", 8443)
+//!     .tls("cert.pem", "key.pem");
+//!
+//! server.route(Method::GET, "/", |req| {
+//!     let sni = req.tls.as_ref().and_then(|x| x.server_name.as_deref());
+//!     Response::new().text(format!("Hello over TLS! (SNI: {sni:?})"))
+//! });
+//!
+//! // Keep a handle to the TLS config to reload the certificate later
+//! let tls = server.tls.clone().unwrap();
+//! # std::thread::spawn(move || tls.reload().unwrap());
+//!
+//! server.start_threaded(4).unwrap();
+//! ```
+
+use std::{
+    fmt::{self, Debug},
+    io::{self, Read, Write},
+    net::{Shutdown, TcpStream},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
+};
+
+use rustls::{
+    crypto::ring,
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+    ServerConfig, ServerConnection,
+};
+
+use crate::{
+    internal::{
+        common::ForceLock,
+        socket::{Connection, PeerAddr, Socket, TlsInfo},
+    },
+    trace::emoji,
+};
+
+/// TLS configuration of a server.
+/// Holds the paths to the certificate chain and private key, as well as the currently loaded certificate.
+///
+/// This can be cloned, and all clones share the same certificate.
+/// Calling [`TlsConfig::reload`] on any clone will update the certificate used for all new connections.
+#[derive(Clone)]
+pub struct TlsConfig {
+    /// Path to the PEM encoded certificate chain.
+    cert_path: PathBuf,
+    /// Path to the PEM encoded private key.
+    key_path: PathBuf,
+    /// The resolver that holds the currently loaded certificate.
+    resolver: Arc<CertResolver>,
+    /// The rustls config used for all connections.
+    config: Arc<ServerConfig>,
+}
+
+/// Hands out the currently loaded certificate to new connections.
+struct CertResolver {
+    key: RwLock<Option<Arc<CertifiedKey>>>,
+}
+
+/// A TLS connection on top of a TCP stream.
+/// The TLS session is shared between clones of the connection, so it can be read from and written to on different threads (WebSockets).
+pub(crate) struct TlsConnection {
+    session: Arc<Mutex<ServerConnection>>,
+    socket: TcpStream,
+}
+
+impl TlsConfig {
+    /// Creates a new TLS config from the paths to a PEM encoded certificate chain and private key.
+    /// The files are not read until [`TlsConfig::reload`] is called, which the server does when it starts.
+    pub fn new(cert_path: impl AsRef<Path>, key_path: impl AsRef<Path>) -> Self {
+        let resolver = Arc::new(CertResolver {
+            key: RwLock::new(None),
+        });
+
+        let config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .expect("The ring provider supports the default protocol versions")
+            .with_no_client_auth()
+            .with_cert_resolver(resolver.clone());
+
+        Self {
+            cert_path: cert_path.as_ref().to_path_buf(),
+            key_path: key_path.as_ref().to_path_buf(),
+            resolver,
+            config: Arc::new(config),
+        }
+    }
+
+    /// (Re)loads the certificate chain and private key from disk.
+    /// New connections will use the new certificate, existing connections are not affected.
+    /// If loading fails, the previously loaded certificate is kept.
+    pub fn reload(&self) -> io::Result<()> {
+        let certs = CertificateDer::pem_file_iter(&self.cert_path)
+            .and_then(|x| x.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| invalid_data(&self.cert_path, e))?;
+        if certs.is_empty() {
+            return Err(invalid_data(&self.cert_path, "No certificates found"));
+        }
+
+        let key = PrivateKeyDer::from_pem_file(&self.key_path)
+            .map_err(|e| invalid_data(&self.key_path, e))?;
+        let provider = self.config.crypto_provider();
+        let key = CertifiedKey::from_der(certs, key, provider)
+            .map_err(|e| invalid_data(&self.key_path, e))?;
+
+        trace!(
+            "{}Loaded TLS certificate from {}",
+            emoji("🔐"),
+            self.cert_path.display()
+        );
+        *self.resolver.key.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(key));
+        Ok(())
+    }
+
+    /// Checks if a certificate has been loaded.
+    pub(crate) fn is_loaded(&self) -> bool {
+        self.resolver
+            .key
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_some()
+    }
+
+    /// Starts a new TLS session on a TCP stream.
+    /// The handshake is done lazily, the first time the connection is read from.
+    pub(crate) fn accept(&self, socket: TcpStream) -> io::Result<Socket> {
+        let session = ServerConnection::new(self.config.clone()).map_err(io::Error::other)?;
+        Ok(Box::new(TlsConnection {
+            session: Arc::new(Mutex::new(session)),
+            socket,
+        }))
+    }
+}
+
+impl Debug for TlsConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsConfig")
+            .field("cert_path", &self.cert_path)
+            .field("key_path", &self.key_path)
+            .finish()
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        self.key.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+impl Debug for CertResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CertResolver").finish()
+    }
+}
+
+impl TlsConnection {
+    /// Writes any pending TLS records to the socket.
+    fn flush_tls(&self, session: &mut ServerConnection) -> io::Result<()> {
+        while session.wants_write() {
+            session.write_tls(&mut &self.socket)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Read for TlsConnection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut raw = [0; 4096];
+        loop {
+            let mut session = self.session.force_lock();
+            match session.reader().read(buf) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                i => return i,
+            }
+            drop(session);
+
+            // Wait for more data without holding the lock, so other clones can still write.
+            let len = (&self.socket).read(&mut raw)?;
+
+            let mut session = self.session.force_lock();
+            let mut data = &raw[..len];
+            loop {
+                if session.read_tls(&mut data)? == 0 && !data.is_empty() {
+                    return Err(io::Error::other("TLS buffer full"));
+                }
+
+                if let Err(e) = session.process_new_packets() {
+                    // Try to let the client know what went wrong
+                    let _ = self.flush_tls(&mut session);
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, e));
+                }
+
+                if data.is_empty() {
+                    break;
+                }
+            }
+
+            self.flush_tls(&mut session)?;
+        }
+    }
+}
+
+impl Write for TlsConnection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut session = self.session.force_lock();
+        let len = session.writer().write(buf)?;
+        self.flush_tls(&mut session)?;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut session = self.session.force_lock();
+        session.writer().flush()?;
+        self.flush_tls(&mut session)
+    }
+}
+
+impl Connection for TlsConnection {
+    fn peer_addr(&self) -> io::Result<PeerAddr> {
+        Ok(PeerAddr::Tcp(self.socket.peer_addr()?))
+    }
+
+    fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        if matches!(how, Shutdown::Write | Shutdown::Both) {
+            let mut session = self.session.force_lock();
+            session.send_close_notify();
+            let _ = self.flush_tls(&mut session);
+        }
+
+        self.socket.shutdown(how)
+    }
+
+    fn try_clone(&self) -> io::Result<Socket> {
+        Ok(Box::new(TlsConnection {
+            session: self.session.clone(),
+            socket: self.socket.try_clone()?,
+        }))
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.socket.set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.socket.set_write_timeout(timeout)
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        self.socket.set_nonblocking(nonblocking)
+    }
+
+    fn tls_info(&self) -> Option<TlsInfo> {
+        let session = self.session.force_lock();
+        Some(TlsInfo {
+            server_name: session.server_name().map(|x| x.to_owned()),
+        })
+    }
+}
+
+/// Creates an [`io::ErrorKind::InvalidData`] error about a certificate or key file.
+fn invalid_data(path: &Path, error: impl fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Error loading {}: {error}", path.display()),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        convert::TryFrom,
+        env, fs,
+        io::{self, Read, Write},
+        net::TcpStream,
+        path::PathBuf,
+        process,
+        sync::Arc,
+    };
+
+    use rustls::{
+        crypto::ring,
+        pki_types::{pem::PemObject, CertificateDer, ServerName},
+        ClientConfig, ClientConnection, RootCertStore, StreamOwned,
+    };
+
+    use super::TlsConfig;
+    use crate::{BackgroundServer, Method, Response, Server};
+
+    fn testdata(file: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("testdata")
+            .join(file)
+    }
+
+    fn start(cert: PathBuf, key: PathBuf) -> (BackgroundServer, TlsConfig) {
+        let mut server = Server::<()>::new("localhost", 0).tls(cert, key);
+        server.route(Method::GET, "/", |req| {
+            let sni = req.tls.as_ref().unwrap().server_name.as_deref();
+            Response::new().text(format!("tls: {}, sni: {sni:?}", req.is_tls()))
+        });
+
+        let tls = server.tls.clone().unwrap();
+        (server.start_background(1).unwrap(), tls)
+    }
+
+    /// Sends a request to the server, only trusting the certificate `trusted`.
+    fn request(server: &BackgroundServer, trusted: &str) -> io::Result<String> {
+        let mut roots = RootCertStore::empty();
+        roots
+            .add(CertificateDer::from_pem_file(testdata(trusted)).unwrap())
+            .unwrap();
+        let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+
+        let name = ServerName::try_from("localhost").unwrap();
+        let client = ClientConnection::new(Arc::new(config), name).unwrap();
+        let mut stream = StreamOwned::new(client, TcpStream::connect(server.addr())?);
+        stream.write_all(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n")?;
+
+        let mut response = String::new();
+        match stream.read_to_string(&mut response) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {}
+            i => {
+                i?;
+            }
+        }
+        Ok(response)
+    }
+
+    #[test]
+    fn test_tls_request() {
+        let (server, _) = start(testdata("localhost.crt"), testdata("localhost.key"));
+
+        let response = request(&server, "localhost.crt").unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("tls: true, sni: Some(\"localhost\")"));
+
+        server.stop().unwrap();
+    }
+
+    #[test]
+    fn test_tls_reload() {
+        let dir = env::temp_dir();
+        let cert = dir.join(format!("afire-{}-tls.crt", process::id()));
+        let key = dir.join(format!("afire-{}-tls.key", process::id()));
+        fs::copy(testdata("localhost.crt"), &cert).unwrap();
+        fs::copy(testdata("localhost.key"), &key).unwrap();
+
+        let (server, tls) = start(cert.clone(), key.clone());
+        assert!(request(&server, "localhost.crt").is_ok());
+        assert!(request(&server, "localhost-alt.crt").is_err());
+
+        // Swap out the certificate and reload it
+        fs::copy(testdata("localhost-alt.crt"), &cert).unwrap();
+        fs::copy(testdata("localhost-alt.key"), &key).unwrap();
+        tls.reload().unwrap();
+
+        assert!(request(&server, "localhost.crt").is_err());
+        assert!(request(&server, "localhost-alt.crt").is_ok());
+
+        server.stop().unwrap();
+        fs::remove_file(cert).unwrap();
+        fs::remove_file(key).unwrap();
+    }
+}